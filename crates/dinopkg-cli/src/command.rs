@@ -1,8 +1,11 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
+pub mod ci;
+pub mod config;
 pub mod init;
 pub mod install;
 pub mod run;
+pub mod store;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -18,6 +21,14 @@ pub enum Command {
     Run {
         /// The name of the script to run
         script_name: Option<String>,
+
+        /// Run the script in a workspace, given by its name or path. Can be repeated
+        #[arg(short, long = "workspace", value_name = "NAME")]
+        workspaces: Vec<String>,
+
+        /// Run the script in every workspace
+        #[arg(long = "workspaces", conflicts_with = "workspaces")]
+        all_workspaces: bool,
     },
 
     /// Run tests for a package
@@ -31,7 +42,143 @@ pub enum Command {
     /// Installs dependencies for `package.json`
     #[command(aliases = ["i", "add"])]
     Install {
-        /// The name of the package to install
-        name: String,
+        /// A package to add, like `lodash`, `lodash@^4` or `@scope/pkg@beta`. If omitted,
+        /// installs everything in `package.json`
+        name: Option<String>,
+
+        /// How to lay out packages in `node_modules`
+        #[arg(long, value_enum, default_value_t)]
+        install_strategy: InstallStrategy,
+
+        /// Never use the network, only cached metadata and packages from the store
+        #[arg(long, conflicts_with = "prefer_offline")]
+        offline: bool,
+
+        /// Use cached metadata without checking for updates, when it has the versions needed
+        #[arg(long)]
+        prefer_offline: bool,
+
+        /// The registry to install packages from, instead of the npm registry
+        #[arg(long)]
+        registry: Option<String>,
+
+        /// Don't run lifecycle scripts, not even the project's own
+        #[arg(long)]
+        ignore_scripts: bool,
     },
+
+    /// Installs exactly what's in `package-lock.json`, for CI and other automated environments
+    #[command(aliases = ["clean-install", "install-clean", "isntall-clean"])]
+    Ci {
+        /// How to lay out packages in `node_modules`
+        #[arg(long, value_enum, default_value_t)]
+        install_strategy: InstallStrategy,
+
+        /// Don't run lifecycle scripts, not even the project's own
+        #[arg(long)]
+        ignore_scripts: bool,
+    },
+
+    /// Manages the global package store
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+
+    /// Manages settings in `.npmrc` files
+    #[command(alias = "c")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StoreCommand {
+    /// Shows where the store is, and how much space it's using
+    Status,
+
+    /// Removes packages that aren't used by any project
+    Prune,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Prints the value of each setting
+    Get {
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
+
+    /// Changes settings, given as `key=value` or `key value`
+    Set {
+        #[arg(required = true)]
+        pairs: Vec<String>,
+
+        /// Which config file to change
+        #[arg(long, value_enum, default_value_t)]
+        location: ConfigLocation,
+    },
+
+    /// Prints every setting that isn't a default
+    #[command(alias = "ls")]
+    List {
+        /// Also print the defaults
+        #[arg(short, long)]
+        long: bool,
+    },
+
+    /// Removes settings
+    #[command(alias = "rm")]
+    Delete {
+        #[arg(required = true)]
+        keys: Vec<String>,
+
+        /// Which config file to change
+        #[arg(long, value_enum, default_value_t)]
+        location: ConfigLocation,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum ConfigLocation {
+    /// The project's `.npmrc`
+    Project,
+
+    /// `~/.npmrc`
+    #[default]
+    User,
+
+    /// `$PREFIX/etc/npmrc`
+    Global,
+}
+
+impl From<ConfigLocation> for dinopkg_config::Location {
+    fn from(location: ConfigLocation) -> Self {
+        match location {
+            ConfigLocation::Project => Self::Project,
+            ConfigLocation::User => Self::User,
+            ConfigLocation::Global => Self::Global,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum InstallStrategy {
+    /// Hoist packages as far up as possible, like npm
+    #[default]
+    Hoisted,
+
+    /// Only let packages see their own dependencies
+    #[value(alias = "linked")]
+    Isolated,
+}
+
+impl From<InstallStrategy> for dinopkg_installer::Strategy {
+    fn from(strategy: InstallStrategy) -> Self {
+        match strategy {
+            InstallStrategy::Hoisted => Self::Hoisted,
+            InstallStrategy::Isolated => Self::Isolated,
+        }
+    }
 }
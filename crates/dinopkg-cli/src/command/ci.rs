@@ -0,0 +1,91 @@
+use std::sync::Mutex;
+
+use color_eyre::{eyre::eyre, Result};
+use dinopkg_config::Config;
+use dinopkg_installer::{GitFetcher, LocalFetcher, Strategy};
+use dinopkg_lockfile::LOCKFILE_NAME;
+use dinopkg_package_json::PackageJson;
+
+use super::install::{
+    find_workspaces, open_repositories, print_dep_version, read_lockfile, registry_client,
+    run_lifecycle_scripts, script_policy, PrepareScript,
+};
+
+/// Installs exactly what's in the lockfile, failing if it doesn't match `package.json`.
+///
+/// Only tarballs and git dependencies are downloaded (local ones are read from disk);
+/// the registry is never asked for package metadata.
+pub async fn ci(strategy: Strategy, config: &Config) -> Result<()> {
+    let (package_json, package_json_path) = PackageJson::from_file(10).await?;
+    let root = package_json_path
+        .parent()
+        .ok_or_else(|| eyre!("package.json should be in a directory"))?;
+
+    let Some(lockfile) = read_lockfile(&root.join(LOCKFILE_NAME)).await? else {
+        return Err(eyre!(
+            "`dino ci` needs a {LOCKFILE_NAME}, run `dino install` to create one"
+        ));
+    };
+    let workspaces = find_workspaces(root, &package_json)?;
+    let mismatches = lockfile.mismatches(&package_json, &workspaces);
+    if !mismatches.is_empty() {
+        let list: Vec<String> = mismatches
+            .iter()
+            .map(|mismatch| format!("  - {mismatch}"))
+            .collect();
+        return Err(eyre!(
+            "package.json and {LOCKFILE_NAME} are out of sync, run `dino install` to update the lockfile\n{}",
+            list.join("\n")
+        ));
+    }
+
+    // Start from scratch, so nothing left over from earlier installs can affect the result.
+    let dirs = std::iter::once(root.to_path_buf()).chain(
+        workspaces
+            .iter()
+            .map(|workspace| root.join(&workspace.path)),
+    );
+    for dir in dirs {
+        let node_modules = dir.join("node_modules");
+        if tokio::fs::try_exists(&node_modules).await? {
+            tokio::fs::remove_dir_all(&node_modules).await?;
+        }
+    }
+
+    let (resolution, hoisted) = lockfile.to_resolution()?;
+    for package in resolution.packages.values() {
+        print_dep_version(&package.id.name, &package.id.version, package.dev);
+    }
+    let layout = match strategy {
+        Strategy::Hoisted => hoisted,
+        Strategy::Isolated => strategy.layout(&resolution),
+    };
+    let store = super::store::open(config)?;
+    // Only used for tarballs, but private ones need the registry's credentials.
+    let registry = registry_client(config)?;
+    let repositories = open_repositories(config)?;
+    let policy = script_policy(&package_json, config);
+    let skipped_prepares = Mutex::new(Vec::new());
+    let prepare = PrepareScript {
+        config,
+        policy: &policy,
+        skipped: &skipped_prepares,
+    };
+    let fetcher = store.fetcher(LocalFetcher::new(
+        GitFetcher::new(&registry, &repositories, prepare),
+        root,
+    ));
+    dinopkg_installer::install(&resolution, &layout, root, &fetcher).await?;
+
+    let skipped_prepares = skipped_prepares.into_inner().unwrap_or_default();
+    run_lifecycle_scripts(
+        &resolution,
+        &layout,
+        root,
+        &workspaces,
+        &policy,
+        skipped_prepares,
+        config,
+    )
+    .await
+}
@@ -0,0 +1,79 @@
+use color_eyre::{eyre::eyre, Result};
+use dinopkg_config::{is_protected, Config, Location, Value};
+use owo_colors::OwoColorize;
+
+pub fn get(keys: &[String], config: &Config) -> Result<()> {
+    for key in keys {
+        if is_protected(key) {
+            return Err(eyre!("the `{key}` option is protected, and can't be shown"));
+        }
+        let value = config.get(key).map(ToString::to_string);
+        if keys.len() == 1 {
+            println!("{}", value.unwrap_or_else(|| "undefined".into()));
+        } else {
+            println!("{key}={}", value.unwrap_or_default());
+        }
+    }
+    Ok(())
+}
+
+pub fn set(pairs: &[String], location: Location, config: &mut Config) -> Result<()> {
+    let mut pairs = pairs.iter();
+    while let Some(pair) = pairs.next() {
+        // Both `key=value` and `key value` work, like npm.
+        let (key, value) = match pair.split_once('=') {
+            Some((key, value)) => (key, value),
+            None => (pair.as_str(), pairs.next().map_or("", String::as_str)),
+        };
+        config.save(location, key, value)?;
+    }
+    Ok(())
+}
+
+pub fn delete(keys: &[String], location: Location, config: &mut Config) -> Result<()> {
+    for key in keys {
+        config.delete(location, key)?;
+    }
+    Ok(())
+}
+
+/// Prints every layer of config, with settings that are overridden by a higher layer
+/// commented out.
+pub fn list(long: bool, config: &Config) {
+    for (index, layer) in config.layers().iter().enumerate() {
+        if layer.values.is_empty() || (layer.location == Location::Default && !long) {
+            continue;
+        }
+        let header = match &layer.path {
+            Some(path) => format!("; \"{}\" config from {}", layer.location, path.display()),
+            None => format!("; \"{}\" config", layer.location),
+        };
+        println!("{}", header.dimmed());
+        for (key, value) in &layer.values {
+            let line = format!("{key} = {}", display_value(key, value));
+            let overridden_by = config.layers()[..index]
+                .iter()
+                .rev()
+                .find(|higher| higher.values.contains_key(key));
+            match overridden_by {
+                Some(higher) => println!(
+                    "{}",
+                    format!("; {line} ; overridden by {}", higher.location).dimmed()
+                ),
+                None => println!("{line}"),
+            }
+        }
+        println!();
+    }
+}
+
+fn display_value(key: &str, value: &Value) -> String {
+    if is_protected(key) {
+        return "(protected)".into();
+    }
+    match value {
+        Value::String(value) => format!("{value:?}"),
+        Value::Bool(value) => value.to_string(),
+        Value::List(values) => format!("{values:?}"),
+    }
+}
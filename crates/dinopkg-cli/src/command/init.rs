@@ -4,9 +4,9 @@ use camino::Utf8PathBuf;
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input};
-use dinopkg_package_json::{AuthorVariant, PackageJson, RepositoryVariant};
+use dinopkg_config::Config;
+use dinopkg_package_json::{AuthorVariant, PackageJson, RepositoryVariant, Scripts};
 use gix_config::File as GitConfigFile;
-use maplit::hashmap;
 use owo_colors::OwoColorize;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, ThemeSet};
@@ -14,7 +14,7 @@ use syntect::parsing::SyntaxSet;
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 use tokio::fs;
 
-pub async fn init() -> Result<()> {
+pub async fn init(config: &Config) -> Result<()> {
     // FIXME: one instance should be loaded at start
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
@@ -56,7 +56,7 @@ pub async fn init() -> Result<()> {
         .interact_text()?;
     let version: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Package version")
-        .default("1.0.0".into())
+        .default(config.get_str("init-version").unwrap_or("1.0.0").into())
         .interact_text()?;
     let private = Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt("Is this a private package?")
@@ -94,7 +94,7 @@ pub async fn init() -> Result<()> {
             spdx::Expression::parse(input)
                 .map_or_else(|err| Err(format!("license is invalid:\n{err}")), |_| Ok(()))
         })
-        .default("MIT".into())
+        .default(config.get_str("init-license").unwrap_or("MIT").into())
         .interact_text()?;
 
     let package_json = PackageJson {
@@ -107,12 +107,9 @@ pub async fn init() -> Result<()> {
         private,
         main: Some(entry_point),
 
-        scripts: Some(hashmap! {
-            "test".into() => test_command,
-        }),
+        scripts: Some(Scripts::from([("test".into(), test_command)])),
 
-        dependencies: None,
-        dev_dependencies: None,
+        ..Default::default()
     };
 
     println!(
@@ -1,30 +1,517 @@
-use color_eyre::Result;
-use dinopkg_npm_registry::PackageInfo;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use dinopkg_config::{Config, Location, Value, DEFAULT_REGISTRY};
+use dinopkg_git::Repositories;
+use dinopkg_installer::lifecycle::{self, Build, Script, Skipped};
+use dinopkg_installer::{
+    layout, GitFetcher, Layout, LocalFetcher, Policy, Prepare, RunScript, Strategy,
+};
+use dinopkg_lockfile::{Lockfile, LOCKFILE_NAME};
+use dinopkg_npm_registry::{
+    read_tarball_file, Auth, CacheMode, DiskCache, NetworkOptions, RegistryClient, RetryPolicy,
+};
+use dinopkg_package_json::{DependencySpec, Editor, PackageArg, PackageJson, Workspace};
+use dinopkg_resolver::{Resolution, ResolvedPackage, Source, WithGit};
 use owo_colors::OwoColorize;
 
-pub async fn install_cmd(name: String) -> Result<()> {
-    let client = reqwest::Client::new();
+use crate::run_script::{run_script, shell_exec_arg, DEFAULT_SHELL};
+
+pub async fn install_cmd(arg: Option<String>, strategy: Strategy, config: &Config) -> Result<()> {
+    let registry = registry_client(config)?;
+    let mode = registry.mode();
+    let repositories = open_repositories(config)?;
+
+    let (mut package_json, package_json_path) = PackageJson::from_file(10).await?;
+    let root = package_json_path
+        .parent()
+        .ok_or_else(|| eyre!("package.json should be in a directory"))?;
+    let added = match arg {
+        Some(arg) => add_dependency(&mut package_json, &arg, root).await?,
+        None => None,
+    };
+
+    let lockfile_path = root.join(LOCKFILE_NAME);
+    let lockfile = read_lockfile(&lockfile_path).await?;
+    let workspaces = find_workspaces(root, &package_json)?;
+
+    // An up-to-date lockfile is used as-is, so installs are reproducible.
+    let locked = lockfile
+        .as_ref()
+        .filter(|lockfile| lockfile.is_up_to_date(&package_json, &workspaces));
+    let (resolution, hoisted) = match locked {
+        Some(lockfile) => lockfile.to_resolution()?,
+        None => {
+            // Whatever hasn't changed keeps what it was locked to, and a lockfile
+            // that can't be used is resolved from scratch.
+            let seed = lockfile
+                .as_ref()
+                .and_then(|lockfile| lockfile.to_seed(&package_json).ok())
+                .unwrap_or_default();
+            let sources = WithGit::new(&registry, &repositories);
+            let resolution =
+                dinopkg_resolver::resolve_from(&sources, &package_json, root, &seed).await?;
+            let hoisted = layout::hoisted(&resolution);
+            (resolution, hoisted)
+        }
+    };
+    for package in resolution.packages.values() {
+        print_dep_version(&package.id.name, &package.id.version, package.dev);
+    }
+
+    let layout = match strategy {
+        Strategy::Hoisted => hoisted.clone(),
+        Strategy::Isolated => strategy.layout(&resolution),
+    };
+    let store = super::store::open(config)?;
+    if mode == CacheMode::Offline {
+        let missing: Vec<String> = resolution
+            .packages
+            .values()
+            // Git dependencies come from the git cache, which is checked when they're
+            // installed, and local ones don't need the network.
+            .filter(|package| package.source == Source::Registry && !store.contains(package))
+            .map(|package| package.id.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return Err(eyre!(
+                "not available offline: {}\nthese packages aren't in the store yet",
+                missing.join(", ")
+            ));
+        }
+    }
+    let policy = script_policy(&package_json, config);
+    let skipped_prepares = Mutex::new(Vec::new());
+    let prepare = PrepareScript {
+        config,
+        policy: &policy,
+        skipped: &skipped_prepares,
+    };
+    // An optional dependency of the same name might not have been installable, so
+    // there's no telling that the added package made it into the resolution.
+    let added = match added {
+        Some((name, spec)) => {
+            let id = resolution
+                .dependencies
+                .get(&name)
+                .ok_or_else(|| eyre!("`{name}` couldn't be installed"))?;
+            let spec = saved_spec(&spec, &id.version, config.save_exact());
+            Some((name, spec))
+        }
+        None => None,
+    };
+    let fetcher = LocalFetcher::new(GitFetcher::new(&registry, &repositories, prepare), root);
+    dinopkg_installer::install(&resolution, &layout, root, &store.fetcher(fetcher)).await?;
+
+    if let Some((name, spec)) = added {
+        save_dependency(&package_json_path, &name, &spec).await?;
+        if let Some(dependencies) = &mut package_json.dependencies {
+            dependencies.insert(name, spec);
+        }
+    }
+
+    if locked.is_none() {
+        let mut new_lockfile = Lockfile::from_resolution(&package_json, &resolution, &hoisted);
+        if let Some(lockfile) = &lockfile {
+            new_lockfile.inherit_style(lockfile);
+        }
+        tokio::fs::write(&lockfile_path, new_lockfile.to_string()).await?;
+    }
+
+    let skipped_prepares = skipped_prepares.into_inner().unwrap_or_default();
+    run_lifecycle_scripts(
+        &resolution,
+        &layout,
+        root,
+        &workspaces,
+        &policy,
+        skipped_prepares,
+        config,
+    )
+    .await
+}
+
+/// Which dependencies `package_json` and the config trust to run lifecycle scripts.
+pub(crate) fn script_policy(package_json: &PackageJson, config: &Config) -> Policy {
+    Policy {
+        ignore_scripts: config.ignore_scripts(),
+        trusted: package_json
+            .trusted_dependencies
+            .iter()
+            .flatten()
+            .cloned()
+            .chain(config.trusted_dependencies())
+            .collect(),
+    }
+}
 
-    let package_info = PackageInfo::from_name(&name, &client).await?;
-    let latest_version = package_info.dist_keys.get("latest").unwrap();
-    let latest_package = package_info.versions.get(latest_version).unwrap();
-    print_dep_version(&latest_package.name, &latest_package.version, false);
+/// Runs the lifecycle scripts of everything that was just installed, and reports the
+/// dependencies that weren't trusted to run theirs, along with the git dependencies
+/// that weren't trusted to run `prepare` (`skipped_prepares`).
+pub(crate) async fn run_lifecycle_scripts(
+    resolution: &Resolution,
+    layout: &Layout,
+    root: &Path,
+    workspaces: &[Workspace],
+    policy: &Policy,
+    skipped_prepares: Vec<Skipped>,
+    config: &Config,
+) -> Result<()> {
+    let workspaces: Vec<String> = workspaces
+        .iter()
+        .map(|workspace| workspace.path.clone())
+        .collect();
+    let mut plan = lifecycle::plan(resolution, layout, root, &workspaces, policy)?;
+    lifecycle::run(&plan, &LifecycleScript { config }).await?;
 
-    if let Some(deps) = &latest_package.dependencies {
-        for (dep_name, dep_version) in deps {
-            print_dep_version(dep_name, dep_version, false);
+    plan.skipped.extend(skipped_prepares);
+    plan.skipped.sort_by(|a, b| a.id.cmp(&b.id));
+    if !plan.skipped.is_empty() {
+        println!(
+            "{}",
+            "Skipped the install scripts of untrusted dependencies:"
+                .yellow()
+                .bold()
+        );
+        for skipped in &plan.skipped {
+            println!("  {} {}", skipped.id, skipped.events.join(", ").dimmed());
         }
+        println!("Add the ones you trust to `trustedDependencies` in package.json to run them.");
+    }
+    Ok(())
+}
+
+/// Finds the workspaces of the monorepo whose root is `root`, if it's one.
+pub fn find_workspaces(root: &Path, package_json: &PackageJson) -> Result<Vec<Workspace>> {
+    match &package_json.workspaces {
+        Some(workspaces) => Ok(dinopkg_package_json::find_workspaces(root, workspaces)?),
+        None => Ok(Vec::new()),
     }
-    if let Some(deps) = &latest_package.dev_dependencies {
-        for (dep_name, dep_version) in deps {
-            print_dep_version(dep_name, dep_version, true);
+}
+
+/// Adds the dependency `arg` asks for to `package_json`, and returns its name and spec.
+///
+/// Nothing's added if `arg` is just the name of a dependency that's already there.
+/// Local paths are saved relative to `root`.
+async fn add_dependency(
+    package_json: &mut PackageJson,
+    arg: &str,
+    root: &Path,
+) -> Result<Option<(String, DependencySpec)>> {
+    let PackageArg { name, spec } = PackageArg::parse(arg)?;
+    let spec = match spec {
+        DependencySpec::Directory(path) => DependencySpec::Directory(relative_to(root, &path)?),
+        DependencySpec::File(path) => DependencySpec::File(relative_to(root, &path)?),
+        DependencySpec::Link(path) => DependencySpec::Link(relative_to(root, &path)?),
+        spec => spec,
+    };
+    let name = match (name, &spec) {
+        (Some(name), _) => name,
+        (None, DependencySpec::Directory(path) | DependencySpec::Link(path)) => {
+            let manifest = root.join(path).join("package.json");
+            let contents = tokio::fs::read_to_string(&manifest)
+                .await
+                .wrap_err_with(|| format!("couldn't read {}", manifest.display()))?;
+            PackageJson::parse(&contents)?.name
+        }
+        (None, DependencySpec::File(path)) => {
+            let tarball = root.join(path);
+            let data = tokio::fs::read(&tarball)
+                .await
+                .wrap_err_with(|| format!("couldn't read {}", tarball.display()))?;
+            let contents = read_tarball_file(&data, Path::new("package.json"))?
+                .ok_or_else(|| eyre!("{} has no package.json", tarball.display()))?;
+            PackageJson::parse(&String::from_utf8_lossy(&contents))?.name
+        }
+        (None, spec) => {
+            return Err(eyre!(
+                "can't tell which package `{arg}` is\nuse `<name>@{spec}` to name it"
+            ))
+        }
+    };
+
+    let dependencies = package_json
+        .dependencies
+        .get_or_insert_with(Default::default);
+    if arg == name && dependencies.contains_key(&name) {
+        return Ok(None);
+    }
+    // Tags and `*` are saved as the version they resolved to, once it's known.
+    let wanted = match &spec {
+        DependencySpec::Range(range) if range == "*" => DependencySpec::Tag("latest".into()),
+        spec => spec.clone(),
+    };
+    dependencies.insert(name.clone(), wanted.to_string());
+    Ok(Some((name, spec)))
+}
+
+/// Turns `path`, relative to the current directory, into a path relative to `root`, with
+/// forward slashes like npm saves them.
+fn relative_to(root: &Path, path: &str) -> Result<String> {
+    let canonicalize = |path: &Path| {
+        std::fs::canonicalize(path).wrap_err_with(|| format!("couldn't find {}", path.display()))
+    };
+    let target = canonicalize(&std::env::current_dir()?.join(path))?;
+    let root = canonicalize(root)?;
+    let common = root
+        .components()
+        .zip(target.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        // Different drives on Windows, so there's no relative path.
+        return Ok(target.to_string_lossy().into_owned());
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..root.components().count() {
+        relative.push("..");
+    }
+    relative.extend(target.components().skip(common));
+    let parts: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    Ok(if parts.is_empty() {
+        ".".into()
+    } else {
+        parts.join("/")
+    })
+}
+
+/// What to save in package.json for a dependency that was installed as `version`, like
+/// npm: `^version` for tags and exact versions, and other specs as they were written.
+fn saved_spec(spec: &DependencySpec, version: &str, save_exact: bool) -> String {
+    match spec {
+        DependencySpec::Alias { name, spec } => {
+            format!("npm:{name}@{}", saved_spec(spec, version, save_exact))
+        }
+        DependencySpec::Range(range)
+            if range != "*" && range.trim_start_matches(['=', 'v']) != version =>
+        {
+            range.clone()
+        }
+        DependencySpec::Range(_) | DependencySpec::Tag(_) => {
+            if save_exact {
+                version.into()
+            } else {
+                format!("^{version}")
+            }
         }
+        spec => spec.to_string(),
     }
+}
 
+/// Adds a dependency to package.json, leaving the rest of the file as it was.
+async fn save_dependency(path: &Path, name: &str, spec: &str) -> Result<()> {
+    let mut editor = Editor::parse(&tokio::fs::read_to_string(path).await?)?;
+    editor.set_dependency("dependencies", name, spec)?;
+    tokio::fs::write(path, editor.as_str()).await?;
     Ok(())
 }
 
-fn print_dep_version(name: &String, version: &String, is_dev: bool) {
+/// Reads the configured credentials. Credentials without a registry are only trusted
+/// from the user's and global config, for the registry set in that same file, since a
+/// project's `.npmrc` could point the registry anywhere.
+fn auth(config: &Config) -> Auth {
+    let mut auth = Auth::from_settings(
+        config
+            .merged()
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_str()?))),
+    );
+    for location in [Location::User, Location::Global] {
+        let Some(layer) = config.layer(location) else {
+            continue;
+        };
+        let registry = layer
+            .values
+            .get("registry")
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_REGISTRY);
+        let settings = layer
+            .values
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str(), value.as_str()?)));
+        auth = auth.with_unscoped_settings(settings, registry);
+    }
+    auth
+}
+
+/// Sets up a registry client with the configured registries, credentials, cache,
+/// proxies and certificates.
+pub(crate) fn registry_client(config: &Config) -> Result<RegistryClient<DiskCache>> {
+    let mode = if config.offline() {
+        CacheMode::Offline
+    } else if config.prefer_offline() {
+        CacheMode::PreferOffline
+    } else {
+        CacheMode::Online
+    };
+    let client = NetworkOptions {
+        proxy: config.proxy().map(Into::into),
+        https_proxy: config.https_proxy().map(Into::into),
+        noproxy: config.noproxy(),
+        ca: config.ca()?,
+        strict_ssl: config.strict_ssl(),
+    }
+    .build()?;
+    let mut registry = RegistryClient::new(client, open_cache(config)?)
+        .with_mode(mode)
+        .with_registry(config.registry())
+        .with_auth(auth(config))
+        .with_retry_policy(RetryPolicy {
+            retries: config.fetch_retries(),
+            factor: config.fetch_retry_factor(),
+            min_timeout: config.fetch_retry_mintimeout(),
+            max_timeout: config.fetch_retry_maxtimeout(),
+        })
+        .with_max_sockets(config.maxsockets());
+    if let Some(timeout) = config.fetch_timeout() {
+        registry = registry.with_timeout(timeout);
+    }
+    for (scope, url) in config.scope_registries() {
+        registry = registry.with_scope_registry(scope, url);
+    }
+    Ok(registry)
+}
+
+/// Opens the packument cache, in `$cache/packuments` if `cache` is set.
+pub(crate) fn open_cache(config: &Config) -> Result<DiskCache> {
+    config
+        .cache_dir()
+        .map(|dir| dir.join("packuments"))
+        .or_else(DiskCache::default_dir)
+        .map(DiskCache::new)
+        .ok_or_else(|| eyre!("couldn't find a cache directory to keep package metadata in"))
+}
+
+/// Opens the cache of git repositories, in `$cache/git` if `cache` is set.
+pub(crate) fn open_repositories(config: &Config) -> Result<Repositories> {
+    let root = config
+        .cache_dir()
+        .map(|dir| dir.join("git"))
+        .or_else(Repositories::default_root)
+        .ok_or_else(|| eyre!("couldn't find a cache directory to keep git repositories in"))?;
+    Ok(Repositories::new(root).with_offline(config.offline()))
+}
+
+/// Prepares git dependencies by installing their dependencies with another dino, and
+/// then running their `prepare` script.
+///
+/// Like other lifecycle scripts, `prepare` only runs for dependencies `policy` trusts.
+/// The others are packed as they are, and added to `skipped`.
+pub(crate) struct PrepareScript<'a> {
+    pub(crate) config: &'a Config,
+    pub(crate) policy: &'a Policy,
+    pub(crate) skipped: &'a Mutex<Vec<Skipped>>,
+}
+
+impl Prepare for PrepareScript<'_> {
+    async fn prepare(
+        &self,
+        package: &ResolvedPackage,
+        dir: &Path,
+        package_json: &PackageJson,
+    ) -> Result<(), dinopkg_installer::Error> {
+        if self.policy.ignore_scripts {
+            return Ok(());
+        }
+        if !self.policy.allows(&package.id.name, package) {
+            if let Ok(mut skipped) = self.skipped.lock() {
+                skipped.push(Skipped {
+                    id: package.id.clone(),
+                    events: vec!["prepare"],
+                });
+            }
+            return Ok(());
+        }
+        let failed = |message: String| dinopkg_installer::Error::Prepare {
+            name: package_json.name.clone(),
+            message,
+        };
+
+        // It reads the same user and global `.npmrc` files, but not this project's or
+        // our flags, so those are passed on (scoped registries and credentials too).
+        let mut install = tokio::process::Command::new(std::env::current_exe()?);
+        install
+            .arg("install")
+            .envs(self.config.to_env(&[Location::Cli, Location::Project]))
+            .current_dir(dir);
+        // Its output would get mixed up with ours, so it's only shown if something goes wrong.
+        let output = install.output().await?;
+        if !output.status.success() {
+            return Err(failed(format!(
+                "installing its dependencies failed\n{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let Some(script) = package_json
+            .scripts
+            .as_ref()
+            .and_then(|scripts| scripts.get("prepare"))
+        else {
+            return Ok(());
+        };
+        let shell = self.config.script_shell().unwrap_or(DEFAULT_SHELL);
+        let status = run_script(shell, shell_exec_arg(shell), script, dir)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+        if !status.success() {
+            return Err(failed(format!("`{script}` failed ({status})")));
+        }
+        Ok(())
+    }
+}
+
+/// Runs lifecycle scripts with the configured shell.
+struct LifecycleScript<'a> {
+    config: &'a Config,
+}
+
+impl RunScript for LifecycleScript<'_> {
+    async fn run_script(
+        &self,
+        build: &Build,
+        script: &Script,
+    ) -> Result<(), dinopkg_installer::Error> {
+        let failed = |message: String| dinopkg_installer::Error::Script {
+            name: build.name.clone(),
+            event: script.event.into(),
+            message,
+        };
+
+        println!(
+            "{} {} {}",
+            "$".purple().dimmed(),
+            format!("{} {}", build.name, script.event).bold(),
+            script.command.dimmed()
+        );
+        let shell = self.config.script_shell().unwrap_or(DEFAULT_SHELL);
+        let status = run_script(shell, shell_exec_arg(shell), &script.command, &build.dir)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+        if !status.success() {
+            return Err(failed(format!("`{}` failed ({status})", script.command)));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) async fn read_lockfile(path: &Path) -> Result<Option<Lockfile>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(Some(Lockfile::parse(&contents)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub(crate) fn print_dep_version(name: &str, version: &str, is_dev: bool) {
     println!(
         "  {} {} {}{}",
         "Installing".green().bold(),
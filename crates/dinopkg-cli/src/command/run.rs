@@ -1,23 +1,84 @@
+use std::path::Path;
+
 use color_eyre::{eyre::eyre, Result};
-use dinopkg_package_json::PackageJson;
+use dinopkg_config::Config;
+use dinopkg_package_json::{PackageJson, Workspace};
 use owo_colors::OwoColorize;
 
-use crate::run_script::{run_script, DEFAULT_SHELL, DEFAULT_SHELL_EXEC_ARG};
+use super::install::find_workspaces;
+use crate::run_script::{run_script, shell_exec_arg, DEFAULT_SHELL};
+
+/// Which packages of a monorepo to run a script in.
+pub enum Target {
+    /// Just the package the current directory is in.
+    Root,
+
+    /// The workspaces with these names or paths.
+    Workspaces(Vec<String>),
+
+    /// Every workspace.
+    AllWorkspaces,
+}
 
-pub async fn run(script_name: Option<String>) -> Result<()> {
+pub async fn run(script_name: Option<String>, target: Target, config: &Config) -> Result<()> {
     let (package_json, package_json_path) = PackageJson::from_file(10).await?;
     let root_path = package_json_path.parent().unwrap(); // Should never happen, `package.json` should always be there
 
+    let names = match target {
+        Target::Root => {
+            return run_in(script_name.as_deref(), package_json, root_path, config).await
+        }
+        Target::Workspaces(names) => Some(names),
+        Target::AllWorkspaces => None,
+    };
+    let workspaces = find_workspaces(root_path, &package_json)?;
+    let selected: Vec<Workspace> = match names {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                workspaces
+                    .iter()
+                    .find(|workspace| is_named(workspace, name))
+                    .cloned()
+                    .ok_or_else(|| eyre!("no workspace named `{name}`"))
+            })
+            .collect::<Result<_>>()?,
+        None if workspaces.is_empty() => return Err(eyre!("no workspaces found")),
+        None => workspaces,
+    };
+
+    for workspace in selected {
+        println!(
+            "{} {}",
+            ">".purple().dimmed(),
+            workspace.package_json.name.bold()
+        );
+        let dir = root_path.join(&workspace.path);
+        run_in(script_name.as_deref(), workspace.package_json, &dir, config)
+            .await
+            .map_err(|err| eyre!("in workspace `{}`: {err}", workspace.path))?;
+    }
+    Ok(())
+}
+
+/// Runs `script_name` from `package_json`, in the package's directory `dir`, or lists
+/// the scripts if there's no name.
+async fn run_in(
+    script_name: Option<&str>,
+    package_json: PackageJson,
+    dir: &Path,
+    config: &Config,
+) -> Result<()> {
     let Some(scripts) = package_json.scripts else {
         return Err(eyre!("no `scripts` provided in package.json"));
     };
     if let Some(script_name) = script_name {
-        match scripts.get(&script_name) {
+        match scripts.get(script_name) {
             Some(script) => {
                 println!("{} {}", "$".purple().dimmed(), script.bold().dimmed());
 
-                let status =
-                    run_script(DEFAULT_SHELL, DEFAULT_SHELL_EXEC_ARG, script, root_path).await?;
+                let shell = config.script_shell().unwrap_or(DEFAULT_SHELL);
+                let status = run_script(shell, shell_exec_arg(shell), script, dir).await?;
 
                 if cfg!(unix) {
                     use std::os::unix::process::ExitStatusExt;
@@ -45,3 +106,10 @@ pub async fn run(script_name: Option<String>) -> Result<()> {
 
     Ok(())
 }
+
+/// Whether `workspace` is the one `name` refers to, either by its package name or its path
+/// (like `packages/a`), which is what npm's `--workspace` accepts too.
+fn is_named(workspace: &Workspace, name: &str) -> bool {
+    let path = name.trim_start_matches("./").trim_end_matches('/');
+    workspace.package_json.name == name || workspace.path == path
+}
@@ -0,0 +1,53 @@
+use color_eyre::{eyre::eyre, Result};
+use dinopkg_config::Config;
+use dinopkg_installer::Store;
+use owo_colors::OwoColorize;
+
+/// Opens the global package store, in `store-dir` if it's set.
+pub(crate) fn open(config: &Config) -> Result<Store> {
+    config
+        .store_dir()
+        .or_else(Store::default_root)
+        .map(Store::new)
+        .ok_or_else(|| eyre!("couldn't find a data directory to keep the package store in"))
+}
+
+pub fn status(config: &Config) -> Result<()> {
+    let store = open(config)?;
+    let status = store.status()?;
+    println!("{} {}", "Store:".bold(), store.root().display());
+    println!(
+        "{} {} ({} unused)",
+        "Packages:".bold(),
+        status.packages,
+        status.unreferenced
+    );
+    println!("{} {}", "Size:".bold(), format_size(status.size));
+    Ok(())
+}
+
+pub fn prune(config: &Config) -> Result<()> {
+    let pruned = open(config)?.prune()?;
+    println!(
+        "  {} {} packages, freeing {}",
+        "Removed".green().bold(),
+        pruned.packages,
+        format_size(pruned.size)
+    );
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,69 @@
+use color_eyre::{Report, Section};
+use dinopkg_npm_registry::{nerf_dart, Error as RegistryError};
+
+/// Adds a suggestion about what to do next, if `report` was caused by a registry error.
+pub fn explain(report: Report) -> Report {
+    let suggestion = report
+        .chain()
+        .find_map(|err| err.downcast_ref::<RegistryError>())
+        .and_then(suggestion);
+    match suggestion {
+        Some(suggestion) => report.suggestion(suggestion),
+        None => report,
+    }
+}
+
+fn suggestion(err: &RegistryError) -> Option<String> {
+    Some(match err {
+        RegistryError::PackageNotFound { name, registry } => format!(
+            "check that `{name}` is spelled correctly. If it's a private package, make sure its registry is set up, like `dino config set @scope:registry <url>` (currently using {registry})"
+        ),
+        RegistryError::VersionNotFound {
+            name,
+            latest: Some(latest),
+            ..
+        } => format!("the latest version of `{name}` is {latest}"),
+        RegistryError::VersionNotFound { name, .. } => {
+            format!("`{name}` doesn't have a `latest` tag, so ask for a specific version")
+        }
+        RegistryError::Unauthorized { url } => format!(
+            "set a token for this registry with `dino config set {}:_authToken <token>`",
+            registry_key(url)
+        ),
+        RegistryError::Forbidden { url } => format!(
+            "the credentials for {} don't give access to this package, so check its permissions or use a different token",
+            registry_key(url)
+        ),
+        RegistryError::RateLimited {
+            retry_after: Some(after),
+            ..
+        } => format!(
+            "the registry is limiting requests, try again in {} seconds",
+            after.as_secs()
+        ),
+        RegistryError::RateLimited { .. } => {
+            "the registry is limiting requests, try again later".into()
+        }
+        RegistryError::MalformedPackument { .. } => {
+            "the registry sent something that isn't package metadata. If you're using a mirror or proxy, check that it's working".into()
+        }
+        RegistryError::IntegrityMismatch { .. } => {
+            "the tarball doesn't match the checksum the registry published, so it's been corrupted or tampered with. Try again, and if it keeps happening, check any mirrors or proxies in between".into()
+        }
+        RegistryError::NotCached(_) => {
+            "run the command again without `--offline`, so the metadata can be downloaded".into()
+        }
+        RegistryError::InvalidProxy(_) => {
+            "check the `proxy` and `https-proxy` settings, and the `HTTP_PROXY` and `HTTPS_PROXY` environment variables".into()
+        }
+        RegistryError::InvalidCertificate(_) => {
+            "`ca` and `cafile` should contain PEM certificates, starting with `-----BEGIN CERTIFICATE-----`".into()
+        }
+        _ => return None,
+    })
+}
+
+/// The `.npmrc` key prefix for `url`'s credentials.
+fn registry_key(url: &str) -> String {
+    nerf_dart(url).unwrap_or_else(|| url.into())
+}
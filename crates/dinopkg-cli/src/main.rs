@@ -1,10 +1,13 @@
 use clap::Parser;
 use color_eyre::Result;
+use dinopkg_config::{Config, Environment, Value};
 use env_logger::Env;
 
 mod command;
+mod help;
 mod run_script;
-use command::{Cli, Command};
+use command::run::Target;
+use command::{Cli, Command, ConfigCommand, StoreCommand};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -15,11 +18,76 @@ async fn main() -> Result<()> {
     env_logger::try_init_from_env(env)?;
 
     let cli = Cli::parse();
+    run(cli).await.map_err(help::explain)
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut config = Config::load(&Environment::current()?)?;
     match cli.command {
-        Command::Run { script_name } => command::run::run(script_name).await?,
-        Command::Test => command::run::run(Some("test".into())).await?,
-        Command::Init => command::init::init().await?,
-        Command::Install { name } => command::install::install_cmd(name).await?,
+        Command::Run {
+            script_name,
+            workspaces,
+            all_workspaces,
+        } => {
+            let target = if all_workspaces {
+                Target::AllWorkspaces
+            } else if workspaces.is_empty() {
+                Target::Root
+            } else {
+                Target::Workspaces(workspaces)
+            };
+            command::run::run(script_name, target, &config).await?;
+        }
+        Command::Test => {
+            command::run::run(Some("test".into()), Target::Root, &config).await?;
+        }
+        Command::Init => command::init::init(&config).await?,
+        Command::Install {
+            name,
+            install_strategy,
+            offline,
+            prefer_offline,
+            registry,
+            ignore_scripts,
+        } => {
+            // Flags override everything else, so they're the highest layer of config.
+            if offline {
+                config.set_cli("offline", Value::Bool(true));
+            }
+            if prefer_offline {
+                config.set_cli("prefer-offline", Value::Bool(true));
+            }
+            if let Some(registry) = registry {
+                config.set_cli("registry", Value::String(registry));
+            }
+            if ignore_scripts {
+                config.set_cli("ignore-scripts", Value::Bool(true));
+            }
+            command::install::install_cmd(name, install_strategy.into(), &config).await?;
+        }
+        Command::Ci {
+            install_strategy,
+            ignore_scripts,
+        } => {
+            if ignore_scripts {
+                config.set_cli("ignore-scripts", Value::Bool(true));
+            }
+            command::ci::ci(install_strategy.into(), &config).await?;
+        }
+        Command::Store { command } => match command {
+            StoreCommand::Status => command::store::status(&config)?,
+            StoreCommand::Prune => command::store::prune(&config)?,
+        },
+        Command::Config { command } => match command {
+            ConfigCommand::Get { keys } => command::config::get(&keys, &config)?,
+            ConfigCommand::Set { pairs, location } => {
+                command::config::set(&pairs, location.into(), &mut config)?;
+            }
+            ConfigCommand::List { long } => command::config::list(long, &config),
+            ConfigCommand::Delete { keys, location } => {
+                command::config::delete(&keys, location.into(), &mut config)?;
+            }
+        },
     }
     Ok(())
 }
@@ -1,10 +1,22 @@
-use std::{path::Path, process::ExitStatus};
+use std::{ffi::OsString, path::Path, process::ExitStatus};
 
 use color_eyre::eyre::Result;
 use tokio::process::Command;
 
 pub const DEFAULT_SHELL: &str = if cfg!(windows) { "cmd.exe" } else { "/bin/sh" };
-pub const DEFAULT_SHELL_EXEC_ARG: &str = if cfg!(windows) { "/c" } else { "-c" };
+
+/// The argument that makes `shell` run a command, since `cmd.exe` is different from everything else.
+pub fn shell_exec_arg(shell: &str) -> &'static str {
+    let name = Path::new(shell)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(shell);
+    if name.eq_ignore_ascii_case("cmd.exe") || name.eq_ignore_ascii_case("cmd") {
+        "/c"
+    } else {
+        "-c"
+    }
+}
 
 pub async fn run_script(
     shell: &str,
@@ -20,6 +32,20 @@ pub async fn run_script(
     tokio_command
         .arg(shell_exec_arg)
         .arg(command)
-        .current_dir(cwd_path);
+        .current_dir(cwd_path)
+        .env("PATH", path_with_bins(cwd_path)?);
     Ok(tokio_command.status().await?)
 }
+
+/// `PATH`, with the `node_modules/.bin` of `dir` and of every directory above it in
+/// front (closest first), so scripts can run their dependencies' executables like npm's can.
+fn path_with_bins(dir: &Path) -> Result<OsString> {
+    let dir = std::path::absolute(dir)?;
+    let bins = dir
+        .ancestors()
+        .map(|dir| dir.join("node_modules").join(".bin"));
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    Ok(std::env::join_paths(
+        bins.chain(std::env::split_paths(&path)),
+    )?)
+}
@@ -0,0 +1,265 @@
+//! Reading and editing `.npmrc` files, which use the same flavour of INI as npm's
+//! [`ini`](https://github.com/npm/ini) package.
+
+use std::collections::BTreeMap;
+
+use crate::Value;
+
+/// Parses an `.npmrc` file.
+///
+/// Keys inside `[sections]` are ignored, since npm doesn't use them either.
+pub fn parse(contents: &str) -> BTreeMap<String, Value> {
+    let mut values = BTreeMap::new();
+    let mut in_section = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            in_section = true;
+            continue;
+        }
+        if in_section {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (unescape(key), Some(unescape(value))),
+            None => (unescape(line), None),
+        };
+        if let Some(key) = key.strip_suffix("[]") {
+            let list = values
+                .entry(key.to_owned())
+                .or_insert_with(|| Value::List(Vec::new()));
+            if let (Value::List(list), Some(value)) = (list, value) {
+                list.push(value);
+            }
+            continue;
+        }
+        let value = match value.as_deref() {
+            None | Some("true") => Value::Bool(true),
+            Some("false") => Value::Bool(false),
+            Some("null") => continue,
+            Some(_) => Value::String(value.unwrap_or_default()),
+        };
+        values.insert(key, value);
+    }
+    values
+}
+
+/// Unquotes a key or value, or strips its comment and escapes if it's not quoted.
+fn unescape(raw: &str) -> String {
+    let raw = raw.trim();
+    let quoted = raw.len() >= 2
+        && ((raw.starts_with('"') && raw.ends_with('"'))
+            || (raw.starts_with('\'') && raw.ends_with('\'')));
+    if quoted {
+        let inner = &raw[1..raw.len() - 1];
+        if raw.starts_with('\'') {
+            return inner.to_owned();
+        }
+        return parse_json_string(raw).unwrap_or_else(|| inner.to_owned());
+    }
+
+    let mut unescaped = String::new();
+    let mut escaping = false;
+    for c in raw.chars() {
+        if escaping {
+            if !matches!(c, '\\' | ';' | '#') {
+                unescaped.push('\\');
+            }
+            unescaped.push(c);
+            escaping = false;
+        } else if c == ';' || c == '#' {
+            break;
+        } else if c == '\\' {
+            escaping = true;
+        } else {
+            unescaped.push(c);
+        }
+    }
+    if escaping {
+        unescaped.push('\\');
+    }
+    unescaped.trim().to_owned()
+}
+
+/// Parses a double-quoted JSON string, without pulling in a JSON parser for it.
+fn parse_json_string(quoted: &str) -> Option<String> {
+    let mut chars = quoted[1..quoted.len() - 1].chars();
+    let mut parsed = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return None,
+            '\\' => parsed.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c @ ('"' | '\\' | '/') => c,
+                _ => return None,
+            }),
+            c => parsed.push(c),
+        }
+    }
+    Some(parsed)
+}
+
+/// Formats a value so that [`parse`] reads it back unchanged, quoting it if needed.
+fn encode(value: &str) -> String {
+    let needs_quotes = value != value.trim()
+        || value.starts_with(['"', '\''])
+        || value.contains([';', '#', '\n', '\r']);
+    if !needs_quotes {
+        return value.to_owned();
+    }
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the key set on `line`, if any.
+fn line_key(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with([';', '#', '[']) {
+        return None;
+    }
+    let key = line.split_once('=').map_or(line, |(key, _)| key);
+    let key = unescape(key);
+    Some(key.strip_suffix("[]").map(str::to_owned).unwrap_or(key))
+}
+
+/// Sets `key` to `value` in an `.npmrc` file, keeping everything else (including
+/// comments) as it was.
+pub fn set(contents: &str, key: &str, value: &str) -> String {
+    let mut line = Some(format!("{}={}", encode(key), encode(value)));
+    let mut lines = Vec::new();
+    let mut in_section = false;
+    for existing in contents.lines() {
+        if existing.trim().starts_with('[') {
+            in_section = true;
+            // Anything after here is in a section, so the new line has to go before it.
+            lines.extend(line.take());
+        }
+        if !in_section && line_key(existing).as_deref() == Some(key) {
+            lines.extend(line.take());
+            continue;
+        }
+        lines.push(existing.to_owned());
+    }
+    lines.extend(line);
+    join(&lines, contents)
+}
+
+/// Removes `key` from an `.npmrc` file, keeping everything else as it was.
+pub fn delete(contents: &str, key: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_section = false;
+    for line in contents.lines() {
+        in_section |= line.trim().starts_with('[');
+        if !in_section && line_key(line).as_deref() == Some(key) {
+            continue;
+        }
+        lines.push(line.to_owned());
+    }
+    join(&lines, contents)
+}
+
+/// Joins lines back up with the same line endings as `original`.
+fn join(lines: &[String], original: &str) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let newline = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut joined = lines.join(newline);
+    joined.push_str(newline);
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_like_npm() {
+        let npmrc = r#"
+; a comment
+# another comment
+registry = https://npm.ourco.com/ ; trailing comment
+@ourco:registry=https://npm.ourco.com/scoped/
+//npm.ourco.com/:_authToken="abc;def#ghi"
+save-exact=true
+engine-strict = false
+message='single quoted'
+escaped = a\;b\#c\d
+strict-ssl
+ca[] = first
+ca[] = second
+unset = null
+
+[section]
+ignored = true
+"#;
+        assert_eq!(
+            parse(npmrc),
+            btreemap! {
+                "registry".into() => Value::String("https://npm.ourco.com/".into()),
+                "@ourco:registry".into() => Value::String("https://npm.ourco.com/scoped/".into()),
+                "//npm.ourco.com/:_authToken".into() => Value::String("abc;def#ghi".into()),
+                "save-exact".into() => Value::Bool(true),
+                "engine-strict".into() => Value::Bool(false),
+                "message".into() => Value::String("single quoted".into()),
+                "escaped".into() => Value::String("a;b#c\\d".into()),
+                "strict-ssl".into() => Value::Bool(true),
+                "ca".into() => Value::List(vec!["first".into(), "second".into()]),
+            }
+        );
+    }
+
+    #[test]
+    fn edits_in_place() {
+        let npmrc = "; keep me\r\nregistry=https://a.example.com/\r\nsave-exact=true\r\n";
+        assert_eq!(
+            set(npmrc, "registry", "https://b.example.com/"),
+            "; keep me\r\nregistry=https://b.example.com/\r\nsave-exact=true\r\n"
+        );
+        assert_eq!(
+            set(npmrc, "message", "has ; semicolon"),
+            "; keep me\r\nregistry=https://a.example.com/\r\nsave-exact=true\r\nmessage=\"has ; semicolon\"\r\n"
+        );
+        assert_eq!(
+            delete(npmrc, "save-exact"),
+            "; keep me\r\nregistry=https://a.example.com/\r\n"
+        );
+        assert_eq!(set("", "save-exact", "true"), "save-exact=true\n");
+    }
+
+    #[test]
+    fn round_trips_values() {
+        for value in ["plain", " padded ", "a;b", "\"quoted\"", "back\\slash"] {
+            let npmrc = set("", "key", value);
+            assert_eq!(parse(&npmrc)["key"], Value::String(value.into()), "{npmrc}");
+        }
+    }
+}
@@ -0,0 +1,826 @@
+//! npm-compatible configuration.
+//!
+//! Settings come from several places, in order of precedence:
+//!
+//! 1. command-line flags
+//! 2. `npm_config_*` environment variables
+//! 3. the project's `.npmrc`, next to its `package.json`
+//! 4. the user's `~/.npmrc`
+//! 5. the global `$PREFIX/etc/npmrc`
+//! 6. the builtin `npmrc`, next to the `dino` executable
+//! 7. defaults
+//!
+//! Values in `.npmrc` files can refer to environment variables, like `${NPM_TOKEN}`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub mod ini;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("failed to replace env in config: ${{{0}}}")]
+    UnsetVariable(String),
+
+    #[error("there's no {0} config file to edit")]
+    NoFile(Location),
+}
+
+/// Where a setting came from. Earlier locations take precedence over later ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+    Cli,
+    Env,
+    Project,
+    User,
+    Global,
+    Builtin,
+    Default,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cli => "cli",
+            Self::Env => "env",
+            Self::Project => "project",
+            Self::User => "user",
+            Self::Global => "global",
+            Self::Builtin => "builtin",
+            Self::Default => "default",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Bool(bool),
+    /// Set with `key[]=value` lines.
+    List(Vec<String>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Booleans can also be set as strings, such as from environment variables.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            Self::String(value) if value == "true" => Some(true),
+            Self::String(value) if value == "false" || value.is_empty() => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(value) => f.write_str(value),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::List(values) => f.write_str(&values.join("\n")),
+        }
+    }
+}
+
+/// The settings from one place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub location: Location,
+
+    /// The file the settings were read from, if any. It might not exist yet.
+    pub path: Option<PathBuf>,
+
+    pub values: BTreeMap<String, Value>,
+}
+
+/// Everything about the outside world that affects the config, so it can be faked in tests.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub cwd: PathBuf,
+    pub home: Option<PathBuf>,
+
+    /// Where the builtin `npmrc` is.
+    pub builtin: Option<PathBuf>,
+
+    pub vars: HashMap<String, String>,
+}
+
+impl Environment {
+    /// The environment `dino` is running in.
+    ///
+    /// # Errors
+    /// This function fails if the current directory can't be read.
+    pub fn current() -> Result<Self, Error> {
+        let builtin = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join("npmrc")));
+        Ok(Self {
+            cwd: std::env::current_dir()?,
+            home: dirs::home_dir(),
+            builtin,
+            // Variables that aren't UTF-8 can't be settings, so they're skipped.
+            vars: std::env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Settings that are sensitive, so they're never shown.
+pub fn is_protected(key: &str) -> bool {
+    let name = key.rsplit(':').next().unwrap_or(key);
+    matches!(name, "_auth" | "_authToken" | "_password")
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Ordered by precedence, highest first.
+    layers: Vec<Layer>,
+}
+
+impl Config {
+    /// Loads every layer of config for `env`.
+    ///
+    /// # Errors
+    /// This function fails if a config file can't be read, or refers to an environment
+    /// variable that isn't set.
+    pub fn load(env: &Environment) -> Result<Self, Error> {
+        let mut config = Self {
+            layers: vec![
+                layer(Location::Cli, None, BTreeMap::new()),
+                layer(Location::Env, None, env_values(&env.vars)),
+                layer(Location::Default, None, defaults(&env.vars)),
+            ],
+        };
+
+        // Where the files are can itself be configured, so each one is found using
+        // the layers loaded so far.
+        if let Some(path) = &env.builtin {
+            config.insert(read_layer(Location::Builtin, path, &env.vars)?);
+        }
+        let global = match config.get_str("globalconfig") {
+            Some(path) => PathBuf::from(path),
+            None => default_prefix(&config, env).join("etc").join("npmrc"),
+        };
+        config.insert(read_layer(Location::Global, &global, &env.vars)?);
+        let user = match (config.get_str("userconfig"), &env.home) {
+            (Some(path), _) => Some(PathBuf::from(path)),
+            (None, Some(home)) => Some(home.join(".npmrc")),
+            (None, None) => None,
+        };
+        if let Some(user) = &user {
+            config.insert(read_layer(Location::User, user, &env.vars)?);
+        }
+        let project = local_prefix(&env.cwd).join(".npmrc");
+        // Like npm, a project that's in the home directory doesn't read `~/.npmrc` twice.
+        if user.as_ref() != Some(&project) {
+            config.insert(read_layer(Location::Project, &project, &env.vars)?);
+        }
+        Ok(config)
+    }
+
+    fn insert(&mut self, layer: Layer) {
+        let index = self
+            .layers
+            .iter()
+            .position(|existing| existing.location > layer.location)
+            .unwrap_or(self.layers.len());
+        self.layers.insert(index, layer);
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, location: Location) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.location == location)
+    }
+
+    /// Overrides a setting for this run, like a command-line flag would.
+    pub fn set_cli(&mut self, key: impl Into<String>, value: Value) {
+        self.layers[0].values.insert(key.into(), value);
+    }
+
+    /// Returns the value of `key` from the layer with the highest precedence that sets it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.layers.iter().find_map(|layer| layer.values.get(key))
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get(key).and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    /// Returns `key` as a list, whether it was set once or with `key[]` lines.
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::String(value)) => vec![value.clone()],
+            Some(Value::List(values)) => values.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns `key` as a number, if it's set to one.
+    pub fn get_number(&self, key: &str) -> Option<u64> {
+        self.get_str(key)?.trim().parse().ok()
+    }
+
+    fn get_millis(&self, key: &str, default: u64) -> Duration {
+        Duration::from_millis(self.get_number(key).unwrap_or(default))
+    }
+
+    /// Every setting, merged across layers.
+    pub fn merged(&self) -> BTreeMap<&str, &Value> {
+        let mut merged = BTreeMap::new();
+        for layer in self.layers.iter().rev() {
+            for (key, value) in &layer.values {
+                merged.insert(key.as_str(), value);
+            }
+        }
+        merged
+    }
+
+    /// The settings from `locations`, as the `npm_config_*` environment variables that
+    /// set them, so that another dino started from here sees them too.
+    ///
+    /// Each one has its merged value, so anything that overrides it here still does.
+    /// Lists can't be set from the environment, so they're left out.
+    pub fn to_env(&self, locations: &[Location]) -> BTreeMap<String, String> {
+        let merged = self.merged();
+        locations
+            .iter()
+            .filter_map(|location| self.layer(*location))
+            .flat_map(|layer| layer.values.keys())
+            .filter_map(|key| match merged.get(key.as_str())? {
+                Value::List(_) => None,
+                value => Some((env_name(key), value.to_string())),
+            })
+            .collect()
+    }
+
+    /// Sets `key` in the config file for `location`, and in this config.
+    ///
+    /// # Errors
+    /// This function fails if `location` doesn't have a file, or it can't be written.
+    pub fn save(&mut self, location: Location, key: &str, value: &str) -> Result<(), Error> {
+        self.edit(location, |contents| ini::set(contents, key, value))?;
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.location == location) {
+            layer.values.insert(key.into(), Value::String(value.into()));
+        }
+        Ok(())
+    }
+
+    /// Removes `key` from the config file for `location`, and from this config.
+    ///
+    /// # Errors
+    /// This function fails if `location` doesn't have a file, or it can't be written.
+    pub fn delete(&mut self, location: Location, key: &str) -> Result<(), Error> {
+        self.edit(location, |contents| ini::delete(contents, key))?;
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.location == location) {
+            layer.values.remove(key);
+        }
+        Ok(())
+    }
+
+    fn edit(&self, location: Location, edit: impl FnOnce(&str) -> String) -> Result<(), Error> {
+        let path = self
+            .layer(location)
+            .and_then(|layer| layer.path.as_ref())
+            .ok_or(Error::NoFile(location))?;
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, edit(&contents))?;
+        Ok(())
+    }
+
+    /// The registry to use for packages that aren't in a scope with its own.
+    pub fn registry(&self) -> &str {
+        self.get_str("registry").unwrap_or(DEFAULT_REGISTRY)
+    }
+
+    /// Scopes with their own registries, set like `@ourco:registry=https://npm.ourco.com/`.
+    pub fn scope_registries(&self) -> BTreeMap<&str, &str> {
+        self.merged()
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let scope = key.strip_suffix(":registry")?;
+                scope.starts_with('@').then_some((scope, value.as_str()?))
+            })
+            .collect()
+    }
+
+    /// Where to cache package metadata, if it's been set.
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.get_str("cache").map(PathBuf::from)
+    }
+
+    /// Where to keep the global package store, if it's been set.
+    pub fn store_dir(&self) -> Option<PathBuf> {
+        self.get_str("store-dir").map(PathBuf::from)
+    }
+
+    /// The shell to run scripts with, if it's been set.
+    pub fn script_shell(&self) -> Option<&str> {
+        self.get_str("script-shell")
+    }
+
+    pub fn offline(&self) -> bool {
+        self.get_bool("offline")
+    }
+
+    pub fn prefer_offline(&self) -> bool {
+        self.get_bool("prefer-offline")
+    }
+
+    /// Whether new dependencies are saved with exact versions instead of `^` ranges.
+    pub fn save_exact(&self) -> bool {
+        self.get_bool("save-exact")
+    }
+
+    /// Whether lifecycle scripts (like `postinstall`) are never run, even for trusted packages.
+    pub fn ignore_scripts(&self) -> bool {
+        self.get_bool("ignore-scripts")
+    }
+
+    /// Dependencies that are allowed to run lifecycle scripts, set as a comma-separated
+    /// list or with `trusted-dependencies[]` lines.
+    pub fn trusted_dependencies(&self) -> Vec<String> {
+        self.get_list("trusted-dependencies")
+            .iter()
+            .flat_map(|names| names.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(Into::into)
+            .collect()
+    }
+
+    /// Whether packages that don't support the current Node.js version are an error.
+    pub fn engine_strict(&self) -> bool {
+        self.get_bool("engine-strict")
+    }
+
+    /// The proxy for `http://` requests, and `https://` ones if `https-proxy` isn't set.
+    pub fn proxy(&self) -> Option<&str> {
+        self.get_str("proxy")
+    }
+
+    pub fn https_proxy(&self) -> Option<&str> {
+        self.get_str("https-proxy")
+    }
+
+    /// Domains that shouldn't be reached through a proxy, set as a comma-separated list
+    /// or with `noproxy[]` lines.
+    pub fn noproxy(&self) -> Vec<String> {
+        self.get_list("noproxy")
+            .iter()
+            .flat_map(|domains| domains.split(','))
+            .map(str::trim)
+            .filter(|domain| !domain.is_empty())
+            .map(Into::into)
+            .collect()
+    }
+
+    /// Certificates to trust instead of the system's. `cafile` takes precedence over `ca`.
+    ///
+    /// # Errors
+    /// This function fails if `cafile` can't be read.
+    pub fn ca(&self) -> Result<Vec<String>, Error> {
+        match self.get_str("cafile") {
+            Some(cafile) => Ok(vec![std::fs::read_to_string(cafile)?]),
+            None => Ok(self.get_list("ca")),
+        }
+    }
+
+    /// Whether registries' certificates are checked.
+    pub fn strict_ssl(&self) -> bool {
+        self.get_bool("strict-ssl")
+    }
+
+    /// How many times to retry a failed request.
+    pub fn fetch_retries(&self) -> u32 {
+        self.get_number("fetch-retries")
+            .and_then(|retries| retries.try_into().ok())
+            .unwrap_or(2)
+    }
+
+    /// How much longer to wait after each retry.
+    pub fn fetch_retry_factor(&self) -> u32 {
+        self.get_number("fetch-retry-factor")
+            .and_then(|factor| factor.try_into().ok())
+            .unwrap_or(10)
+    }
+
+    /// How long to wait before the first retry.
+    pub fn fetch_retry_mintimeout(&self) -> Duration {
+        self.get_millis("fetch-retry-mintimeout", 10_000)
+    }
+
+    /// The longest to wait between retries.
+    pub fn fetch_retry_maxtimeout(&self) -> Duration {
+        self.get_millis("fetch-retry-maxtimeout", 60_000)
+    }
+
+    /// How long a request can take before it's given up on, if there's a limit.
+    pub fn fetch_timeout(&self) -> Option<Duration> {
+        Some(self.get_millis("fetch-timeout", 300_000)).filter(|timeout| !timeout.is_zero())
+    }
+
+    /// How many requests can be in flight at once.
+    pub fn maxsockets(&self) -> usize {
+        self.get_number("maxsockets")
+            .and_then(|max| max.try_into().ok())
+            .unwrap_or(15)
+    }
+}
+
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+fn layer(location: Location, path: Option<PathBuf>, values: BTreeMap<String, Value>) -> Layer {
+    Layer {
+        location,
+        path,
+        values,
+    }
+}
+
+fn defaults(vars: &HashMap<String, String>) -> BTreeMap<String, Value> {
+    let string = |value: &str| Value::String(value.into());
+    let mut defaults = BTreeMap::from([
+        ("registry".into(), string(DEFAULT_REGISTRY)),
+        ("save-exact".into(), Value::Bool(false)),
+        ("engine-strict".into(), Value::Bool(false)),
+        ("ignore-scripts".into(), Value::Bool(false)),
+        ("strict-ssl".into(), Value::Bool(true)),
+        ("offline".into(), Value::Bool(false)),
+        ("prefer-offline".into(), Value::Bool(false)),
+        ("init-version".into(), string("1.0.0")),
+        ("init-license".into(), string("MIT")),
+        ("fetch-retries".into(), string("2")),
+        ("fetch-retry-factor".into(), string("10")),
+        ("fetch-retry-mintimeout".into(), string("10000")),
+        ("fetch-retry-maxtimeout".into(), string("60000")),
+        ("fetch-timeout".into(), string("300000")),
+        ("maxsockets".into(), string("15")),
+    ]);
+
+    // Like npm, the usual proxy environment variables are the defaults for its proxy settings.
+    let first_var = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| vars.get(*name).filter(|value| !value.is_empty()))
+    };
+    let from_env = [
+        (
+            "https-proxy",
+            &["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"][..],
+        ),
+        ("proxy", &["HTTP_PROXY", "http_proxy"]),
+        ("noproxy", &["NO_PROXY", "no_proxy"]),
+    ];
+    for (key, names) in from_env {
+        if let Some(value) = first_var(names) {
+            defaults.insert(key.into(), string(value));
+        }
+    }
+    defaults
+}
+
+/// Reads `npm_config_*` environment variables. Like npm, `npm_config_foo_bar` sets `foo-bar`,
+/// but credentials for a registry, like `npm_config_//npm.ourco.com/:_authToken`, are
+/// kept as they are.
+fn env_values(vars: &HashMap<String, String>) -> BTreeMap<String, Value> {
+    const PREFIX: &str = "npm_config_";
+    vars.iter()
+        .filter_map(|(name, value)| {
+            // Names aren't always ASCII, so this can't slice in the middle of a character.
+            let prefix = name.get(..PREFIX.len())?;
+            if !prefix.eq_ignore_ascii_case(PREFIX) || value.is_empty() {
+                return None;
+            }
+            let key = &name[PREFIX.len()..];
+            if key.starts_with("//") {
+                return Some((key.into(), Value::String(value.clone())));
+            }
+            let mut key = key.chars();
+            let first = key.next()?;
+            let key = format!("{first}{}", key.as_str().replace('_', "-")).to_lowercase();
+            Some((key, Value::String(value.clone())))
+        })
+        .collect()
+}
+
+/// The environment variable that sets `key`, the other way around from [`env_values`].
+fn env_name(key: &str) -> String {
+    if key.starts_with("//") {
+        format!("npm_config_{key}")
+    } else {
+        format!("npm_config_{}", key.replace('-', "_"))
+    }
+}
+
+fn read_layer(
+    location: Location,
+    path: &Path,
+    vars: &HashMap<String, String>,
+) -> Result<Layer, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut values = BTreeMap::new();
+    for (key, value) in ini::parse(&contents) {
+        let value = match value {
+            Value::String(value) => Value::String(replace_env(&value, vars)?),
+            Value::List(values) => Value::List(
+                values
+                    .iter()
+                    .map(|value| replace_env(value, vars))
+                    .collect::<Result<_, _>>()?,
+            ),
+            value => value,
+        };
+        values.insert(replace_env(&key, vars)?, value);
+    }
+    Ok(layer(location, Some(path.to_path_buf()), values))
+}
+
+/// Replaces `${NAME}` with the environment variable `NAME`.
+///
+/// `${NAME?}` is replaced with nothing if `NAME` isn't set, and `\${NAME}` isn't replaced.
+fn replace_env(value: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let mut replaced = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let backslashes = rest[..start].len() - rest[..start].trim_end_matches('\\').len();
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        // Every pair of backslashes is one escaped backslash.
+        replaced.push_str(&rest[..start - backslashes]);
+        replaced.push_str(&"\\".repeat(backslashes / 2));
+        if backslashes % 2 == 1 {
+            replaced.push_str(&rest[start..=end]);
+        } else {
+            let name = &rest[start + 2..end];
+            let (name, optional) = match name.strip_suffix('?') {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            match vars.get(name) {
+                Some(value) => replaced.push_str(value),
+                None if optional => {}
+                None => return Err(Error::UnsetVariable(name.into())),
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    replaced.push_str(rest);
+    Ok(replaced)
+}
+
+/// npm's `prefix`, which is where global packages (and the global config) live.
+fn default_prefix(config: &Config, env: &Environment) -> PathBuf {
+    if let Some(prefix) = config.get_str("prefix") {
+        return PathBuf::from(prefix);
+    }
+    if cfg!(windows) {
+        if let Some(appdata) = env.vars.get("APPDATA") {
+            return Path::new(appdata).join("npm");
+        }
+    }
+    PathBuf::from("/usr/local")
+}
+
+/// The closest directory to `cwd` that looks like a project root, or `cwd` itself.
+fn local_prefix(cwd: &Path) -> PathBuf {
+    cwd.ancestors()
+        .find(|dir| dir.join("package.json").exists() || dir.join("node_modules").exists())
+        .unwrap_or(cwd)
+        .to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::{btreemap, hashmap};
+    use pretty_assertions::assert_eq;
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+        env: Environment,
+    }
+
+    /// A home directory with a project in it, and a prefix for the global config.
+    fn fixture(vars: HashMap<String, String>) -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("home/project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::create_dir_all(dir.path().join("prefix/etc")).unwrap();
+        std::fs::write(project.join("package.json"), "{}").unwrap();
+        let mut vars = vars;
+        vars.insert(
+            "npm_config_prefix".into(),
+            dir.path().join("prefix").display().to_string(),
+        );
+        let env = Environment {
+            cwd: project.join("src"),
+            home: Some(dir.path().join("home")),
+            builtin: Some(dir.path().join("npmrc")),
+            vars,
+        };
+        Fixture { dir, env }
+    }
+
+    impl Fixture {
+        fn write(&self, path: &str, contents: &str) {
+            std::fs::write(self.dir.path().join(path), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn layers_in_npm_order() {
+        let fixture = fixture(hashmap! {
+            "NPM_CONFIG_ENGINE_STRICT".into() => "true".into(),
+            "npm_config__auth".into() => "c2VjcmV0".into(),
+            "npm_config_Äx_y".into() => "unicode".into(),
+            "npm_config_//npm.ourco.com/:_authToken".into() => "s3cret".into(),
+            "ÄÄÄÄÄÄ".into() => "not a setting".into(),
+            "HTTP_PROXY".into() => "http://proxy.ourco.com:3128".into(),
+            "NO_PROXY".into() => "localhost, .ourco.com".into(),
+        });
+        fixture.write(
+            "npmrc",
+            "a=builtin\nb=builtin\nc=builtin\nd=builtin\nregistry=https://b.example.com/",
+        );
+        fixture.write("prefix/etc/npmrc", "a=global\nb=global\nc=global");
+        fixture.write(
+            "home/.npmrc",
+            "a=user\nb=user\n@ourco:registry=https://npm.ourco.com/",
+        );
+        fixture.write("home/project/.npmrc", "a=project\nengine-strict=false");
+
+        let mut config = Config::load(&fixture.env).unwrap();
+        config.set_cli("save-exact", Value::Bool(true));
+        assert_eq!(
+            [
+                config.get_str("a"),
+                config.get_str("b"),
+                config.get_str("c"),
+                config.get_str("d")
+            ],
+            [
+                Some("project"),
+                Some("user"),
+                Some("global"),
+                Some("builtin")
+            ]
+        );
+        assert!(config.engine_strict());
+        assert!(config.save_exact());
+        assert_eq!(config.get_str("_auth"), Some("c2VjcmV0"));
+        assert_eq!(config.get_str("äx-y"), Some("unicode"));
+        assert_eq!(
+            config.get_str("//npm.ourco.com/:_authToken"),
+            Some("s3cret")
+        );
+        assert_eq!(config.registry(), "https://b.example.com/");
+        assert_eq!(config.fetch_retries(), 2);
+        assert_eq!(config.https_proxy(), Some("http://proxy.ourco.com:3128"));
+        assert_eq!(config.noproxy(), ["localhost", ".ourco.com"]);
+        assert!(config.strict_ssl());
+        assert_eq!(config.fetch_timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(
+            config.scope_registries().into_iter().collect::<Vec<_>>(),
+            [("@ourco", "https://npm.ourco.com/")]
+        );
+        let locations: Vec<_> = config.layers().iter().map(|layer| layer.location).collect();
+        assert_eq!(
+            locations,
+            [
+                Location::Cli,
+                Location::Env,
+                Location::Project,
+                Location::User,
+                Location::Global,
+                Location::Builtin,
+                Location::Default
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_settings_on_through_the_environment() {
+        let parent = fixture(hashmap! {
+            "npm_config_fetch_retries".into() => "5".into(),
+        });
+        parent.write(
+            "home/project/.npmrc",
+            "@ourco:registry=https://npm.ourco.com/\n//npm.ourco.com/:_authToken=s3cret\n\
+             fetch-retries=3\ntrusted-dependencies[]=esbuild",
+        );
+        let mut config = Config::load(&parent.env).unwrap();
+        config.set_cli("offline", Value::Bool(true));
+
+        let env = config.to_env(&[Location::Cli, Location::Project]);
+        assert_eq!(
+            env,
+            btreemap! {
+                "npm_config_//npm.ourco.com/:_authToken".into() => "s3cret".into(),
+                "npm_config_@ourco:registry".into() => "https://npm.ourco.com/".into(),
+                "npm_config_fetch_retries".into() => "5".into(),
+                "npm_config_offline".into() => "true".into(),
+            }
+        );
+
+        let child = fixture(env.into_iter().collect());
+        let child = Config::load(&child.env).unwrap();
+        assert_eq!(child.get_str("//npm.ourco.com/:_authToken"), Some("s3cret"));
+        assert_eq!(
+            child.scope_registries().into_iter().collect::<Vec<_>>(),
+            [("@ourco", "https://npm.ourco.com/")]
+        );
+        assert_eq!(child.fetch_retries(), 5);
+        assert!(child.offline());
+    }
+
+    #[test]
+    fn interpolates_environment_variables() {
+        let vars = hashmap! {
+            "NPM_TOKEN".into() => "s3cret".into(),
+            "HOST".into() => "npm.ourco.com".into(),
+        };
+        assert_eq!(replace_env("${NPM_TOKEN}", &vars).unwrap(), "s3cret");
+        assert_eq!(replace_env("a${MISSING?}b", &vars).unwrap(), "ab");
+        assert_eq!(
+            replace_env("\\${NPM_TOKEN}", &vars).unwrap(),
+            "${NPM_TOKEN}"
+        );
+        assert_eq!(replace_env("\\\\${NPM_TOKEN}", &vars).unwrap(), "\\s3cret");
+        assert!(matches!(
+            replace_env("${MISSING}", &vars),
+            Err(Error::UnsetVariable(name)) if name == "MISSING"
+        ));
+
+        let fixture = fixture(vars);
+        fixture.write("home/.npmrc", "//${HOST}/:_authToken=${NPM_TOKEN}");
+        let config = Config::load(&fixture.env).unwrap();
+        assert_eq!(
+            config.get_str("//npm.ourco.com/:_authToken"),
+            Some("s3cret")
+        );
+    }
+
+    #[test]
+    fn edits_config_files() {
+        let fixture = fixture(HashMap::new());
+        fixture.write("home/.npmrc", "; my settings\nsave-exact=true\n");
+        let mut config = Config::load(&fixture.env).unwrap();
+
+        config
+            .save(Location::User, "registry", "https://npm.ourco.com/")
+            .unwrap();
+        config.delete(Location::User, "save-exact").unwrap();
+        config
+            .save(Location::Project, "engine-strict", "true")
+            .unwrap();
+        assert_eq!(config.registry(), "https://npm.ourco.com/");
+        assert!(!config.save_exact());
+        assert!(config.engine_strict());
+
+        let read = |path: &str| std::fs::read_to_string(fixture.dir.path().join(path)).unwrap();
+        assert_eq!(
+            read("home/.npmrc"),
+            "; my settings\nregistry=https://npm.ourco.com/\n"
+        );
+        assert_eq!(read("home/project/.npmrc"), "engine-strict=true\n");
+        assert!(matches!(
+            config.save(Location::Env, "a", "b"),
+            Err(Error::NoFile(Location::Env))
+        ));
+    }
+}
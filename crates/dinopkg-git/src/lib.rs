@@ -0,0 +1,444 @@
+//! Git dependencies, like `github:user/repo#semver:^2` or `git+ssh://host/repo.git#v1.2.0`.
+//!
+//! Every repository is cloned once into a bare mirror in the cache, and fetched again
+//! when a dependency needs something that might have changed. Commits are checked out
+//! of the mirror, so that only the first install of a repository needs the whole history.
+//!
+//! This uses the `git` command, like npm does, so SSH keys, credential helpers and
+//! `insteadOf` rewrites all work the same way they do everywhere else.
+
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use dinopkg_package_json::{GitSpec, PackageJson};
+use dinopkg_semver::{Options, Range};
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("couldn't run git, is it installed? {0}")]
+    GitNotFound(io::Error),
+
+    #[error("`git {command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+
+    #[error("`{committish}` isn't a branch, tag or commit in {url}")]
+    UnknownCommittish { url: String, committish: String },
+
+    #[error("no tag in {url} matches `{range}`")]
+    NoMatchingTag { url: String, range: String },
+
+    #[error("invalid range `{0}`")]
+    InvalidRange(String),
+
+    #[error("{0} isn't cached, and the network can't be used")]
+    NotCached(String),
+
+    #[error("`{0}` isn't a full commit hash")]
+    InvalidCommit(String),
+
+    #[error("invalid package.json in {url}: {source}")]
+    InvalidPackageJson {
+        url: String,
+        source: dinopkg_package_json::Error,
+    },
+}
+
+/// A cache of bare git mirrors.
+#[derive(Debug)]
+pub struct Repositories {
+    root: PathBuf,
+    offline: bool,
+
+    /// Mirrors that have already been fetched, so each one is only fetched once. It's
+    /// held while fetching, so nothing uses a mirror that's still being cloned.
+    fetched: Mutex<HashSet<PathBuf>>,
+}
+
+impl Repositories {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            offline: false,
+            fetched: Mutex::default(),
+        }
+    }
+
+    /// `$XDG_CACHE_HOME/dino/git` on Linux, and the platform's equivalent elsewhere.
+    pub fn default_root() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("dino").join("git"))
+    }
+
+    /// Never clones or fetches, so only commits that are already cached can be used.
+    #[must_use]
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Where the mirror of `url` is (or would be) kept.
+    pub fn mirror_dir(&self, url: &str) -> PathBuf {
+        self.root.join(hex::encode(Sha256::digest(url.as_bytes())))
+    }
+
+    /// Finds the commit that `spec` points at: the newest tag matching its semver range,
+    /// its committish, or the default branch.
+    ///
+    /// # Errors
+    /// This function fails if the repository can't be cloned or fetched, or if nothing
+    /// matches the spec.
+    pub async fn resolve(&self, spec: &GitSpec) -> Result<String, Error> {
+        let url = &spec.url;
+        // Commits never change, so there's no need to fetch if we already have it.
+        if let Some(commit) = spec.committish.as_deref().filter(|c| is_commit_hash(c)) {
+            if self.has_commit(url, commit).await {
+                return Ok(commit.into());
+            }
+        }
+
+        let mirror = self.fetch(url).await?;
+        if let Some(range) = &spec.semver {
+            let loose = Options {
+                loose: true,
+                ..Default::default()
+            };
+            let parsed =
+                Range::parse_with(range, loose).map_err(|_| Error::InvalidRange(range.clone()))?;
+            let tags = git(Some(&mirror), ["tag", "--list"]).await?;
+            let tag = parsed
+                .max_satisfying(tags.lines())
+                .ok_or_else(|| Error::NoMatchingTag {
+                    url: url.clone(),
+                    range: range.clone(),
+                })?;
+            return rev_parse(&mirror, &format!("refs/tags/{tag}"))
+                .await
+                .ok_or_else(|| Error::UnknownCommittish {
+                    url: url.clone(),
+                    committish: tag.into(),
+                });
+        }
+
+        let committish = spec.committish.as_deref().unwrap_or("HEAD");
+        rev_parse(&mirror, committish)
+            .await
+            .ok_or_else(|| Error::UnknownCommittish {
+                url: url.clone(),
+                committish: committish.into(),
+            })
+    }
+
+    /// Reads `package.json` at `commit`, without checking anything out.
+    ///
+    /// # Errors
+    /// This function fails if the commit isn't available, or its `package.json` is
+    /// missing or invalid.
+    pub async fn package_json(&self, url: &str, commit: &str) -> Result<PackageJson, Error> {
+        let mirror = self.mirror_with(url, commit).await?;
+        let json = git(
+            Some(&mirror),
+            [
+                "show",
+                "--end-of-options",
+                &format!("{commit}:package.json"),
+            ],
+        )
+        .await?;
+        PackageJson::parse(&json).map_err(|source| Error::InvalidPackageJson {
+            url: url.into(),
+            source,
+        })
+    }
+
+    /// Checks out `commit` into `dest`, which shouldn't exist yet, along with any submodules.
+    ///
+    /// # Errors
+    /// This function fails if the commit isn't available, or can't be checked out.
+    pub async fn checkout(&self, url: &str, commit: &str, dest: &Path) -> Result<(), Error> {
+        let mirror = self.mirror_with(url, commit).await?;
+        git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--shared"),
+                OsStr::new("--no-checkout"),
+                OsStr::new("--"),
+                mirror.as_os_str(),
+                dest.as_os_str(),
+            ],
+        )
+        .await?;
+        // `checkout` doesn't understand `--end-of-options`, but `mirror_with` already
+        // made sure `commit` is a hash, so it can't be mistaken for an option.
+        git(Some(dest), ["checkout", "--quiet", "--detach", commit]).await?;
+        if tokio::fs::try_exists(dest.join(".gitmodules")).await? {
+            git(
+                Some(dest),
+                ["submodule", "update", "--quiet", "--init", "--recursive"],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the mirror of `url`, fetching it first if it doesn't have `commit`.
+    ///
+    /// Only full commit hashes are accepted, since anything else could point at a
+    /// different commit tomorrow.
+    async fn mirror_with(&self, url: &str, commit: &str) -> Result<PathBuf, Error> {
+        if !is_commit_hash(commit) {
+            return Err(Error::InvalidCommit(commit.into()));
+        }
+        if self.has_commit(url, commit).await {
+            Ok(self.mirror_dir(url))
+        } else {
+            self.fetch(url).await
+        }
+    }
+
+    async fn has_commit(&self, url: &str, commit: &str) -> bool {
+        let mirror = self.mirror_dir(url);
+        mirror.exists() && rev_parse(&mirror, commit).await.as_deref() == Some(commit)
+    }
+
+    /// Clones `url`, or fetches it if it's already cloned, and returns its mirror.
+    async fn fetch(&self, url: &str) -> Result<PathBuf, Error> {
+        let mirror = self.mirror_dir(url);
+        if self.offline {
+            return if tokio::fs::try_exists(&mirror).await? {
+                Ok(mirror)
+            } else {
+                Err(Error::NotCached(url.into()))
+            };
+        }
+        let mut fetched = self.fetched.lock().await;
+        if fetched.contains(&mirror) {
+            return Ok(mirror);
+        }
+
+        if tokio::fs::try_exists(&mirror).await? {
+            git(Some(&mirror), ["fetch", "--quiet", "--prune"]).await?;
+        } else {
+            self.clone_mirror(url, &mirror).await?;
+        }
+        fetched.insert(mirror.clone());
+        Ok(mirror)
+    }
+
+    async fn clone_mirror(&self, url: &str, mirror: &Path) -> Result<(), Error> {
+        // Cloned somewhere else first, so an interrupted clone never looks like a mirror.
+        tokio::fs::create_dir_all(&self.root).await?;
+        let tmp = tempfile::tempdir_in(&self.root)?;
+        let clone = tmp.path().join("mirror");
+        git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--mirror"),
+                // `url` comes from a lockfile or package.json, so it mustn't be taken as an option.
+                OsStr::new("--"),
+                OsStr::new(url),
+                clone.as_os_str(),
+            ],
+        )
+        .await?;
+        match tokio::fs::rename(&clone, mirror).await {
+            Ok(()) => Ok(()),
+            // Another dino cloned it first, which is fine.
+            Err(_) if tokio::fs::try_exists(mirror).await? => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Whether `committish` is a full commit hash, rather than a branch or tag.
+pub fn is_commit_hash(committish: &str) -> bool {
+    committish.len() == 40 && committish.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Returns the commit that `committish` points at in `repo`, if there is one.
+async fn rev_parse(repo: &Path, committish: &str) -> Option<String> {
+    git(
+        Some(repo),
+        [
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "--end-of-options",
+            &format!("{committish}^{{commit}}"),
+        ],
+    )
+    .await
+    .ok()
+}
+
+/// Runs git, in `dir` if there is one, and returns what it printed.
+async fn git<I, S>(dir: Option<&Path>, args: I) -> Result<String, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<OsString> = args.into_iter().map(|arg| arg.as_ref().into()).collect();
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(&args)
+        // Never wait for a password that nobody's going to type.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(Error::GitNotFound)?;
+    if !output.status.success() {
+        return Err(Error::Command {
+            command: args
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().into(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Runs git synchronously, for setting up fixtures.
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=dino", "-c", "user.email=dino@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap().trim().into()
+    }
+
+    /// Makes a bare repository with a commit for each version, tagged `v<version>`.
+    /// Returns its URL, and the commits in order.
+    fn bare_repo(dir: &Path, versions: &[&str]) -> (String, Vec<String>) {
+        let work = dir.join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        run(&work, &["init", "--quiet", "--initial-branch=main"]);
+        let mut commits = Vec::new();
+        for version in versions {
+            std::fs::write(
+                work.join("package.json"),
+                format!(r#"{{"name":"lib","version":"{version}"}}"#),
+            )
+            .unwrap();
+            run(&work, &["add", "."]);
+            run(&work, &["commit", "--quiet", "-m", version]);
+            run(&work, &["tag", &format!("v{version}")]);
+            commits.push(run(&work, &["rev-parse", "HEAD"]));
+        }
+        let bare = dir.join("lib.git");
+        run(
+            dir,
+            &["clone", "--quiet", "--bare", "work", bare.to_str().unwrap()],
+        );
+        (format!("file://{}", bare.display()), commits)
+    }
+
+    fn spec(url: &str, committish: Option<&str>, semver: Option<&str>) -> GitSpec {
+        GitSpec {
+            source: format!("git+{url}"),
+            url: url.into(),
+            committish: committish.map(Into::into),
+            semver: semver.map(Into::into),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_committishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, commits) = bare_repo(dir.path(), &["1.0.0", "1.1.0", "2.0.0"]);
+        let repositories = Repositories::new(dir.path().join("cache"));
+
+        let resolve = |committish, semver| {
+            let spec = spec(&url, committish, semver);
+            let repositories = &repositories;
+            async move { repositories.resolve(&spec).await }
+        };
+        assert_eq!(resolve(None, None).await.unwrap(), commits[2]);
+        assert_eq!(resolve(Some("v1.0.0"), None).await.unwrap(), commits[0]);
+        assert_eq!(resolve(Some("main"), None).await.unwrap(), commits[2]);
+        assert_eq!(resolve(Some(&commits[1]), None).await.unwrap(), commits[1]);
+        assert_eq!(resolve(None, Some("^1")).await.unwrap(), commits[1]);
+        assert!(matches!(
+            resolve(None, Some("^3")).await,
+            Err(Error::NoMatchingTag { .. })
+        ));
+        assert!(matches!(
+            resolve(Some("nope"), None).await,
+            Err(Error::UnknownCommittish { .. })
+        ));
+
+        let package_json = repositories.package_json(&url, &commits[0]).await.unwrap();
+        assert_eq!(package_json.version, "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn checks_out_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, commits) = bare_repo(dir.path(), &["1.0.0", "1.1.0"]);
+        let repositories = Repositories::new(dir.path().join("cache"));
+
+        let dest = dir.path().join("checkout");
+        repositories
+            .checkout(&url, &commits[0], &dest)
+            .await
+            .unwrap();
+        let package_json = std::fs::read_to_string(dest.join("package.json")).unwrap();
+        assert!(package_json.contains("1.0.0"));
+
+        assert!(matches!(
+            repositories
+                .checkout(&url, "main", &dir.path().join("branch"))
+                .await,
+            Err(Error::InvalidCommit(commit)) if commit == "main"
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_uses_the_cache_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, commits) = bare_repo(dir.path(), &["1.0.0"]);
+        let cache = dir.path().join("cache");
+
+        let offline = Repositories::new(&cache).with_offline(true);
+        assert!(matches!(
+            offline.resolve(&spec(&url, None, None)).await,
+            Err(Error::NotCached(_))
+        ));
+
+        Repositories::new(&cache)
+            .resolve(&spec(&url, None, None))
+            .await
+            .unwrap();
+        assert_eq!(
+            offline.resolve(&spec(&url, None, None)).await.unwrap(),
+            commits[0]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Installing git dependencies.
+//!
+//! A git dependency is checked out, built with its `prepare` script if it has one,
+//! and then [packed](crate::pack), so it ends up with the same files it would have
+//! if it had been published.
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+use dinopkg_git::Repositories;
+use dinopkg_package_json::PackageJson;
+use dinopkg_resolver::{ResolvedPackage, Source};
+
+use crate::{extract, pack, Error, Fetcher};
+
+/// Builds a checked out git dependency before it's packed.
+pub trait Prepare {
+    /// Installs the dependencies of `package`, checked out in `dir`, and runs its
+    /// `prepare` script.
+    fn prepare(
+        &self,
+        package: &ResolvedPackage,
+        dir: &Path,
+        package_json: &PackageJson,
+    ) -> impl Future<Output = Result<(), Error>>;
+}
+
+/// A [`Fetcher`] that installs git dependencies from `repositories`, and passes
+/// everything else on to another fetcher.
+pub struct GitFetcher<'a, F, P> {
+    fetcher: F,
+    repositories: &'a Repositories,
+    prepare: P,
+}
+
+impl<'a, F, P> GitFetcher<'a, F, P> {
+    pub fn new(fetcher: F, repositories: &'a Repositories, prepare: P) -> Self {
+        Self {
+            fetcher,
+            repositories,
+            prepare,
+        }
+    }
+}
+
+impl<F: Fetcher, P: Prepare> Fetcher for GitFetcher<'_, F, P> {
+    async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+        let Source::Git { url, commit } = &package.source else {
+            return self.fetcher.fetch(package, dest).await;
+        };
+
+        let tmp = tempfile::tempdir()?;
+        let checkout = tmp.path().join("package");
+        self.repositories.checkout(url, commit, &checkout).await?;
+        let package_json =
+            PackageJson::parse(&tokio::fs::read_to_string(checkout.join("package.json")).await?)?;
+        if needs_prepare(&package_json) {
+            self.prepare
+                .prepare(package, &checkout, &package_json)
+                .await?;
+        }
+
+        let tarball = tokio::task::spawn_blocking(move || pack::pack(&checkout))
+            .await
+            .map_err(io::Error::other)??;
+        extract(tarball, dest).await
+    }
+}
+
+/// Whether a git dependency has to be built before it can be used, which is what
+/// `prepare` scripts are for.
+fn needs_prepare(package_json: &PackageJson) -> bool {
+    package_json
+        .scripts
+        .as_ref()
+        .is_some_and(|scripts| scripts.contains_key("prepare"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+
+    use super::*;
+    use dinopkg_resolver::PackageId;
+    use pretty_assertions::assert_eq;
+
+    /// Builds packages by writing `dist/index.js`, instead of running anything.
+    struct FakePrepare;
+
+    impl Prepare for FakePrepare {
+        async fn prepare(
+            &self,
+            _package: &ResolvedPackage,
+            dir: &Path,
+            _package_json: &PackageJson,
+        ) -> Result<(), Error> {
+            tokio::fs::create_dir_all(dir.join("dist")).await?;
+            tokio::fs::write(dir.join("dist/index.js"), "module.exports = 42").await?;
+            Ok(())
+        }
+    }
+
+    /// Fails for anything that isn't from git.
+    struct NoFetcher;
+
+    impl Fetcher for NoFetcher {
+        async fn fetch(&self, package: &ResolvedPackage, _dest: &Path) -> Result<(), Error> {
+            panic!("{} should have come from git", package.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn prepares_and_packs_git_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = dir.path().join("work");
+        fs::create_dir_all(work.join("src")).unwrap();
+        fs::write(
+            work.join("package.json"),
+            r#"{"name":"lib","version":"1.0.0","files":["dist"],"scripts":{"prepare":"build"}}"#,
+        )
+        .unwrap();
+        fs::write(work.join("src/index.ts"), "export default 42").unwrap();
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=dino", "-c", "user.email=dino@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?} failed");
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+        git(&["init", "--quiet", "work"]);
+        git(&["-C", "work", "add", "."]);
+        git(&["-C", "work", "commit", "--quiet", "-m", "1.0.0"]);
+        git(&["clone", "--quiet", "--bare", "work", "lib.git"]);
+        let commit = git(&["-C", "lib.git", "rev-parse", "HEAD"]);
+
+        let repositories = Repositories::new(dir.path().join("cache"));
+        let fetcher = GitFetcher::new(NoFetcher, &repositories, FakePrepare);
+        let source = Source::Git {
+            url: format!("file://{}", dir.path().join("lib.git").display()),
+            commit,
+        };
+        let package = ResolvedPackage {
+            id: PackageId {
+                name: "lib".into(),
+                version: "1.0.0".into(),
+                source: source.resolved(),
+            },
+            source,
+            dist: Default::default(),
+            manifest: Default::default(),
+            dependencies: Default::default(),
+            dev: false,
+            optional: false,
+            peer: false,
+        };
+        let dest = dir.path().join("node_modules/lib");
+        fs::create_dir_all(&dest).unwrap();
+        fetcher.fetch(&package, &dest).await.unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dest.join("dist/index.js")).unwrap(),
+            "module.exports = 42"
+        );
+        assert!(dest.join("package.json").exists());
+        assert!(!dest.join("src").exists());
+    }
+}
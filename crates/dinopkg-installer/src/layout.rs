@@ -0,0 +1,479 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
+
+use dinopkg_npm_registry::{Integrity, Manifest};
+use dinopkg_package_json::BinVariant;
+use dinopkg_resolver::{PackageId, Resolution, Source};
+
+/// The directory isolated installs keep the real package directories in.
+pub const ISOLATED_STORE_DIR: &str = ".dino";
+
+/// Where each package goes on disk, relative to the project root.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Layout {
+    /// Directories to extract packages into, such as `node_modules/a/node_modules/b`.
+    pub packages: BTreeMap<PathBuf, PackageId>,
+
+    /// Symlinks to create. The key is where the link goes, and the value is its
+    /// target, relative to the link's parent directory.
+    pub links: BTreeMap<PathBuf, PathBuf>,
+}
+
+/// A package's position in a hoisted tree, as the list of `node_modules` it's nested
+/// in. `["a", "b"]` is `node_modules/a/node_modules/b`, and the root is `[]`.
+type NodePath = Vec<String>;
+
+/// Lays packages out like npm does: everything is hoisted as close to the root
+/// as possible, and packages are only nested when versions conflict.
+///
+/// Node resolves `require("x")` by walking up the directory tree looking for
+/// `node_modules/x`, so every package ends up seeing exactly the version it
+/// resolved to.
+///
+/// Links (like workspaces) are symlinked, so anything nested under one goes in
+/// the `node_modules` of the directory it points at.
+pub fn hoisted(resolution: &Resolution) -> Layout {
+    let mut nodes: BTreeMap<NodePath, PackageId> = BTreeMap::new();
+    let mut processed = BTreeSet::new();
+    let mut queue = VecDeque::new();
+
+    for (name, id) in resolution
+        .dependencies
+        .iter()
+        .chain(&resolution.dev_dependencies)
+    {
+        let path = vec![name.clone()];
+        nodes.insert(path.clone(), id.clone());
+        queue.push_back(path);
+    }
+
+    // Breadth-first, so shallower packages get first dibs on the root.
+    while let Some(path) = queue.pop_front() {
+        let package = &resolution.packages[&nodes[&path]];
+        for (name, id) in &package.dependencies {
+            if let Some(location) = place(&nodes, &processed, resolution, &path, name, id) {
+                let mut placed = location;
+                placed.push(name.clone());
+                nodes.insert(placed.clone(), id.clone());
+                queue.push_back(placed);
+            }
+        }
+        processed.insert(path);
+    }
+
+    Layout {
+        packages: nodes
+            .iter()
+            .map(|(path, id)| (install_path(&nodes, resolution, path), id.clone()))
+            .collect(),
+        links: BTreeMap::new(),
+    }
+}
+
+/// Finds the shallowest `node_modules` that a dependency of the package at
+/// `from` can go into, or `None` if a suitable copy is already reachable.
+fn place(
+    nodes: &BTreeMap<NodePath, PackageId>,
+    processed: &BTreeSet<NodePath>,
+    resolution: &Resolution,
+    from: &NodePath,
+    name: &str,
+    id: &PackageId,
+) -> Option<NodePath> {
+    let mut candidate = from.clone();
+    let mut location = from.clone();
+    loop {
+        let mut at = location.clone();
+        at.push(name.into());
+        match nodes.get(&at) {
+            Some(existing) if existing == id => return None,
+            // Anything above this would be shadowed by the conflicting version.
+            Some(_) => break,
+            None if shadows(nodes, processed, resolution, &location, name, id) => break,
+            None => candidate.clone_from(&location),
+        }
+        if location.pop().is_none() {
+            break;
+        }
+    }
+    Some(candidate)
+}
+
+/// Returns whether putting `name@id` in `location`'s `node_modules` would change
+/// what a package there (or below) already resolved `name` to.
+///
+/// Packages whose dependencies haven't been placed yet don't count, since they'll
+/// pick up whatever ends up closest to them.
+fn shadows(
+    nodes: &BTreeMap<NodePath, PackageId>,
+    processed: &BTreeSet<NodePath>,
+    resolution: &Resolution,
+    location: &NodePath,
+    name: &str,
+    id: &PackageId,
+) -> bool {
+    nodes
+        .iter()
+        .filter(|(path, _)| path.starts_with(location) && processed.contains(*path))
+        .any(|(path, package)| {
+            let wanted = resolution.packages[package].dependencies.get(name);
+            if wanted.is_none_or(|wanted| wanted == id) {
+                return false;
+            }
+            // It'd only be shadowed if it currently resolves from above `location`.
+            match resolve_from(nodes, path, name) {
+                Some(found_at) => found_at.len() < location.len(),
+                None => false,
+            }
+        })
+}
+
+/// Finds the `node_modules` that Node would load `name` from, starting at `path`.
+fn resolve_from(
+    nodes: &BTreeMap<NodePath, PackageId>,
+    path: &NodePath,
+    name: &str,
+) -> Option<NodePath> {
+    let mut location = path.clone();
+    loop {
+        let mut at = location.clone();
+        at.push(name.into());
+        if nodes.contains_key(&at) {
+            return Some(location);
+        }
+        location.pop()?;
+    }
+}
+
+/// Where the package at `path` goes on disk, following links.
+fn install_path(
+    nodes: &BTreeMap<NodePath, PackageId>,
+    resolution: &Resolution,
+    path: &NodePath,
+) -> PathBuf {
+    let mut buf = PathBuf::new();
+    for (depth, name) in path.iter().enumerate() {
+        debug_assert!(is_package_dir(name), "`{name}` isn't a package name");
+        buf.push("node_modules");
+        buf.push(name);
+        if depth + 1 == path.len() {
+            break;
+        }
+        let id = &nodes[&path[..=depth]];
+        if let Source::Link { path: target } = &resolution.packages[id].source {
+            buf = PathBuf::from(target);
+        }
+    }
+    buf
+}
+
+/// Lays packages out so that each one can only see its own dependencies.
+///
+/// Every package lives at `node_modules/.dino/<name>@<version>/node_modules/<name>`,
+/// next to symlinks to its dependencies. Only the root's direct dependencies are
+/// linked into the top-level `node_modules`, so undeclared dependencies can't be
+/// `require`d by accident.
+pub fn isolated(resolution: &Resolution) -> Layout {
+    let mut layout = Layout::default();
+
+    for (id, package) in &resolution.packages {
+        debug_assert!(
+            is_package_dir(&id.name),
+            "`{}` isn't a package name",
+            id.name
+        );
+        let store_dir = isolated_store_dir(id);
+        layout.packages.insert(store_dir.join(&id.name), id.clone());
+
+        // A linked package only sees what's in its own directory's `node_modules`.
+        let dependencies_dir = match &package.source {
+            Source::Link { path } => Path::new(path).join("node_modules"),
+            _ => store_dir,
+        };
+        for (name, dependency) in &package.dependencies {
+            if name == &id.name {
+                // A package that depends on itself already sees itself.
+                continue;
+            }
+            debug_assert!(is_package_dir(name), "`{name}` isn't a package name");
+            let link = dependencies_dir.join(name);
+            let target = isolated_store_dir(dependency).join(&dependency.name);
+            layout
+                .links
+                .insert(link.clone(), relative_link(&link, &target));
+        }
+    }
+
+    for (name, id) in resolution
+        .dependencies
+        .iter()
+        .chain(&resolution.dev_dependencies)
+    {
+        debug_assert!(is_package_dir(name), "`{name}` isn't a package name");
+        let link = Path::new("node_modules").join(name);
+        let target = isolated_store_dir(id).join(&id.name);
+        layout
+            .links
+            .insert(link.clone(), relative_link(&link, &target));
+    }
+
+    layout
+}
+
+/// The `node_modules` directory that `id` and its dependencies live in, in an isolated install.
+///
+/// Packages that don't come from the registry can share a version with ones that do, so
+/// they're told apart by a hash of where they come from.
+fn isolated_store_dir(id: &PackageId) -> PathBuf {
+    let mut dir_name = format!("{}@{}", id.name.replace('/', "+"), id.version);
+    if let Some(source) = &id.source {
+        let hash = Integrity::sha512(source.as_bytes()).to_hex();
+        dir_name = format!("{dir_name}_{}", &hash[..16]);
+    }
+    Path::new("node_modules")
+        .join(ISOLATED_STORE_DIR)
+        .join(dir_name)
+        .join("node_modules")
+}
+
+/// Works out which executables to link into `.bin` directories, from each package's `bin`.
+///
+/// A package's executables go in the `.bin` of the `node_modules` it's installed (or
+/// linked) into, so that whatever can `require` the package can run them too. Like
+/// [`Layout::links`], the key is where the link goes, and the value is its target.
+pub fn bins(resolution: &Resolution, layout: &Layout) -> BTreeMap<PathBuf, PathBuf> {
+    let store = Path::new("node_modules").join(ISOLATED_STORE_DIR);
+    // Packages in an isolated store are only visible through the links to them.
+    let packages = layout
+        .packages
+        .iter()
+        .filter(|(path, _)| !path.starts_with(&store))
+        .map(|(path, id)| (path.clone(), id));
+    let links = layout.links.iter().filter_map(|(link, target)| {
+        let target = normalize(&link.parent()?.join(target));
+        Some((link.clone(), layout.packages.get(&target)?))
+    });
+
+    let mut bins = BTreeMap::new();
+    for (path, id) in packages.chain(links) {
+        let Some(bin_dir) = bin_dir(&path) else {
+            continue;
+        };
+        for (name, target) in executables(&resolution.packages[id].manifest) {
+            let link = bin_dir.join(name);
+            let target = relative_link(&link, &path.join(target));
+            bins.entry(link).or_insert(target);
+        }
+    }
+    bins
+}
+
+/// A package's executables, as their names and where they are in the package.
+///
+/// Names that aren't plain file names, and paths that lead out of the package, are
+/// left out, so a package can't put links (or change permissions) anywhere else.
+fn executables(manifest: &Manifest) -> Vec<(&str, PathBuf)> {
+    let entries: Vec<(&str, &str)> = match &manifest.bin {
+        Some(BinVariant::String(path)) => {
+            let name = manifest.name.rsplit('/').next().unwrap_or_default();
+            vec![(name, path)]
+        }
+        Some(BinVariant::Map(bins)) => bins
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+            .collect(),
+        None => Vec::new(),
+    };
+    entries
+        .into_iter()
+        .filter(|(name, _)| !matches!(*name, "" | "." | "..") && !name.contains(['/', '\\']))
+        .filter_map(|(name, path)| {
+            let mut inside = PathBuf::new();
+            for component in Path::new(path).components() {
+                match component {
+                    Component::Normal(part) => inside.push(part),
+                    Component::CurDir => {}
+                    _ => return None,
+                }
+            }
+            (!inside.as_os_str().is_empty()).then_some((name, inside))
+        })
+        .collect()
+}
+
+/// Whether `name` can be joined onto a `node_modules` directory without leading out of
+/// it, like `a` or `@scope/a`. The resolver only allows valid package names, which are.
+fn is_package_dir(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('/').collect();
+    let normal = |part: &str| !matches!(part, "" | "." | "..") && !part.contains('\\');
+    match parts[..] {
+        [name] => normal(name),
+        [scope, name] => scope.starts_with('@') && normal(scope) && normal(name),
+        _ => false,
+    }
+}
+
+/// The `.bin` directory next to the package at `path`, in the `node_modules` it's in.
+fn bin_dir(path: &Path) -> Option<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    let i = components
+        .iter()
+        .rposition(|component| component.as_os_str() == "node_modules")?;
+    Some(components[..=i].iter().collect::<PathBuf>().join(".bin"))
+}
+
+/// Removes `.` and `..` from `path`, without touching the filesystem.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Computes the path from `link`'s parent directory to `target`.
+pub(crate) fn relative_link(link: &Path, target: &Path) -> PathBuf {
+    let base: Vec<Component> = link
+        .parent()
+        .unwrap_or(Path::new(""))
+        .components()
+        .collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dinopkg_npm_registry::Dist;
+    use dinopkg_resolver::{ResolvedPackage, Source};
+    use pretty_assertions::assert_eq;
+
+    fn id(name: &str, version: &str) -> PackageId {
+        PackageId {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a resolution from `(package, [(dependency, version)])` pairs.
+    /// The first entry's dependencies are the root's.
+    fn resolution(packages: &[(&str, &[(&str, &str)])]) -> Resolution {
+        let dependencies = |deps: &[(&str, &str)]| -> BTreeMap<String, PackageId> {
+            deps.iter()
+                .map(|(name, version)| ((*name).into(), id(name, version)))
+                .collect()
+        };
+        let mut resolution = Resolution {
+            dependencies: dependencies(packages[0].1),
+            ..Default::default()
+        };
+        for (package, deps) in &packages[1..] {
+            let (name, version) = package.split_once('@').unwrap();
+            resolution.packages.insert(
+                id(name, version),
+                ResolvedPackage {
+                    id: id(name, version),
+                    source: Source::Registry,
+                    dist: Dist::default(),
+                    manifest: Default::default(),
+                    dependencies: dependencies(deps),
+                    dev: false,
+                    optional: false,
+                    peer: false,
+                },
+            );
+        }
+        resolution
+    }
+
+    fn paths(layout: &Layout) -> Vec<(String, String)> {
+        layout
+            .packages
+            .iter()
+            .map(|(path, id)| (path.display().to_string(), id.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn avoids_shadowing_already_placed_packages() {
+        // `b@2` gets nested under `a` and resolves `x@1` from the root. When
+        // `c@1` (also nested under `a`) needs `x@2`, putting it in
+        // `a/node_modules` would break `b`, so it has to go under `c`.
+        let resolution = resolution(&[
+            (
+                "root",
+                &[
+                    ("x", "1.0.0"),
+                    ("a", "1.0.0"),
+                    ("b", "1.0.0"),
+                    ("c", "9.0.0"),
+                ],
+            ),
+            ("x@1.0.0", &[]),
+            ("x@2.0.0", &[]),
+            ("a@1.0.0", &[("b", "2.0.0"), ("c", "1.0.0")]),
+            ("b@1.0.0", &[]),
+            ("b@2.0.0", &[("x", "1.0.0")]),
+            ("c@1.0.0", &[("x", "2.0.0")]),
+            ("c@9.0.0", &[]),
+        ]);
+        let layout = hoisted(&resolution);
+        assert!(layout
+            .packages
+            .contains_key(Path::new("node_modules/a/node_modules/c/node_modules/x")));
+        assert!(!layout
+            .packages
+            .contains_key(Path::new("node_modules/a/node_modules/x")));
+    }
+
+    #[test]
+    fn handles_cycles() {
+        let resolution = resolution(&[
+            ("root", &[("a", "1.0.0")]),
+            ("a@1.0.0", &[("b", "1.0.0")]),
+            ("b@1.0.0", &[("a", "1.0.0")]),
+        ]);
+        assert_eq!(
+            paths(&hoisted(&resolution)),
+            [
+                ("node_modules/a".into(), "a@1.0.0".into()),
+                ("node_modules/b".into(), "b@1.0.0".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_links() {
+        assert_eq!(
+            relative_link(
+                Path::new("node_modules/.dino/a@1.0.0/node_modules/b"),
+                Path::new("node_modules/.dino/b@1.0.0/node_modules/b")
+            ),
+            Path::new("../../b@1.0.0/node_modules/b")
+        );
+        assert_eq!(
+            relative_link(
+                Path::new("node_modules/@scope/a"),
+                Path::new("node_modules/.dino/@scope+a@1.0.0/node_modules/@scope/a")
+            ),
+            Path::new("../.dino/@scope+a@1.0.0/node_modules/@scope/a")
+        );
+    }
+}
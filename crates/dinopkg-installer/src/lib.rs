@@ -0,0 +1,695 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dinopkg_npm_registry::{download_tarball, extract_tarball, CacheStore, RegistryClient};
+use dinopkg_resolver::{Resolution, ResolvedPackage, Source};
+use futures_util::{StreamExt, TryStreamExt};
+
+pub mod git;
+pub mod layout;
+pub mod lifecycle;
+pub mod local;
+pub mod pack;
+pub mod store;
+pub use git::{GitFetcher, Prepare};
+pub use layout::Layout;
+pub use lifecycle::{Policy, RunScript};
+pub use local::LocalFetcher;
+pub use store::Store;
+
+/// How many packages to download and extract at once.
+const CONCURRENCY: usize = 16;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("registry error: {0}")]
+    Registry(#[from] dinopkg_npm_registry::Error),
+
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("git error: {0}")]
+    Git(#[from] dinopkg_git::Error),
+
+    #[error("invalid package.json: {0}")]
+    PackageJson(#[from] dinopkg_package_json::Error),
+
+    #[error("invalid pattern `{0}` in `files`")]
+    InvalidFilesPattern(String),
+
+    /// A git dependency's `prepare` script, or installing what it needs, failed.
+    #[error("couldn't prepare `{name}`: {message}")]
+    Prepare { name: String, message: String },
+
+    /// One of a package's lifecycle scripts, like `postinstall`, failed.
+    #[error("`{name}` couldn't run its `{event}` script: {message}")]
+    Script {
+        name: String,
+        event: String,
+        message: String,
+    },
+}
+
+/// How packages are arranged inside `node_modules`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// npm's layout: hoist everything as far up as possible, and nest copies when
+    /// versions conflict. See [`layout::hoisted`].
+    #[default]
+    Hoisted,
+
+    /// A strict layout where packages can only see what they depend on. See [`layout::isolated`].
+    Isolated,
+}
+
+/// Puts a package's contents into a directory.
+pub trait Fetcher {
+    fn fetch(
+        &self,
+        package: &ResolvedPackage,
+        dest: &Path,
+    ) -> impl Future<Output = Result<(), Error>>;
+}
+
+impl Fetcher for reqwest::Client {
+    async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+        let data = download_tarball(&package.dist, self).await?;
+        extract(data, dest).await
+    }
+}
+
+/// Downloads tarballs with the registry's credentials, for private packages.
+impl<S: CacheStore> Fetcher for &RegistryClient<S> {
+    async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+        let data = self.download_tarball(&package.dist).await?;
+        extract(data, dest).await
+    }
+}
+
+async fn extract(data: Vec<u8>, dest: &Path) -> Result<(), Error> {
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || extract_tarball(&data, &dest))
+        .await
+        .map_err(io::Error::other)??;
+    Ok(())
+}
+
+impl Strategy {
+    /// Works out where every package in `resolution` goes.
+    pub fn layout(self, resolution: &Resolution) -> Layout {
+        match self {
+            Self::Hoisted => layout::hoisted(resolution),
+            Self::Isolated => layout::isolated(resolution),
+        }
+    }
+}
+
+/// Writes every package in `layout` into `root/node_modules`. Packages from
+/// [`Source::Link`] are symlinked rather than fetched. Their executables are then
+/// linked into `.bin` directories (see [`layout::bins`]).
+///
+/// Everything is written into staging directories next to each `node_modules` first,
+/// which replace them once the whole install has succeeded. That way packages that
+/// are no longer needed don't stick around, and a failed install leaves the existing
+/// `node_modules` as it was. That includes the `node_modules` of workspaces that have
+/// packages nested in them.
+///
+/// # Errors
+/// This function fails if a package can't be fetched, or if `node_modules` can't be written to.
+pub async fn install<F: Fetcher>(
+    resolution: &Resolution,
+    layout: &Layout,
+    root: &Path,
+    fetcher: &F,
+) -> Result<(), Error> {
+    let mut node_modules = BTreeSet::from([PathBuf::from("node_modules")]);
+    for path in layout.packages.keys().chain(layout.links.keys()) {
+        node_modules.extend(node_modules_dir(path));
+    }
+    for dir in &node_modules {
+        remove_dir(&root.join(staged(dir))).await?;
+        tokio::fs::create_dir_all(root.join(staged(dir))).await?;
+    }
+
+    let result = write_staged(resolution, layout, root, fetcher).await;
+    if let Err(err) = result {
+        for dir in &node_modules {
+            remove_dir(&root.join(staged(dir))).await?;
+        }
+        return Err(err);
+    }
+
+    for dir in &node_modules {
+        let (dir, staging) = (root.join(dir), root.join(staged(dir)));
+        let old = staging.with_file_name(".node_modules.old");
+        remove_dir(&old).await?;
+        match tokio::fs::rename(&dir, &old).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        tokio::fs::rename(&staging, &dir).await?;
+        remove_dir(&old).await?;
+    }
+    Ok(())
+}
+
+/// Writes everything [`install`] does, but into the staging directories.
+async fn write_staged<F: Fetcher>(
+    resolution: &Resolution,
+    layout: &Layout,
+    root: &Path,
+    fetcher: &F,
+) -> Result<(), Error> {
+    futures_util::stream::iter(&layout.packages)
+        .map(|(path, id)| {
+            let dest = root.join(staged(path));
+            let package = &resolution.packages[id];
+            async move {
+                if let Source::Link { path: target } = &package.source {
+                    if let Some(parent) = dest.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
+                    return symlink_dir(&layout::relative_link(path, Path::new(target)), &dest)
+                        .await
+                        .map_err(Error::from);
+                }
+                tokio::fs::create_dir_all(&dest).await?;
+                fetcher.fetch(package, &dest).await
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .try_collect::<()>()
+        .await?;
+
+    for (link, target) in &layout.links {
+        let link = root.join(staged(link));
+        if let Some(parent) = link.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        symlink_dir(target, &link).await?;
+    }
+
+    for (link, target) in layout::bins(resolution, layout) {
+        let Some(dir) = link.parent() else {
+            continue;
+        };
+        let executable = root.join(staged(&layout::normalize(&dir.join(&target))));
+        // Like npm, executables that aren't actually in the package are skipped.
+        if !tokio::fs::try_exists(&executable).await? {
+            continue;
+        }
+        tokio::fs::create_dir_all(root.join(staged(dir))).await?;
+        link_bin(&executable, &target, &root.join(staged(&link))).await?;
+    }
+
+    Ok(())
+}
+
+/// Where `path` is written to before it's swapped in: its `node_modules` directory is
+/// replaced with a staging directory next to it. Since that's at the same depth,
+/// relative links work the same from either one.
+fn staged(path: &Path) -> PathBuf {
+    let mut staged = PathBuf::new();
+    let mut components = path.components();
+    for component in components.by_ref() {
+        if component.as_os_str() == "node_modules" {
+            staged.push(".node_modules.staging");
+            break;
+        }
+        staged.push(component);
+    }
+    staged.extend(components);
+    staged
+}
+
+/// Removes `dir` and everything in it, if it's there.
+async fn remove_dir(dir: &Path) -> io::Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// The `node_modules` directory that `path` is inside of, like `packages/a/node_modules`
+/// for `packages/a/node_modules/b/node_modules/c`.
+fn node_modules_dir(path: &Path) -> Option<PathBuf> {
+    let mut dir = PathBuf::new();
+    for component in path.components() {
+        dir.push(component);
+        if component.as_os_str() == "node_modules" {
+            return Some(dir);
+        }
+    }
+    None
+}
+
+#[cfg(unix)]
+async fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    tokio::fs::symlink(target, link).await
+}
+
+#[cfg(windows)]
+async fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    tokio::fs::symlink_dir(target, link).await
+}
+
+/// Links `executable` (which `target` points at), making sure it can be run.
+#[cfg(unix)]
+async fn link_bin(executable: &Path, target: &Path, link: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = tokio::fs::metadata(executable).await?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    tokio::fs::set_permissions(executable, permissions).await?;
+    tokio::fs::symlink(target, link).await
+}
+
+/// Windows can't run scripts through links, so executables get `.cmd` shims instead.
+#[cfg(windows)]
+async fn link_bin(_executable: &Path, target: &Path, link: &Path) -> io::Result<()> {
+    let target = target.to_string_lossy().replace('/', "\\");
+    let mut shim = link.as_os_str().to_owned();
+    shim.push(".cmd");
+    tokio::fs::write(shim, format!("@node \"%~dp0\\{target}\" %*\r\n")).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use super::*;
+    use dinopkg_npm_registry::Packument;
+    use dinopkg_package_json::{BinVariant, PackageJson};
+    use dinopkg_resolver::testing::packument;
+    use dinopkg_resolver::MemoryRegistry;
+    use pretty_assertions::assert_eq;
+
+    /// Writes a `package.json` with the package's name and version (and an empty file for
+    /// each of its executables), instead of downloading anything.
+    struct FixtureFetcher;
+
+    impl Fetcher for FixtureFetcher {
+        async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+            let contents = format!(
+                r#"{{"name":"{}","version":"{}"}}"#,
+                package.id.name, package.id.version
+            );
+            tokio::fs::write(dest.join("package.json"), contents).await?;
+            let bins = match &package.manifest.bin {
+                Some(BinVariant::String(path)) => vec![path],
+                Some(BinVariant::Map(bins)) => bins.values().collect(),
+                None => Vec::new(),
+            };
+            for path in bins {
+                tokio::fs::write(dest.join(path), "").await?;
+            }
+            Ok(())
+        }
+    }
+
+    async fn install_fixture(
+        registry: MemoryRegistry,
+        dependencies: &[(&str, &str)],
+        strategy: Strategy,
+    ) -> tempfile::TempDir {
+        let package_json = PackageJson {
+            name: "root".into(),
+            version: "1.0.0".into(),
+            dependencies: Some(
+                dependencies
+                    .iter()
+                    .map(|(name, range)| ((*name).into(), (*range).into()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let resolution = dinopkg_resolver::resolve(&registry, &package_json, dir.path())
+            .await
+            .unwrap();
+        let layout = strategy.layout(&resolution);
+        install(&resolution, &layout, dir.path(), &FixtureFetcher)
+            .await
+            .unwrap();
+        dir
+    }
+
+    /// Maps every installed package's directory (relative to `root`) to its version.
+    /// Symlinks aren't followed.
+    fn tree(root: &Path) -> BTreeMap<String, String> {
+        fn walk(root: &Path, dir: &Path, tree: &mut BTreeMap<String, String>) {
+            let manifest = dir.join("package.json");
+            if manifest.exists() {
+                let package = PackageJson::parse(&fs::read_to_string(manifest).unwrap()).unwrap();
+                let path = dir.strip_prefix(root).unwrap();
+                tree.insert(path.display().to_string(), package.version);
+            }
+            for entry in fs::read_dir(dir).unwrap() {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_dir() {
+                    walk(root, &entry.path(), tree);
+                }
+            }
+        }
+        let mut tree = BTreeMap::new();
+        walk(root, &root.join("node_modules"), &mut tree);
+        tree
+    }
+
+    fn expected(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(path, version)| ((*path).into(), (*version).into()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn hoists_shared_dependencies() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("c", "^1.0.0")])]));
+        registry.insert(packument("b", &[("1.0.0", &[("c", "^1.0.0")])]));
+        registry.insert(packument("c", &[("1.0.0", &[])]));
+
+        let dir = install_fixture(
+            registry,
+            &[("a", "^1.0.0"), ("b", "^1.0.0")],
+            Strategy::Hoisted,
+        )
+        .await;
+        assert_eq!(
+            tree(dir.path()),
+            expected(&[
+                ("node_modules/a", "1.0.0"),
+                ("node_modules/b", "1.0.0"),
+                ("node_modules/c", "1.0.0"),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn nests_conflicting_versions() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument(
+            "a",
+            &[("1.0.0", &[("c", "^1.0.0"), ("@scope/d", "1")])],
+        ));
+        registry.insert(packument("b", &[("1.0.0", &[("c", "^1.0.0")])]));
+        registry.insert(packument("c", &[("1.0.0", &[]), ("2.0.0", &[])]));
+        registry.insert(packument("@scope/d", &[("1.0.0", &[])]));
+
+        let dir = install_fixture(
+            registry,
+            &[("a", "^1.0.0"), ("b", "^1.0.0"), ("c", "^2.0.0")],
+            Strategy::Hoisted,
+        )
+        .await;
+        assert_eq!(
+            tree(dir.path()),
+            expected(&[
+                ("node_modules/@scope/d", "1.0.0"),
+                ("node_modules/a", "1.0.0"),
+                ("node_modules/a/node_modules/c", "1.0.0"),
+                ("node_modules/b", "1.0.0"),
+                ("node_modules/b/node_modules/c", "1.0.0"),
+                ("node_modules/c", "2.0.0"),
+            ])
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_executables() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let with_bin = |mut packument: Packument, bin: BinVariant| {
+            for manifest in packument.versions.values_mut() {
+                manifest.bin = Some(bin.clone());
+            }
+            packument
+        };
+        let registry = || {
+            let mut registry = MemoryRegistry::new();
+            registry.insert(with_bin(
+                packument("a", &[("1.0.0", &[("c", "^1.0.0")])]),
+                BinVariant::Map(
+                    [("a", "./cli.js"), ("escape", "../../outside.js")]
+                        .into_iter()
+                        .map(|(name, path)| (name.into(), path.into()))
+                        .collect(),
+                ),
+            ));
+            registry.insert(packument("b", &[("1.0.0", &[("c", "^1.0.0")])]));
+            registry.insert(with_bin(
+                packument("c", &[("1.0.0", &[]), ("2.0.0", &[])]),
+                BinVariant::String("c.js".into()),
+            ));
+            registry
+        };
+        let dependencies = [("a", "^1.0.0"), ("b", "^1.0.0"), ("c", "^2.0.0")];
+
+        let dir = install_fixture(registry(), &dependencies, Strategy::Hoisted).await;
+        let node_modules = dir.path().join("node_modules");
+        for (link, target) in [
+            (".bin/a", "../a/cli.js"),
+            (".bin/c", "../c/c.js"),
+            ("a/node_modules/.bin/c", "../c/c.js"),
+            ("b/node_modules/.bin/c", "../c/c.js"),
+        ] {
+            assert_eq!(
+                fs::read_link(node_modules.join(link)).unwrap(),
+                Path::new(target)
+            );
+        }
+        assert!(!node_modules.join(".bin/escape").exists());
+        let mode = fs::metadata(node_modules.join(".bin/a"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+
+        let dir = install_fixture(registry(), &dependencies, Strategy::Isolated).await;
+        let node_modules = dir.path().join("node_modules");
+        for (link, target) in [
+            (".bin/a", "../a/cli.js"),
+            (".bin/c", "../c/c.js"),
+            (".dino/a@1.0.0/node_modules/.bin/c", "../c/c.js"),
+        ] {
+            assert_eq!(
+                fs::read_link(node_modules.join(link)).unwrap(),
+                Path::new(target)
+            );
+            assert!(node_modules.join(link).exists(), "{link}");
+        }
+    }
+
+    #[tokio::test]
+    async fn replaces_existing_node_modules() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[])]));
+        let dir = install_fixture(registry, &[("a", "1.0.0")], Strategy::Hoisted).await;
+
+        fs::create_dir_all(dir.path().join("node_modules/stale")).unwrap();
+        fs::write(dir.path().join("node_modules/stale/package.json"), "{}").unwrap();
+        let resolution = dinopkg_resolver::Resolution::default();
+        install(&resolution, &Layout::default(), dir.path(), &FixtureFetcher)
+            .await
+            .unwrap();
+        assert!(!dir.path().join("node_modules/stale").exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_node_modules_when_install_fails() {
+        struct FailingFetcher;
+
+        impl Fetcher for FailingFetcher {
+            async fn fetch(&self, _: &ResolvedPackage, _: &Path) -> Result<(), Error> {
+                Err(io::Error::other("offline").into())
+            }
+        }
+
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[])]));
+        let dir = install_fixture(registry, &[("a", "1.0.0")], Strategy::Hoisted).await;
+
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("b", &[("1.0.0", &[])]));
+
+        let package_json = PackageJson {
+            name: "root".into(),
+            version: "1.0.0".into(),
+            dependencies: Some([("b".into(), "1.0.0".into())].into()),
+            ..Default::default()
+        };
+        let resolution = dinopkg_resolver::resolve(&registry, &package_json, dir.path())
+            .await
+            .unwrap();
+        let layout = Strategy::Hoisted.layout(&resolution);
+        assert!(install(&resolution, &layout, dir.path(), &FailingFetcher)
+            .await
+            .is_err());
+        assert_eq!(tree(dir.path()), expected(&[("node_modules/a", "1.0.0")]));
+        assert!(!dir.path().join(".node_modules.staging").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn isolated_only_exposes_declared_dependencies() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("b", "^1.0.0")])]));
+        registry.insert(packument("b", &[("1.0.0", &[])]));
+
+        let dir = install_fixture(registry, &[("a", "^1.0.0")], Strategy::Isolated).await;
+        let node_modules = dir.path().join("node_modules");
+        assert_eq!(
+            tree(dir.path()),
+            expected(&[
+                ("node_modules/.dino/a@1.0.0/node_modules/a", "1.0.0"),
+                ("node_modules/.dino/b@1.0.0/node_modules/b", "1.0.0"),
+            ])
+        );
+        assert_eq!(
+            fs::read_link(node_modules.join("a")).unwrap(),
+            Path::new(".dino/a@1.0.0/node_modules/a")
+        );
+        // `a` can see `b` next to it...
+        assert!(node_modules
+            .join(".dino/a@1.0.0/node_modules/b/package.json")
+            .exists());
+        // ...but the root can't.
+        assert!(!node_modules.join("b").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn installs_local_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(
+            "lib/package.json",
+            r#"{"name":"lib","version":"1.0.0","files":["index.js"]}"#,
+        );
+        write("lib/index.js", "");
+        write("lib/src/index.ts", "");
+        write(
+            "packed/package.json",
+            r#"{"name":"packed","version":"2.0.0"}"#,
+        );
+        write(
+            "linked/package.json",
+            r#"{"name":"linked","version":"3.0.0","bin":"run.js"}"#,
+        );
+        write("linked/run.js", "");
+        fs::write(
+            dir.path().join("packed.tgz"),
+            pack::pack(&dir.path().join("packed")).unwrap(),
+        )
+        .unwrap();
+
+        let root = dir.path().join("app");
+        fs::create_dir_all(&root).unwrap();
+        let package_json = PackageJson {
+            name: "app".into(),
+            version: "1.0.0".into(),
+            dependencies: Some(
+                [
+                    ("lib", "file:../lib"),
+                    ("packed", "file:../packed.tgz"),
+                    ("linked", "link:../linked"),
+                ]
+                .into_iter()
+                .map(|(name, spec)| (name.into(), spec.into()))
+                .collect(),
+            ),
+            ..Default::default()
+        };
+        let resolution = dinopkg_resolver::resolve(&MemoryRegistry::new(), &package_json, &root)
+            .await
+            .unwrap();
+        let layout = Strategy::Hoisted.layout(&resolution);
+        install(
+            &resolution,
+            &layout,
+            &root,
+            &LocalFetcher::new(FixtureFetcher, &root),
+        )
+        .await
+        .unwrap();
+
+        let node_modules = root.join("node_modules");
+        assert!(node_modules.join("lib/index.js").exists());
+        assert!(!node_modules.join("lib/src").exists());
+        assert!(node_modules.join("packed/package.json").exists());
+        assert_eq!(
+            fs::read_link(node_modules.join("linked")).unwrap(),
+            Path::new("../../linked")
+        );
+        assert!(node_modules.join("linked/package.json").exists());
+        assert_eq!(
+            fs::read_link(node_modules.join(".bin/linked")).unwrap(),
+            Path::new("../linked/run.js")
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn installs_workspaces() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("c", &[("1.0.0", &[]), ("2.0.0", &[])]));
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("packages/a")).unwrap();
+        fs::write(
+            dir.path().join("packages/a/package.json"),
+            r#"{"name":"a","version":"1.0.0","bin":{"a-cli":"cli.js"},"dependencies":{"c":"^1.0.0"}}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("packages/a/cli.js"), "").unwrap();
+        let package_json = PackageJson {
+            name: "root".into(),
+            version: "1.0.0".into(),
+            dependencies: Some([("c".into(), "^2.0.0".into())].into_iter().collect()),
+            workspaces: Some(dinopkg_package_json::Workspaces::Globs(vec![
+                "packages/*".into()
+            ])),
+            ..Default::default()
+        };
+        let resolution = dinopkg_resolver::resolve(&registry, &package_json, dir.path())
+            .await
+            .unwrap();
+
+        let layout = Strategy::Hoisted.layout(&resolution);
+        install(&resolution, &layout, dir.path(), &FixtureFetcher)
+            .await
+            .unwrap();
+        let node_modules = dir.path().join("node_modules");
+        assert_eq!(
+            fs::read_link(node_modules.join("a")).unwrap(),
+            Path::new("../packages/a")
+        );
+        assert_eq!(tree(dir.path()), expected(&[("node_modules/c", "2.0.0")]));
+        assert!(dir
+            .path()
+            .join("packages/a/node_modules/c/package.json")
+            .exists());
+        assert_eq!(
+            fs::read_link(node_modules.join(".bin/a-cli")).unwrap(),
+            Path::new("../a/cli.js")
+        );
+
+        let layout = Strategy::Isolated.layout(&resolution);
+        install(&resolution, &layout, dir.path(), &FixtureFetcher)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_link(dir.path().join("packages/a/node_modules/c")).unwrap(),
+            Path::new("../../../node_modules/.dino/c@1.0.0/node_modules/c")
+        );
+        assert!(node_modules.join(".bin/a-cli").exists());
+    }
+}
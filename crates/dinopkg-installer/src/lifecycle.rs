@@ -0,0 +1,496 @@
+//! Running packages' lifecycle scripts after they're installed.
+//!
+//! Install scripts run arbitrary code, so dependencies only get to run theirs if
+//! they're trusted by a [`Policy`]. The rest are [skipped](Plan::skipped), so they
+//! can be reported. The project itself and its workspaces are always trusted.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dinopkg_package_json::PackageJson;
+use dinopkg_resolver::{PackageId, Resolution, ResolvedPackage, Source};
+
+use crate::{store, Error, Layout};
+
+/// The scripts dependencies run when they're installed, in order.
+pub const INSTALL_EVENTS: &[&str] = &["preinstall", "install", "postinstall"];
+
+/// The scripts the project and its workspaces run, in order.
+pub const PROJECT_EVENTS: &[&str] = &["preinstall", "install", "postinstall", "prepare"];
+
+/// Which dependencies are allowed to run lifecycle scripts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Don't run any scripts, not even the project's own.
+    pub ignore_scripts: bool,
+
+    /// The dependencies that can run scripts: registry packages by name, and anything
+    /// else by name and source (see [`Policy::allows`]).
+    pub trusted: BTreeSet<String>,
+}
+
+impl Policy {
+    /// Whether `package`, installed as `name`, can run scripts.
+    ///
+    /// A plain name only trusts the registry package with that name, installed under
+    /// it. Aliases, git and local packages have to be listed with where they come
+    /// from, like `foo@npm:bar`, `foo@git+https://example.com/foo.git` (any commit,
+    /// or add `#<commit>` for just one) or `foo@file:../foo`, so that something else
+    /// named like a trusted package doesn't get to run its scripts.
+    pub fn allows(&self, name: &str, package: &ResolvedPackage) -> bool {
+        if self.ignore_scripts {
+            return false;
+        }
+        let sources = match &package.source {
+            Source::Registry if name == package.id.name => return self.trusted.contains(name),
+            Source::Registry => vec![format!("npm:{}", package.id.name)],
+            Source::Git { url, commit } => {
+                vec![format!("git+{url}"), format!("git+{url}#{commit}")]
+            }
+            Source::Directory { path } | Source::Tarball { path } => vec![format!("file:{path}")],
+            Source::Link { path } => vec![format!("link:{path}")],
+        };
+        sources
+            .iter()
+            .any(|source| self.trusted.contains(&format!("{name}@{source}")))
+    }
+}
+
+/// A script to run, like `postinstall`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub event: &'static str,
+    pub command: String,
+}
+
+/// The scripts to run in one package directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    /// The package's name, for showing what's running.
+    pub name: String,
+    pub dir: PathBuf,
+
+    /// Whether this is a dependency, rather than the project or a workspace.
+    pub dependency: bool,
+    pub scripts: Vec<Script>,
+}
+
+/// A dependency whose scripts weren't run, because the policy doesn't trust it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub id: PackageId,
+    pub events: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// What to run, with dependencies before the packages that need them.
+    pub builds: Vec<Build>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Runs a single lifecycle script.
+pub trait RunScript {
+    /// Runs `script` in `build`'s directory, failing if it doesn't succeed.
+    fn run_script(&self, build: &Build, script: &Script)
+        -> impl Future<Output = Result<(), Error>>;
+}
+
+/// Works out which scripts to run once everything in `layout` has been installed into
+/// `root`, and which ones `policy` doesn't allow.
+///
+/// Scripts are read from the installed `package.json` files. Each dependency runs
+/// its scripts in every directory it's installed in, and [links](Source::Link) only
+/// run theirs if they're one of `workspaces` (given as paths like `packages/a`). The
+/// project's own scripts run last.
+///
+/// # Errors
+/// This function fails if an installed `package.json` can't be read.
+pub fn plan(
+    resolution: &Resolution,
+    layout: &Layout,
+    root: &Path,
+    workspaces: &[String],
+    policy: &Policy,
+) -> Result<Plan, Error> {
+    let mut plan = Plan::default();
+    if policy.ignore_scripts {
+        return Ok(plan);
+    }
+
+    let mut paths: BTreeMap<&PackageId, Vec<&PathBuf>> = BTreeMap::new();
+    for (path, id) in &layout.packages {
+        paths.entry(id).or_default().push(path);
+    }
+    // Packages are trusted by the names they're installed as, so aliases are noticed.
+    let mut names: BTreeMap<&PackageId, BTreeSet<&str>> = BTreeMap::new();
+    let edges = resolution
+        .dependencies
+        .iter()
+        .chain(&resolution.dev_dependencies)
+        .chain(
+            resolution
+                .packages
+                .values()
+                .flat_map(|package| &package.dependencies),
+        );
+    for (name, id) in edges {
+        names.entry(id).or_default().insert(name);
+    }
+    for id in dependency_order(resolution) {
+        let package = &resolution.packages[id];
+        if let Source::Link { path } = &package.source {
+            if workspaces.contains(path) {
+                let dir = root.join(path);
+                plan.builds
+                    .extend(build(&id.name, &dir, PROJECT_EVENTS, false)?);
+            }
+            continue;
+        }
+
+        let mut builds = Vec::new();
+        for path in paths.get(id).into_iter().flatten() {
+            builds.extend(build(&id.name, &root.join(path), INSTALL_EVENTS, true)?);
+        }
+        let Some(first) = builds.first() else {
+            continue;
+        };
+        let trusted = match names.get(id) {
+            Some(names) => names.iter().all(|name| policy.allows(name, package)),
+            None => policy.allows(&id.name, package),
+        };
+        if trusted {
+            plan.builds.extend(builds);
+        } else {
+            plan.skipped.push(Skipped {
+                id: id.clone(),
+                events: first.scripts.iter().map(|script| script.event).collect(),
+            });
+        }
+    }
+
+    let name = PackageJson::parse(&fs::read_to_string(root.join("package.json"))?)?.name;
+    plan.builds
+        .extend(build(&name, root, PROJECT_EVENTS, false)?);
+    Ok(plan)
+}
+
+/// Runs every build in `plan`, one script at a time.
+///
+/// Dependencies' files might be hard linked from the [store](crate::store), so
+/// they're given their own copies first. Otherwise, a script that changed them would
+/// change every other project's copy too.
+///
+/// # Errors
+/// This function fails as soon as a script does.
+pub async fn run<R: RunScript>(plan: &Plan, runner: &R) -> Result<(), Error> {
+    for build in &plan.builds {
+        if build.dependency {
+            let dir = build.dir.clone();
+            tokio::task::spawn_blocking(move || store::detach(&dir))
+                .await
+                .map_err(io::Error::other)??;
+        }
+        for script in &build.scripts {
+            runner.run_script(build, script).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the scripts for `events` from the package in `dir`, if it has any.
+fn build(
+    name: &str,
+    dir: &Path,
+    events: &[&'static str],
+    dependency: bool,
+) -> Result<Option<Build>, Error> {
+    let contents = match fs::read_to_string(dir.join("package.json")) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // Published packages can have all sorts of malformed fields, which shouldn't stop
+    // their scripts from being found.
+    let scripts = serde_json::from_str(&contents)
+        .ok()
+        .and_then(PackageJson::from_value_lenient)
+        .and_then(|(package_json, _)| package_json.scripts)
+        .unwrap_or_default();
+    let mut found: Vec<Script> = events
+        .iter()
+        .filter_map(|event| {
+            Some(Script {
+                event,
+                command: scripts.get(*event)?.clone(),
+            })
+        })
+        .collect();
+    // Like npm, native modules that don't build themselves are built with node-gyp.
+    let builds_itself = scripts.contains_key("install") || scripts.contains_key("preinstall");
+    if !builds_itself && dir.join("binding.gyp").is_file() {
+        found.insert(
+            0,
+            Script {
+                event: "install",
+                command: "node-gyp rebuild".into(),
+            },
+        );
+    }
+
+    Ok((!found.is_empty()).then(|| Build {
+        name: name.into(),
+        dir: dir.into(),
+        dependency,
+        scripts: found,
+    }))
+}
+
+/// Every package in `resolution`, with each one after everything it depends on.
+/// Packages that depend on each other are in an arbitrary (but stable) order.
+fn dependency_order(resolution: &Resolution) -> Vec<&PackageId> {
+    fn visit<'a>(
+        resolution: &'a Resolution,
+        id: &'a PackageId,
+        visited: &mut BTreeSet<&'a PackageId>,
+        order: &mut Vec<&'a PackageId>,
+    ) {
+        if !visited.insert(id) {
+            return;
+        }
+        if let Some(package) = resolution.packages.get(id) {
+            for dependency in package.dependencies.values() {
+                visit(resolution, dependency, visited, order);
+            }
+            order.push(id);
+        }
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    for id in resolution.packages.keys() {
+        visit(resolution, id, &mut visited, &mut order);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use maplit::btreemap;
+    use pretty_assertions::assert_eq;
+
+    /// Records which scripts were run, instead of running them.
+    #[derive(Default)]
+    struct Recorder {
+        ran: Mutex<Vec<String>>,
+    }
+
+    impl RunScript for Recorder {
+        async fn run_script(&self, build: &Build, script: &Script) -> Result<(), Error> {
+            self.ran.lock().unwrap().push(format!(
+                "{} {}: {}",
+                build.name, script.event, script.command
+            ));
+            Ok(())
+        }
+    }
+
+    fn id(name: &str) -> PackageId {
+        PackageId {
+            name: name.into(),
+            version: "1.0.0".into(),
+            ..Default::default()
+        }
+    }
+
+    fn package(name: &str, source: Source, dependencies: &[&str]) -> (PackageId, ResolvedPackage) {
+        let package = ResolvedPackage {
+            id: id(name),
+            source,
+            dist: Default::default(),
+            manifest: Default::default(),
+            dependencies: dependencies
+                .iter()
+                .map(|dependency| ((*dependency).into(), id(dependency)))
+                .collect(),
+            dev: false,
+            optional: false,
+            peer: false,
+        };
+        (id(name), package)
+    }
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[tokio::test]
+    async fn runs_trusted_scripts_in_dependency_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            root,
+            "package.json",
+            r#"{"name":"app","version":"1.0.0","scripts":{"prepare":"husky","test":"jest"}}"#,
+        );
+        write(
+            root,
+            "packages/ui/package.json",
+            r#"{"name":"ui","version":"1.0.0","scripts":{"postinstall":"build-icons"}}"#,
+        );
+        write(
+            root,
+            "node_modules/esbuild/package.json",
+            r#"{"name":"esbuild","version":"1.0.0","scripts":{"postinstall":"node install.js"}}"#,
+        );
+        write(
+            root,
+            "node_modules/native/package.json",
+            r#"{"name":"native","version":"1.0.0"}"#,
+        );
+        write(root, "node_modules/native/binding.gyp", "{}");
+        write(
+            root,
+            "node_modules/sketchy/package.json",
+            r#"{"name":"sketchy","version":"1.0.0","scripts":{"preinstall":"curl","install":"sh"}}"#,
+        );
+        write(
+            root,
+            "node_modules/plain/package.json",
+            r#"{"name":"plain","version":"1.0.0"}"#,
+        );
+        write(
+            root,
+            "node_modules/legacy/package.json",
+            r#"{"name":"legacy","version":"1.0.0","license":{"type":"MIT"},"author":["a","b"],"scripts":{"postinstall":"node old.js"}}"#,
+        );
+
+        let workspace = Source::Link {
+            path: "packages/ui".into(),
+        };
+        let resolution = Resolution {
+            packages: [
+                package("ui", workspace, &["esbuild"]),
+                package("esbuild", Source::Registry, &["native"]),
+                package("native", Source::Registry, &[]),
+                package("sketchy", Source::Registry, &[]),
+                package("plain", Source::Registry, &[]),
+                package("legacy", Source::Registry, &[]),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let layout = Layout {
+            packages: btreemap! {
+                PathBuf::from("node_modules/ui") => id("ui"),
+                PathBuf::from("node_modules/esbuild") => id("esbuild"),
+                PathBuf::from("node_modules/native") => id("native"),
+                PathBuf::from("node_modules/sketchy") => id("sketchy"),
+                PathBuf::from("node_modules/plain") => id("plain"),
+                PathBuf::from("node_modules/legacy") => id("legacy"),
+            },
+            ..Default::default()
+        };
+        let policy = Policy {
+            ignore_scripts: false,
+            trusted: ["esbuild".into(), "native".into(), "legacy".into()].into(),
+        };
+        let workspaces = ["packages/ui".into()];
+        let plan = plan(&resolution, &layout, root, &workspaces, &policy).unwrap();
+        assert_eq!(
+            plan.skipped,
+            [Skipped {
+                id: id("sketchy"),
+                events: vec!["preinstall", "install"],
+            }]
+        );
+
+        let recorder = Recorder::default();
+        run(&plan, &recorder).await.unwrap();
+        assert_eq!(
+            *recorder.ran.lock().unwrap(),
+            [
+                "native install: node-gyp rebuild",
+                "esbuild postinstall: node install.js",
+                "legacy postinstall: node old.js",
+                "ui postinstall: build-icons",
+                "app prepare: husky",
+            ]
+        );
+
+        let policy = Policy {
+            ignore_scripts: true,
+            ..policy
+        };
+        assert_eq!(
+            super::plan(&resolution, &layout, root, &workspaces, &policy).unwrap(),
+            Plan::default()
+        );
+    }
+
+    #[test]
+    fn only_trusts_other_sources_when_listed_with_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(root, "package.json", r#"{"name":"app","version":"1.0.0"}"#);
+        write(
+            root,
+            "node_modules/esbuild/package.json",
+            r#"{"name":"evil","version":"1.0.0","scripts":{"postinstall":"curl"}}"#,
+        );
+
+        // `"esbuild": "npm:evil@1"` doesn't get esbuild's trust.
+        let resolution = Resolution {
+            dependencies: btreemap! { "esbuild".into() => id("evil") },
+            packages: [package("evil", Source::Registry, &[])].into(),
+            ..Default::default()
+        };
+        let layout = Layout {
+            packages: btreemap! { PathBuf::from("node_modules/esbuild") => id("evil") },
+            ..Default::default()
+        };
+        let mut policy = Policy {
+            ignore_scripts: false,
+            trusted: ["esbuild".into(), "evil".into()].into(),
+        };
+        let planned = plan(&resolution, &layout, root, &[], &policy).unwrap();
+        assert_eq!(planned.skipped.len(), 1);
+
+        policy.trusted = ["esbuild@npm:evil".into()].into();
+        let planned = plan(&resolution, &layout, root, &[], &policy).unwrap();
+        assert_eq!(planned.skipped, []);
+        assert_eq!(planned.builds.len(), 1);
+
+        let git = Source::Git {
+            url: "https://example.com/esbuild.git".into(),
+            commit: "abc123".into(),
+        };
+        let (_, git) = package("esbuild", git, &[]);
+        let (_, local) = package(
+            "esbuild",
+            Source::Directory {
+                path: "../esbuild".into(),
+            },
+            &[],
+        );
+        policy.trusted = ["esbuild".into()].into();
+        assert!(!policy.allows("esbuild", &git));
+        assert!(!policy.allows("esbuild", &local));
+
+        policy.trusted = ["esbuild@git+https://example.com/esbuild.git".into()].into();
+        assert!(policy.allows("esbuild", &git));
+        policy.trusted = ["esbuild@git+https://example.com/esbuild.git#def456".into()].into();
+        assert!(!policy.allows("esbuild", &git));
+        policy.trusted = ["esbuild@file:../esbuild".into()].into();
+        assert!(policy.allows("esbuild", &local));
+    }
+}
@@ -0,0 +1,54 @@
+//! Installing dependencies from the local filesystem, like `file:../lib`.
+//!
+//! Directories are [packed](crate::pack) first, so they end up with the same files
+//! they would have if they'd been published. Links (`link:../lib`) aren't fetched at
+//! all; [`install`](crate::install) symlinks them instead.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dinopkg_npm_registry::Integrity;
+use dinopkg_resolver::{ResolvedPackage, Source};
+
+use crate::{extract, pack, Error, Fetcher};
+
+/// A [`Fetcher`] that installs local directories and tarballs, and passes everything
+/// else on to another fetcher.
+pub struct LocalFetcher<F> {
+    fetcher: F,
+
+    /// The project root, which local paths are relative to.
+    root: PathBuf,
+}
+
+impl<F> LocalFetcher<F> {
+    pub fn new(fetcher: F, root: impl Into<PathBuf>) -> Self {
+        Self {
+            fetcher,
+            root: root.into(),
+        }
+    }
+}
+
+impl<F: Fetcher> Fetcher for LocalFetcher<F> {
+    async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+        match &package.source {
+            Source::Directory { path } => {
+                let dir = self.root.join(path);
+                let tarball = tokio::task::spawn_blocking(move || pack::pack(&dir))
+                    .await
+                    .map_err(io::Error::other)??;
+                extract(tarball, dest).await
+            }
+            Source::Tarball { path } => {
+                let data = tokio::fs::read(self.root.join(path)).await?;
+                // The lockfile pins the tarball's hash, so changes to it are noticed.
+                if let Ok(integrity) = Integrity::from_dist(&package.dist) {
+                    integrity.verify(&data, path)?;
+                }
+                extract(data, dest).await
+            }
+            _ => self.fetcher.fetch(package, dest).await,
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Packing a directory into a tarball, choosing files the way `npm pack` does.
+//!
+//! This is what git and local directory dependencies go through, so they end up
+//! with the same files they would have if they'd been published.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use dinopkg_package_json::{BinVariant, PackageJson};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::Error;
+
+/// Files that are never packed, in `.gitignore` syntax.
+const IGNORED: &[&str] = &[
+    ".npmignore",
+    ".gitignore",
+    ".git",
+    ".svn",
+    ".hg",
+    "CVS",
+    ".npmrc",
+    "node_modules",
+    ".DS_Store",
+    "._*",
+    ".*.swp",
+    "*.orig",
+    "npm-debug.log",
+    "/.lock-wscript",
+    "/.wafpickle-*",
+    "/build/config.gypi",
+    "/package-lock.json",
+    "/yarn.lock",
+    "/pnpm-lock.yaml",
+    "/archived-packages/",
+];
+
+/// npm sets every file's modification time to this, so packing is reproducible.
+const MTIME: u64 = 499_162_500;
+
+/// Packs `dir` into a gzipped tarball, with everything under `package/` like npm does.
+///
+/// # Errors
+/// This function fails if `dir` can't be read, or if its `files` has an invalid pattern.
+pub fn pack(dir: &Path) -> Result<Vec<u8>, Error> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for path in packlist(dir)? {
+        let full = dir.join(&path);
+        let contents = fs::read(&full)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(if is_executable(&fs::metadata(&full)?) {
+            0o755
+        } else {
+            0o644
+        });
+        header.set_mtime(MTIME);
+        let name = format!("package/{}", slash_path(&path));
+        builder.append_data(&mut header, name, contents.as_slice())?;
+    }
+    let mut encoder = builder.into_inner()?;
+    encoder.flush()?;
+    Ok(encoder.finish()?)
+}
+
+/// Lists the files in `dir` that would be packed, relative to `dir` and sorted.
+///
+/// If `package.json` has `files`, only those are packed. Otherwise, everything is
+/// except what `.npmignore` (or `.gitignore`, if there's no `.npmignore`) excludes.
+/// `package.json`, the README and the license are always packed, and so are `main`
+/// and `bin`.
+///
+/// # Errors
+/// This function fails if `dir` can't be read, or if its `files` has an invalid pattern.
+pub fn packlist(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let package_json = PackageJson::parse(&fs::read_to_string(dir.join("package.json"))?)?;
+
+    let mut ignored = GitignoreBuilder::new(dir);
+    for line in IGNORED {
+        ignored
+            .add_line(None, line)
+            .expect("built-in ignore patterns should be valid");
+    }
+    let files = match &package_json.files {
+        Some(files) => Some(Files::new(files)?),
+        None => {
+            let npmignore = dir.join(".npmignore");
+            let ignore_file = if npmignore.is_file() {
+                npmignore
+            } else {
+                dir.join(".gitignore")
+            };
+            if ignore_file.is_file() {
+                // A bad line is skipped, which is what git does too.
+                ignored.add(ignore_file);
+            }
+            None
+        }
+    };
+    let ignored = ignored.build().map_err(std::io::Error::other)?;
+
+    let mut list = Vec::new();
+    let walker = walkdir::WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !is_ignored(&ignored, dir, entry.path(), entry.file_type().is_dir()));
+    for entry in walker {
+        let entry = entry.map_err(std::io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let wanted = files.as_ref().is_none_or(|files| files.includes(path));
+        if wanted || always_packed(path, &package_json) {
+            list.push(path.to_path_buf());
+        }
+    }
+    list.sort();
+    Ok(list)
+}
+
+/// The patterns from `files`, where a directory includes everything inside it.
+struct Files {
+    included: GlobSet,
+    excluded: GlobSet,
+}
+
+impl Files {
+    fn new(patterns: &[String]) -> Result<Self, Error> {
+        let mut included = GlobSetBuilder::new();
+        let mut excluded = GlobSetBuilder::new();
+        for pattern in patterns {
+            let (set, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (&mut excluded, pattern),
+                None => (&mut included, pattern.as_str()),
+            };
+            let pattern = pattern.trim_start_matches("./").trim_matches('/');
+            if pattern.is_empty() {
+                continue;
+            }
+            for glob in [pattern.to_owned(), format!("{pattern}/**")] {
+                let glob = GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|_| Error::InvalidFilesPattern(pattern.into()))?;
+                set.add(glob);
+            }
+        }
+        let build = |set: GlobSetBuilder| {
+            set.build()
+                .map_err(|err| Error::InvalidFilesPattern(err.to_string()))
+        };
+        Ok(Self {
+            included: build(included)?,
+            excluded: build(excluded)?,
+        })
+    }
+
+    fn includes(&self, path: &Path) -> bool {
+        self.included.is_match(path) && !self.excluded.is_match(path)
+    }
+}
+
+fn is_ignored(ignored: &Gitignore, dir: &Path, path: &Path, is_dir: bool) -> bool {
+    let path = path.strip_prefix(dir).unwrap_or(path);
+    // `package.json` and friends are packed even if something tries to ignore them.
+    if !is_dir && path.parent() == Some(Path::new("")) && is_always_packed_name(path) {
+        return false;
+    }
+    ignored.matched(path, is_dir).is_ignore()
+}
+
+/// Whether `path` is packed no matter what `files` and the ignore files say.
+fn always_packed(path: &Path, package_json: &PackageJson) -> bool {
+    if path.parent() == Some(Path::new("")) && is_always_packed_name(path) {
+        return true;
+    }
+    let bins = match &package_json.bin {
+        Some(BinVariant::String(bin)) => vec![bin.as_str()],
+        Some(BinVariant::Map(bins)) => bins.values().map(String::as_str).collect(),
+        None => Vec::new(),
+    };
+    package_json
+        .main
+        .iter()
+        .map(String::as_str)
+        .chain(bins)
+        .any(|file| Path::new(file.trim_start_matches("./")) == path)
+}
+
+fn is_always_packed_name(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    name == "package.json"
+        || ["readme", "license", "licence", "copying"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Tarballs always use forward slashes, even on Windows.
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn write(dir: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    fn list(dir: &Path) -> Vec<String> {
+        packlist(dir)
+            .unwrap()
+            .iter()
+            .map(|path| slash_path(path))
+            .collect()
+    }
+
+    #[test]
+    fn uses_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            &[
+                (
+                    "package.json",
+                    r#"{"name":"a","version":"1.0.0","main":"index.js","files":["dist","!dist/*.map"]}"#,
+                ),
+                ("README.md", ""),
+                ("index.js", ""),
+                ("dist/a.js", ""),
+                ("dist/a.js.map", ""),
+                ("dist/nested/b.js", ""),
+                ("src/a.ts", ""),
+                (".gitignore", "dist\n"),
+                ("node_modules/x/index.js", ""),
+            ],
+        );
+        assert_eq!(
+            list(dir.path()),
+            [
+                "README.md",
+                "dist/a.js",
+                "dist/nested/b.js",
+                "index.js",
+                "package.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            &[
+                ("package.json", r#"{"name":"a","version":"1.0.0"}"#),
+                ("LICENSE", ""),
+                ("index.js", ""),
+                ("test/a.js", ""),
+                ("coverage/lcov.info", ""),
+                (".gitignore", "coverage\n"),
+                (".npmignore", "test/\n"),
+                (".git/HEAD", ""),
+                ("package-lock.json", "{}"),
+            ],
+        );
+        // .npmignore wins over .gitignore.
+        assert_eq!(
+            list(dir.path()),
+            ["LICENSE", "coverage/lcov.info", "index.js", "package.json"]
+        );
+
+        let tarball = pack(dir.path()).unwrap();
+        let out = tempfile::tempdir().unwrap();
+        dinopkg_npm_registry::extract_tarball(&tarball, out.path()).unwrap();
+        assert!(out.path().join("index.js").exists());
+        assert!(!out.path().join("test").exists());
+    }
+}
@@ -0,0 +1,445 @@
+//! A global, content-addressable store of extracted packages.
+//!
+//! Every package is extracted into the store once, keyed by its integrity hash,
+//! and then hard linked into each `node_modules` that needs it. If hard links
+//! don't work (usually because the store is on a different filesystem), files
+//! are reflinked or copied instead.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use dinopkg_npm_registry::Integrity;
+use dinopkg_resolver::ResolvedPackage;
+
+use crate::{Error, Fetcher};
+
+/// Bumped whenever the store's layout changes, so old stores are left alone.
+const STORE_VERSION: &str = "v1";
+
+#[derive(Debug, Clone)]
+pub struct Store {
+    root: PathBuf,
+    /// Cleared the first time a hard link fails, so we don't keep trying.
+    hard_links: Arc<AtomicBool>,
+}
+
+/// A summary of what's in the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Status {
+    /// How many packages are stored.
+    pub packages: usize,
+
+    /// How many stored packages aren't used by any `node_modules`.
+    pub unreferenced: usize,
+
+    /// The total size of every stored file, in bytes.
+    pub size: u64,
+}
+
+/// What [`Store::prune`] removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pruned {
+    pub packages: usize,
+
+    /// How many bytes were freed.
+    pub size: u64,
+}
+
+impl Store {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            hard_links: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// `$XDG_DATA_HOME/dino/store` on Linux, and the platform's equivalent elsewhere.
+    pub fn default_root() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("dino").join("store"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn packages_dir(&self) -> PathBuf {
+        self.root.join(STORE_VERSION).join("packages")
+    }
+
+    fn tmp_dir(&self) -> PathBuf {
+        self.root.join(STORE_VERSION).join("tmp")
+    }
+
+    /// Where the package with `integrity` is (or would be) stored.
+    pub fn package_dir(&self, integrity: &Integrity) -> PathBuf {
+        self.packages_dir()
+            .join(integrity.algorithm())
+            .join(integrity.to_hex())
+    }
+
+    /// Returns whether `package` is in the store, so installing it won't need the network.
+    pub fn contains(&self, package: &ResolvedPackage) -> bool {
+        Integrity::from_dist(&package.dist)
+            .is_ok_and(|integrity| self.package_dir(&integrity).exists())
+    }
+
+    /// Wraps `fetcher`, so that packages only go through it if they aren't already stored.
+    pub fn fetcher<F: Fetcher>(&self, fetcher: F) -> StoreFetcher<'_, F> {
+        StoreFetcher {
+            store: self,
+            fetcher,
+        }
+    }
+
+    /// Fetches `package` into the store at `dir`.
+    async fn add<F: Fetcher>(
+        &self,
+        fetcher: &F,
+        package: &ResolvedPackage,
+        dir: &Path,
+    ) -> Result<(), Error> {
+        // Packages are fetched somewhere else first, so a half-extracted
+        // package never ends up in the store.
+        tokio::fs::create_dir_all(self.tmp_dir()).await?;
+        let tmp = tempfile::tempdir_in(self.tmp_dir())?;
+        fetcher.fetch(package, tmp.path()).await?;
+
+        if let Some(parent) = dir.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        match tokio::fs::rename(tmp.path(), dir).await {
+            Ok(()) => Ok(()),
+            // Someone else stored the same package first, which is fine.
+            Err(_) if tokio::fs::try_exists(dir).await? => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Summarises what's in the store.
+    ///
+    /// # Errors
+    /// This function fails if the store can't be read.
+    pub fn status(&self) -> Result<Status, Error> {
+        let mut status = Status::default();
+        for dir in self.package_dirs()? {
+            let usage = usage(&dir)?;
+            status.packages += 1;
+            status.size += usage.size;
+            if !usage.referenced {
+                status.unreferenced += 1;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Removes every package that isn't used by any `node_modules`, along with
+    /// anything left over from interrupted installs.
+    ///
+    /// A package is in use as long as one of its files is hard linked somewhere else,
+    /// so packages that had to be copied into `node_modules` are removed too. They'll
+    /// just be downloaded again next time.
+    ///
+    /// # Errors
+    /// This function fails if the store can't be read or written to.
+    pub fn prune(&self) -> Result<Pruned, Error> {
+        let mut pruned = Pruned::default();
+        for dir in self.package_dirs()? {
+            let usage = usage(&dir)?;
+            if !usage.referenced {
+                fs::remove_dir_all(&dir)?;
+                pruned.packages += 1;
+                pruned.size += usage.size;
+            }
+        }
+        match fs::remove_dir_all(self.tmp_dir()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        Ok(pruned)
+    }
+
+    fn package_dirs(&self) -> io::Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        for algorithm in read_dir_or_empty(&self.packages_dir())? {
+            for package in read_dir_or_empty(&algorithm)? {
+                dirs.push(package);
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+}
+
+fn read_dir_or_empty(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| Ok(entry?.path())).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+struct Usage {
+    size: u64,
+    referenced: bool,
+}
+
+fn usage(dir: &Path) -> io::Result<Usage> {
+    let mut usage = Usage {
+        size: 0,
+        referenced: false,
+    };
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let inner = self::usage(&entry.path())?;
+            usage.size += inner.size;
+            usage.referenced |= inner.referenced;
+        } else if metadata.is_file() {
+            usage.size += metadata.len();
+            usage.referenced |= is_hard_linked(&metadata);
+        }
+    }
+    Ok(usage)
+}
+
+#[cfg(unix)]
+fn is_hard_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+// There's no stable way to get the link count elsewhere, so never treat anything as unused.
+#[cfg(not(unix))]
+fn is_hard_linked(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+/// Gives every file under `dir` that's hard linked its own copy, so changing it won't
+/// change the file in the store (or in any other `node_modules`).
+///
+/// # Errors
+/// This function fails if `dir` can't be read or written to.
+pub fn detach(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            detach(&path)?;
+        } else if file_type.is_file() && is_hard_linked(&entry.metadata()?) {
+            let mut copy = path.clone().into_os_string();
+            copy.push(".dino-copy");
+            fs::copy(&path, &copy)?;
+            fs::rename(&copy, &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// A [`Fetcher`] that goes through a [`Store`]. Create one with [`Store::fetcher`].
+pub struct StoreFetcher<'a, F> {
+    store: &'a Store,
+    fetcher: F,
+}
+
+impl<F: Fetcher> Fetcher for StoreFetcher<'_, F> {
+    async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+        let Ok(integrity) = Integrity::from_dist(&package.dist) else {
+            // Without a hash, there's nothing to store the package under.
+            return self.fetcher.fetch(package, dest).await;
+        };
+
+        let dir = self.store.package_dir(&integrity);
+        if !tokio::fs::try_exists(&dir).await? {
+            self.store.add(&self.fetcher, package, &dir).await?;
+        }
+
+        let hard_links = Arc::clone(&self.store.hard_links);
+        let dest = dest.to_path_buf();
+        tokio::task::spawn_blocking(move || link_tree(&hard_links, &dir, &dest))
+            .await
+            .map_err(io::Error::other)??;
+        Ok(())
+    }
+}
+
+/// Recreates the directory tree at `from` in `to`, linking files instead of copying them.
+fn link_tree(hard_links: &AtomicBool, from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        if file_type.is_dir() {
+            link_tree(hard_links, &from, &to)?;
+        } else if file_type.is_symlink() {
+            symlink(&fs::read_link(&from)?, &to)?;
+        } else {
+            link_file(hard_links, &from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+fn link_file(hard_links: &AtomicBool, from: &Path, to: &Path) -> io::Result<()> {
+    if hard_links.load(Ordering::Relaxed) {
+        if fs::hard_link(from, to).is_ok() {
+            return Ok(());
+        }
+        hard_links.store(false, Ordering::Relaxed);
+    }
+    reflink_copy::reflink_or_copy(from, to).map(|_| ())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use dinopkg_npm_registry::Dist;
+    use dinopkg_resolver::{PackageId, Source};
+    use pretty_assertions::assert_eq;
+
+    /// Writes a `package.json`, and counts how many times it's called.
+    #[derive(Default)]
+    struct CountingFetcher {
+        fetches: AtomicUsize,
+    }
+
+    impl Fetcher for &CountingFetcher {
+        async fn fetch(&self, package: &ResolvedPackage, dest: &Path) -> Result<(), Error> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            tokio::fs::write(dest.join("package.json"), &package.id.name).await?;
+            tokio::fs::create_dir_all(dest.join("lib")).await?;
+            tokio::fs::write(dest.join("lib/index.js"), "module.exports = 1").await?;
+            Ok(())
+        }
+    }
+
+    fn package(name: &str, shasum: Option<&str>) -> ResolvedPackage {
+        ResolvedPackage {
+            id: PackageId {
+                name: name.into(),
+                version: "1.0.0".into(),
+                ..Default::default()
+            },
+            source: Source::Registry,
+            dist: Dist {
+                shasum: shasum.map(Into::into),
+                ..Default::default()
+            },
+            manifest: Default::default(),
+            dependencies: Default::default(),
+            dev: false,
+            optional: false,
+            peer: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_packages_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().join("store"));
+        let counter = CountingFetcher::default();
+        let fetcher = store.fetcher(&counter);
+
+        let package = package("a", Some(&"ab".repeat(20)));
+        assert!(!store.contains(&package));
+        for project in ["one", "two"] {
+            let dest = dir.path().join(project);
+            fetcher.fetch(&package, &dest).await.unwrap();
+            assert_eq!(fs::read_to_string(dest.join("package.json")).unwrap(), "a");
+            assert!(dest.join("lib/index.js").exists());
+        }
+        assert_eq!(counter.fetches.load(Ordering::Relaxed), 1);
+        assert!(store.contains(&package));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let stored = store
+                .package_dir(&Integrity::from_dist(&package.dist).unwrap())
+                .join("lib/index.js");
+            assert_eq!(fs::metadata(stored).unwrap().nlink(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_packages_without_integrity() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().join("store"));
+        let counter = CountingFetcher::default();
+
+        let dest = dir.path().join("project");
+        fs::create_dir_all(&dest).unwrap();
+        store
+            .fetcher(&counter)
+            .fetch(&package("a", None), &dest)
+            .await
+            .unwrap();
+        assert!(dest.join("package.json").exists());
+        assert_eq!(store.status().unwrap().packages, 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn prunes_unreferenced_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().join("store"));
+        let counter = CountingFetcher::default();
+        let fetcher = store.fetcher(&counter);
+
+        let kept = package("kept", Some(&"01".repeat(20)));
+        let removed = package("removed", Some(&"02".repeat(20)));
+        fetcher
+            .fetch(&kept, &dir.path().join("kept"))
+            .await
+            .unwrap();
+        fetcher
+            .fetch(&removed, &dir.path().join("removed"))
+            .await
+            .unwrap();
+        fs::remove_dir_all(dir.path().join("removed")).unwrap();
+
+        let status = store.status().unwrap();
+        assert_eq!((status.packages, status.unreferenced), (2, 1));
+
+        let pruned = store.prune().unwrap();
+        assert_eq!(pruned.packages, 1);
+        assert_eq!(pruned.size, "removed".len() as u64 + 18);
+        assert!(store
+            .package_dir(&Integrity::from_dist(&kept.dist).unwrap())
+            .exists());
+        assert_eq!(store.status().unwrap().packages, 1);
+    }
+
+    #[test]
+    fn detaches_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = dir.path().join("stored.js");
+        fs::write(&stored, "original").unwrap();
+        let installed = dir.path().join("node_modules/a/lib");
+        fs::create_dir_all(&installed).unwrap();
+        fs::hard_link(&stored, installed.join("index.js")).unwrap();
+
+        detach(&dir.path().join("node_modules/a")).unwrap();
+        fs::write(installed.join("index.js"), "changed").unwrap();
+        assert_eq!(fs::read_to_string(&stored).unwrap(), "original");
+    }
+}
@@ -0,0 +1,261 @@
+//! Writes JSON exactly the way npm writes `package-lock.json`, so that files
+//! round-trip byte-for-byte and dino doesn't cause diff churn for npm users.
+//!
+//! npm uses [json-stringify-nice](https://github.com/isaacs/json-stringify-nice),
+//! which sorts every object's keys:
+//!
+//! - values that aren't objects come before values that are
+//! - a fixed list of keys comes first, in that order
+//! - everything else is sorted with `String.prototype.localeCompare`
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// Keys that npm always puts first, in this order.
+const PREFERRED_KEYS: &[&str] = &[
+    "name",
+    "version",
+    "lockfileVersion",
+    "resolved",
+    "integrity",
+    "requires",
+    "packages",
+    "dependencies",
+];
+
+/// The indentation and line endings of a lockfile, so we can write it back the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Style {
+    indent: String,
+    newline: String,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            indent: "  ".into(),
+            newline: "\n".into(),
+        }
+    }
+}
+
+impl Style {
+    /// Detects the style of `json` like npm does, from the whitespace after the opening brace.
+    pub(crate) fn detect(json: &str) -> Self {
+        let Some(rest) = json.trim_start().strip_prefix(['{', '[']) else {
+            return Self::default();
+        };
+        let newline = if rest.starts_with("\r\n") {
+            "\r\n"
+        } else if rest.starts_with('\n') {
+            "\n"
+        } else {
+            // Everything's on one line, so there's nothing to go on.
+            return Self::default();
+        };
+        let indent: String = rest
+            .trim_start_matches(['\r', '\n'])
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        Self {
+            indent: if indent.is_empty() {
+                Self::default().indent
+            } else {
+                indent
+            },
+            newline: newline.into(),
+        }
+    }
+}
+
+/// Serialises `value` like `stringify(value, swKeyOrder, indent)`, with a trailing newline.
+pub(crate) fn stringify(value: &Value, style: &Style) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, style, 0);
+    out.push_str(&style.newline);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value, style: &Style, depth: usize) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, style, depth + 1);
+                write_value(out, item, style, depth + 1);
+            }
+            newline(out, style, depth);
+            out.push(']');
+        }
+        Value::Object(map) if !map.is_empty() => {
+            out.push('{');
+            for (i, (key, value)) in sorted_entries(map).into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, style, depth + 1);
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push_str(": ");
+                write_value(out, value, style, depth + 1);
+            }
+            newline(out, style, depth);
+            out.push('}');
+        }
+        // Empty arrays and objects, and scalars, are formatted the same as compact JSON.
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+fn newline(out: &mut String, style: &Style, depth: usize) {
+    out.push_str(&style.newline);
+    for _ in 0..depth {
+        out.push_str(&style.indent);
+    }
+}
+
+fn sorted_entries(map: &serde_json::Map<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|(a_key, a), (b_key, b)| {
+        a.is_object()
+            .cmp(&b.is_object())
+            .then_with(|| compare_keys(a_key, b_key))
+    });
+    // The sorted entries are put back into a JavaScript object, which always
+    // iterates integer-like keys first, in numeric order.
+    entries.sort_by_key(|(key, _)| array_index(key).map_or((1, 0), |index| (0, index)));
+    entries
+}
+
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    let preferred = |key| PREFERRED_KEYS.iter().position(|k| *k == key);
+    match (preferred(a), preferred(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => locale_compare(a, b),
+    }
+}
+
+/// Returns the key's numeric value if JavaScript would treat it as an array index.
+fn array_index(key: &str) -> Option<u32> {
+    if key.len() > 1 && key.starts_with('0') {
+        return None;
+    }
+    key.parse::<u32>().ok().filter(|index| *index != u32::MAX)
+}
+
+/// The order ICU's root collation puts ASCII punctuation, symbols and digits in.
+/// Letters come after all of these.
+const ASCII_ORDER: &str = "\t\n\u{b}\u{c}\r _-,;:!?.'\"()[]{}@*/\\&#%`^+<=>|~$0123456789";
+
+/// Approximates `a.localeCompare(b, "en")` as implemented by ICU.
+///
+/// Strings are first compared ignoring case, with punctuation sorting before digits and
+/// digits before letters. Only if they're otherwise equal does lowercase come
+/// before uppercase. Non-ASCII characters sort after ASCII ones by code point, which
+/// is good enough for package names and paths.
+pub(crate) fn locale_compare(a: &str, b: &str) -> Ordering {
+    fn primary(c: char) -> u32 {
+        if c.is_ascii_alphabetic() {
+            return 100 + u32::from(c.to_ascii_lowercase());
+        }
+        match ASCII_ORDER.find(c) {
+            Some(position) => position as u32,
+            None => 1000 + u32::from(c),
+        }
+    }
+    let primaries = |s: &str| s.chars().map(primary).collect::<Vec<_>>();
+    let cases = |s: &str| s.chars().map(|c| c.is_uppercase()).collect::<Vec<_>>();
+
+    primaries(a)
+        .cmp(&primaries(b))
+        .then_with(|| cases(a).cmp(&cases(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn sorts_like_locale_compare() {
+        // Sorted by Node's `a.localeCompare(b, "en")`.
+        let expected = [
+            "@babel/core",
+            "@types/node",
+            "~tilde",
+            "$dollar",
+            "0x",
+            "10",
+            "2",
+            "9",
+            "a",
+            "A",
+            "a1",
+            "a10",
+            "a2",
+            "b",
+            "left_pad",
+            "left-pad",
+            "Left-pad",
+            "left.pad",
+            "leftpad",
+            "node_modules/@scope/a",
+            "node_modules/a",
+            "node_modules/a-b",
+            "node_modules/a/node_modules/b",
+            "node_modules/ab",
+            "z",
+            "Z",
+        ];
+        let mut keys = expected;
+        keys.reverse();
+        keys.sort_by(|a, b| locale_compare(a, b));
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn orders_keys_like_npm() {
+        let value = json!({
+            "dependencies": { "b": "1", "a": "1" },
+            "zebra": true,
+            "version": "1.0.0",
+            "10": 1,
+            "2": 2,
+            "array": [],
+            "empty": {},
+        });
+        assert_eq!(
+            stringify(&value, &Style::default()),
+            r#"{
+  "2": 2,
+  "10": 1,
+  "version": "1.0.0",
+  "array": [],
+  "zebra": true,
+  "dependencies": {
+    "a": "1",
+    "b": "1"
+  },
+  "empty": {}
+}
+"#
+        );
+    }
+
+    #[test]
+    fn detects_style() {
+        let style = Style::detect("{\r\n\t\"name\": \"x\"\r\n}\r\n");
+        assert_eq!(
+            stringify(&json!({ "name": "x", "os": ["linux"] }), &style),
+            "{\r\n\t\"name\": \"x\",\r\n\t\"os\": [\r\n\t\t\"linux\"\r\n\t]\r\n}\r\n"
+        );
+        assert_eq!(Style::detect("{}"), Style::default());
+    }
+}
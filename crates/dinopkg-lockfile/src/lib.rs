@@ -0,0 +1,1456 @@
+//! Reading and writing npm's `package-lock.json`, lockfile versions 2 and 3.
+//!
+//! Lockfiles are written in exactly the format npm uses, so a lockfile that dino
+//! reads and writes back without changes stays byte-for-byte identical.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use dinopkg_installer::Layout;
+use dinopkg_npm_registry::{Dist, Integrity, Manifest};
+use dinopkg_package_json::{BinVariant, Dependencies, DependencySpec, PackageJson, Workspace};
+use dinopkg_resolver::{PackageId, Resolution, ResolvedPackage, Source};
+use dinopkg_semver::{Range, Version};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+
+mod format;
+use format::Style;
+
+/// The name of the lockfile, next to `package.json`.
+pub const LOCKFILE_NAME: &str = "package-lock.json";
+
+/// The `lockfileVersion` dino writes new lockfiles with. Existing ones keep theirs (see
+/// [`Lockfile::inherit_style`]).
+pub const LOCKFILE_VERSION: u32 = 3;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("deserialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+
+    #[error("unsupported lockfileVersion {0} (only 2 and 3 are supported)")]
+    UnsupportedVersion(u32),
+
+    #[error("`{0}` in the lockfile has no version")]
+    MissingVersion(String),
+
+    #[error("`{package}` depends on `{dependency}`, but it isn't in the lockfile")]
+    MissingDependency { package: String, dependency: String },
+
+    #[error("`{package}` is locked to `{commit}`, which isn't a full commit hash")]
+    InvalidCommit { package: String, commit: String },
+}
+
+/// A way that `package.json` and the lockfile disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// `package.json` has a dependency that isn't in the lockfile.
+    Missing { name: String, range: String },
+
+    /// The locked version doesn't satisfy the range in `package.json`.
+    Invalid {
+        name: String,
+        range: String,
+        locked: String,
+    },
+
+    /// The lockfile still has a dependency that's been removed from `package.json`.
+    Extraneous { name: String, range: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { name, range } => write!(f, "missing: {name}@{range} from lockfile"),
+            Self::Invalid {
+                name,
+                range,
+                locked,
+            } => write!(
+                f,
+                "invalid: lockfile's {name}@{locked} does not satisfy {name}@{range}"
+            ),
+            Self::Extraneous { name, range } => write!(
+                f,
+                "extraneous: lockfile's {name}@{range} is no longer in package.json"
+            ),
+        }
+    }
+}
+
+/// A parsed `package-lock.json`.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub lockfile_version: u32,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub requires: bool,
+
+    /// Every package in the tree, keyed by where it's installed, such as
+    /// `node_modules/a/node_modules/b`. The root package is under `""`.
+    #[serde(default)]
+    pub packages: BTreeMap<String, Package>,
+
+    /// The nested tree that version 2 lockfiles include for npm 6 and below.
+    /// dino only reads `packages`, but keeps this so it isn't lost when the file is written back.
+    pub dependencies: Option<Value>,
+
+    /// Fields dino doesn't know about.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+
+    #[serde(skip)]
+    style: Style,
+}
+
+/// An entry in a lockfile's `packages`.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Package {
+    /// Only set when the package's name differs from its directory, such as for aliases.
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub resolved: Option<String>,
+    pub integrity: Option<String>,
+
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub link: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dev: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dev_optional: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub peer: bool,
+
+    pub license: Option<String>,
+    pub dependencies: Option<BTreeMap<String, String>>,
+    pub dev_dependencies: Option<BTreeMap<String, String>>,
+    pub optional_dependencies: Option<BTreeMap<String, String>>,
+    pub peer_dependencies: Option<BTreeMap<String, String>>,
+
+    /// Fields dino doesn't know about.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// Puts `entry` into a version 2 lockfile's `dependencies` tree, at the package that
+/// `names` leads to through each one's own `dependencies`.
+fn insert_legacy(tree: &mut serde_json::Map<String, Value>, names: &[&str], entry: Value) {
+    let Some((name, rest)) = names.split_first() else {
+        return;
+    };
+    let node = tree
+        .entry(*name)
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    let (Value::Object(node), Value::Object(entry)) = (node, &entry) else {
+        return;
+    };
+    if rest.is_empty() {
+        node.extend(entry.clone());
+        return;
+    }
+    let children = node
+        .entry("dependencies")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(children) = children {
+        insert_legacy(children, rest, Value::Object(entry.clone()));
+    }
+}
+
+/// How npm 6 describes `package` in a version 2 lockfile's `dependencies` tree. Links
+/// have their `details` in the directory they point at.
+fn legacy_entry(package: &Package, details: Option<&Package>) -> Value {
+    let mut entry = serde_json::Map::new();
+    let resolved = package.resolved.as_deref();
+    let version = match (&package.name, &package.version) {
+        _ if package.link => resolved.map(|target| format!("file:{target}")),
+        _ if resolved.is_some_and(|resolved| {
+            resolved.starts_with("git+") || resolved.starts_with("file:")
+        }) =>
+        {
+            resolved.map(Into::into)
+        }
+        (Some(name), Some(version)) => Some(format!("npm:{name}@{version}")),
+        (None, version) => version.clone(),
+        (Some(_), None) => None,
+    };
+    let from_registry = version.as_deref() != resolved && !package.link;
+    let fields = [
+        ("version", version),
+        (
+            "resolved",
+            resolved.filter(|_| from_registry).map(Into::into),
+        ),
+        ("integrity", package.integrity.clone()),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            entry.insert(key.into(), Value::String(value));
+        }
+    }
+    let flags = [
+        ("dev", package.dev || package.dev_optional),
+        ("optional", package.optional || package.dev_optional),
+        ("peer", package.peer),
+    ];
+    for (key, value) in flags {
+        if value {
+            entry.insert(key.into(), Value::Bool(true));
+        }
+    }
+    let details = details.unwrap_or(package);
+    let requires: serde_json::Map<String, Value> =
+        [&details.dependencies, &details.optional_dependencies]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|(name, spec)| (name.clone(), Value::String(spec.clone())))
+            .collect();
+    if !requires.is_empty() {
+        entry.insert("requires".into(), Value::Object(requires));
+    }
+    Value::Object(entry)
+}
+
+// serde :/
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Lockfile {
+    /// Parses a `package-lock.json`, remembering its indentation and line endings
+    /// so that [`Lockfile::to_string`](ToString::to_string) matches them.
+    ///
+    /// # Errors
+    /// This function fails if `json` isn't a valid lockfile, or if it's from before npm 7.
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let json = json.strip_prefix('\u{feff}').unwrap_or(json);
+        let mut lockfile: Self = serde_json::from_str(json)?;
+        if !matches!(lockfile.lockfile_version, 2 | 3) {
+            return Err(Error::UnsupportedVersion(lockfile.lockfile_version));
+        }
+        lockfile.style = Style::detect(json);
+        Ok(lockfile)
+    }
+
+    /// Builds a lockfile for `package_json` from a resolution and the tree it was installed as.
+    ///
+    /// `layout` should be [hoisted](dinopkg_installer::layout::hoisted), since that's the
+    /// only layout npm understands.
+    pub fn from_resolution(
+        package_json: &PackageJson,
+        resolution: &Resolution,
+        layout: &Layout,
+    ) -> Self {
+        let mut packages = BTreeMap::new();
+        packages.insert(
+            String::new(),
+            Package {
+                name: Some(package_json.name.clone()),
+                version: Some(package_json.version.clone()),
+                license: package_json.license.clone(),
+                dependencies: package_json.dependencies.clone(),
+                dev_dependencies: package_json.dev_dependencies.clone(),
+                optional_dependencies: package_json.optional_dependencies.clone(),
+                peer_dependencies: package_json.peer_dependencies.clone(),
+                extra: package_json
+                    .workspaces
+                    .iter()
+                    .map(|workspaces| ("workspaces".into(), serde_json::json!(workspaces)))
+                    .collect(),
+                ..Default::default()
+            },
+        );
+
+        for (path, id) in &layout.packages {
+            let package = &resolution.packages[id];
+            let mut key = path_key(path);
+            // Like npm, a link only says where it points, and the details go under
+            // the directory it points at.
+            if let Source::Link { path: target } = &package.source {
+                let link = Package {
+                    resolved: Some(target.clone()),
+                    link: true,
+                    dev: package.dev,
+                    optional: package.optional,
+                    peer: package.peer,
+                    ..Default::default()
+                };
+                packages.insert(std::mem::replace(&mut key, target.clone()), link);
+            }
+            packages.insert(
+                key.clone(),
+                Package {
+                    name: (dir_name(&key) != id.name).then(|| id.name.clone()),
+                    version: Some(id.version.clone()),
+                    resolved: match &package.source {
+                        Source::Link { .. } => None,
+                        source => source.resolved().or_else(|| {
+                            Some(package.dist.tarball.clone()).filter(|url| !url.is_empty())
+                        }),
+                    },
+                    integrity: package.dist.integrity.clone().or_else(|| {
+                        Integrity::from_dist(&package.dist)
+                            .ok()
+                            .map(|integrity| integrity.to_string())
+                    }),
+                    dev: package.dev,
+                    optional: package.optional,
+                    peer: package.peer,
+                    license: package.manifest.license.clone(),
+                    dependencies: package.manifest.dependencies.clone(),
+                    optional_dependencies: package.manifest.optional_dependencies.clone(),
+                    peer_dependencies: package.manifest.peer_dependencies.clone(),
+                    // Workspaces' dev dependencies are installed too.
+                    dev_dependencies: match &package.source {
+                        Source::Link { .. } => package.manifest.dev_dependencies.clone(),
+                        _ => None,
+                    },
+                    extra: manifest_extras(&package.manifest),
+                    ..Default::default()
+                },
+            );
+        }
+
+        Self {
+            name: Some(package_json.name.clone()),
+            version: Some(package_json.version.clone()),
+            lockfile_version: LOCKFILE_VERSION,
+            requires: true,
+            packages,
+            ..Default::default()
+        }
+    }
+
+    /// Writes this lockfile with the same indentation, line endings and
+    /// `lockfileVersion` as `previous`. A version 2 lockfile gets its `dependencies`
+    /// tree rebuilt, so npm 6 can still read it.
+    pub fn inherit_style(&mut self, previous: &Lockfile) {
+        self.style = previous.style.clone();
+        self.lockfile_version = previous.lockfile_version;
+        self.dependencies = (self.lockfile_version == 2).then(|| self.legacy_dependencies());
+    }
+
+    /// Builds the nested tree that version 2 lockfiles include for npm 6 and below, from
+    /// `packages`. Packages nested in a linked directory go under the link.
+    fn legacy_dependencies(&self) -> Value {
+        let links: BTreeMap<&str, &str> = self
+            .packages
+            .iter()
+            .filter(|(_, package)| package.link)
+            .filter_map(|(path, link)| Some((link.resolved.as_deref()?, path.as_str())))
+            .collect();
+
+        let mut tree = serde_json::Map::new();
+        for (path, package) in &self.packages {
+            let path = match path.split_once("/node_modules/") {
+                _ if path.starts_with("node_modules/") => path.clone(),
+                Some((dir, rest)) => match links.get(dir) {
+                    Some(link) => format!("{link}/node_modules/{rest}"),
+                    None => continue,
+                },
+                // The root, and the directories that links point at.
+                None => continue,
+            };
+            let names: Vec<&str> = path["node_modules/".len()..]
+                .split("/node_modules/")
+                .collect();
+            let details = package
+                .resolved
+                .as_ref()
+                .filter(|_| package.link)
+                .and_then(|target| self.packages.get(target));
+            insert_legacy(&mut tree, &names, legacy_entry(package, details));
+        }
+        Value::Object(tree)
+    }
+
+    /// Returns whether the dependencies in the lockfile match `package_json` and its
+    /// `workspaces`.
+    ///
+    /// If they don't, a `package.json` has been changed since the lockfile was written.
+    pub fn is_up_to_date(&self, package_json: &PackageJson, workspaces: &[Workspace]) -> bool {
+        let Some(root) = self.packages.get("") else {
+            return false;
+        };
+        let matches = |locked: &Package, wanted: &PackageJson| {
+            let empty = Dependencies::new();
+            let same = |locked: &Option<Dependencies>, wanted: &Option<Dependencies>| {
+                locked.as_ref().unwrap_or(&empty) == wanted.as_ref().unwrap_or(&empty)
+            };
+            same(&locked.dependencies, &wanted.dependencies)
+                && same(&locked.dev_dependencies, &wanted.dev_dependencies)
+                && same(&locked.optional_dependencies, &wanted.optional_dependencies)
+                && same(&locked.peer_dependencies, &wanted.peer_dependencies)
+        };
+        let workspace_globs = package_json
+            .workspaces
+            .as_ref()
+            .map(|workspaces| serde_json::json!(workspaces));
+        let workspace_matches = |workspace: &Workspace| {
+            let name = &workspace.package_json.name;
+            let linked = self
+                .packages
+                .get(&format!("node_modules/{name}"))
+                .is_some_and(|link| {
+                    link.link && link.resolved.as_deref() == Some(workspace.path.as_str())
+                });
+            self.packages.get(&workspace.path).is_some_and(|locked| {
+                linked
+                    && locked.version.as_ref() == Some(&workspace.package_json.version)
+                    && matches(locked, &workspace.package_json)
+            })
+        };
+        matches(root, package_json)
+            && root.extra.get("workspaces") == workspace_globs.as_ref()
+            && workspaces.iter().all(workspace_matches)
+    }
+
+    /// Lists every dependency of `package_json` and its `workspaces` that's missing
+    /// from the lockfile, or locked to a version that doesn't satisfy its range, along
+    /// with every dependency the lockfile has that they don't anymore.
+    ///
+    /// Unlike [`Lockfile::is_up_to_date`], this allows ranges to change, as long as
+    /// the locked versions still satisfy them.
+    pub fn mismatches(
+        &self,
+        package_json: &PackageJson,
+        workspaces: &[Workspace],
+    ) -> Vec<Mismatch> {
+        let mut mismatches = self.check("", package_json);
+        for workspace in workspaces {
+            let package_json = &workspace.package_json;
+            let range = format!("workspace:{}", package_json.version);
+            match self.packages.get(&workspace.path) {
+                None => mismatches.push(Mismatch::Missing {
+                    name: package_json.name.clone(),
+                    range,
+                }),
+                Some(locked) if locked.version.as_ref() != Some(&package_json.version) => {
+                    mismatches.push(Mismatch::Invalid {
+                        name: package_json.name.clone(),
+                        range,
+                        locked: locked.version.clone().unwrap_or_default(),
+                    });
+                }
+                Some(_) => {}
+            }
+            mismatches.extend(self.check(&workspace.path, package_json));
+        }
+        mismatches
+    }
+
+    /// Checks the dependencies of the package at `path` against `package_json`.
+    fn check(&self, path: &str, package_json: &PackageJson) -> Vec<Mismatch> {
+        let locked_entry = self.packages.get(path).cloned().unwrap_or_default();
+        let mut mismatches = Vec::new();
+        // Optional dependencies might not have been installed on this platform, so only
+        // their specs need to be locked.
+        let groups = [
+            (
+                &package_json.dependencies,
+                &locked_entry.dependencies,
+                false,
+            ),
+            (
+                &package_json.dev_dependencies,
+                &locked_entry.dev_dependencies,
+                false,
+            ),
+            (
+                &package_json.optional_dependencies,
+                &locked_entry.optional_dependencies,
+                true,
+            ),
+        ];
+        for (wanted, locked_ranges, optional) in groups {
+            let Some(wanted) = wanted else { continue };
+            for (name, range) in wanted.clone() {
+                let locked_range = locked_ranges.as_ref().and_then(|ranges| ranges.get(&name));
+                let locked_package =
+                    find(&self.packages, path, &name).and_then(|package| self.details(package));
+                let locked_version = locked_package.and_then(|package| package.version.clone());
+                if optional && locked_range.is_some() && locked_version.is_none() {
+                    continue;
+                }
+                let (Some(locked_range), Some(locked)) = (locked_range, locked_version) else {
+                    mismatches.push(Mismatch::Missing { name, range });
+                    continue;
+                };
+
+                let satisfies = |range: &str| {
+                    Range::parse(range).is_ok_and(|range| {
+                        Version::parse(&locked).is_ok_and(|version| range.satisfies(&version))
+                    })
+                };
+                let satisfied = match DependencySpec::parse(&range) {
+                    Ok(DependencySpec::Range(range)) => satisfies(&range),
+                    Ok(DependencySpec::Workspace(range)) => {
+                        matches!(range.trim(), "" | "*" | "^" | "~") || satisfies(&range)
+                    }
+                    Ok(DependencySpec::Alias { name: target, spec }) => {
+                        let locked_name = locked_package.and_then(|package| package.name.as_ref());
+                        locked_name == Some(&target)
+                            && matches!(*spec, DependencySpec::Range(range) if satisfies(&range))
+                    }
+                    // Anything else (like a dist-tag) has to be unchanged.
+                    _ => false,
+                } || *locked_range == range;
+                if !satisfied {
+                    mismatches.push(Mismatch::Invalid {
+                        name,
+                        range,
+                        locked,
+                    });
+                }
+            }
+        }
+
+        let wanted = [
+            &package_json.dependencies,
+            &package_json.dev_dependencies,
+            &package_json.optional_dependencies,
+            &package_json.peer_dependencies,
+        ];
+        let locked = [
+            &locked_entry.dependencies,
+            &locked_entry.dev_dependencies,
+            &locked_entry.optional_dependencies,
+            &locked_entry.peer_dependencies,
+        ];
+        let mut extraneous = Vec::new();
+        for (name, range) in locked.into_iter().flatten().flatten() {
+            let wanted = wanted
+                .into_iter()
+                .flatten()
+                .any(|deps| deps.contains_key(name));
+            let mismatch = Mismatch::Extraneous {
+                name: name.clone(),
+                range: range.clone(),
+            };
+            if !wanted && !extraneous.contains(&mismatch) {
+                extraneous.push(mismatch);
+            }
+        }
+        mismatches.extend(extraneous);
+        mismatches
+    }
+
+    /// Turns the lockfile back into a resolution, along with the tree it describes.
+    ///
+    /// # Errors
+    /// This function fails if the lockfile is missing a package that another one needs.
+    pub fn to_resolution(&self) -> Result<(Resolution, Layout), Error> {
+        let mut resolution = Resolution::default();
+        let mut layout = Layout::default();
+
+        let mut ids = BTreeMap::new();
+        for (path, package) in self.installed() {
+            let details = self
+                .details(package)
+                .ok_or_else(|| Error::MissingVersion(path.into()))?;
+            let name = match &details.name {
+                Some(name) => name.clone(),
+                None => dir_name(path).into(),
+            };
+            let version = details
+                .version
+                .clone()
+                .ok_or_else(|| Error::MissingVersion(path.into()))?;
+            let source = package.source().resolved();
+            ids.insert(
+                path,
+                PackageId {
+                    name,
+                    version,
+                    source,
+                },
+            );
+        }
+
+        for (path, package) in self.installed() {
+            let id = &ids[path];
+            layout.packages.insert(PathBuf::from(path), id.clone());
+            if resolution.packages.contains_key(id) {
+                continue;
+            }
+            let details = self.details(package).unwrap_or(package);
+            let source = package.source();
+            // A branch or tag could point somewhere else by the next install.
+            if let Source::Git { commit, .. } = &source {
+                if !dinopkg_git::is_commit_hash(commit) {
+                    return Err(Error::InvalidCommit {
+                        package: path.into(),
+                        commit: commit.clone(),
+                    });
+                }
+            }
+            let dist = match source {
+                Source::Registry => Dist {
+                    tarball: package.resolved.clone().unwrap_or_default(),
+                    integrity: package.integrity.clone(),
+                    shasum: None,
+                },
+                Source::Tarball { .. } => Dist {
+                    integrity: package.integrity.clone(),
+                    ..Default::default()
+                },
+                _ => Dist::default(),
+            };
+            // A link's dependencies are found from the directory it points at. Other
+            // than workspaces, links install their own, so they don't have any here.
+            let (dependencies, locked_dependencies) = match &package.resolved {
+                Some(target) if package.link => {
+                    let mut locked = self.link_dependencies(target, details, &ids)?;
+                    let dev = details.dev_dependencies.iter().flatten();
+                    locked.extend(self.find_all(target, dev, false, &ids)?);
+                    (details.dependencies.clone(), locked)
+                }
+                _ => (
+                    package.dependencies.clone(),
+                    self.link_dependencies(path, package, &ids)?,
+                ),
+            };
+            resolution.packages.insert(
+                id.clone(),
+                ResolvedPackage {
+                    id: id.clone(),
+                    source,
+                    dist: dist.clone(),
+                    manifest: Manifest {
+                        name: id.name.clone(),
+                        version: id.version.clone(),
+                        license: details.license.clone(),
+                        dependencies: dependencies.map(|deps| deps.into_iter().collect()),
+                        optional_dependencies: details
+                            .optional_dependencies
+                            .clone()
+                            .map(|deps| deps.into_iter().collect()),
+                        peer_dependencies: details
+                            .peer_dependencies
+                            .clone()
+                            .map(|deps| deps.into_iter().collect()),
+                        peer_dependencies_meta: details.extra_field("peerDependenciesMeta"),
+                        dev_dependencies: package
+                            .link
+                            .then(|| details.dev_dependencies.clone())
+                            .flatten(),
+                        bin: details.extra_field("bin").map(BinVariant::Map),
+                        engines: details.extra_field("engines"),
+                        os: details.extra_field("os"),
+                        cpu: details.extra_field("cpu"),
+                        deprecated: details.extra_field("deprecated"),
+                        has_install_script: details
+                            .extra_field("hasInstallScript")
+                            .unwrap_or_default(),
+                        dist,
+                    },
+                    dependencies: locked_dependencies,
+                    dev: package.dev,
+                    optional: package.optional,
+                    peer: package.peer,
+                },
+            );
+        }
+
+        if let Some(root) = self.packages.get("") {
+            resolution.dependencies = self.link_dependencies("", root, &ids)?;
+            resolution.dev_dependencies =
+                self.find_all("", root.dev_dependencies.iter().flatten(), false, &ids)?;
+        }
+        // Workspaces are linked from the root without being in its `package.json`.
+        for (path, package) in self.installed() {
+            let name = dir_name(path);
+            let top_level = path.strip_prefix("node_modules/") == Some(name);
+            if top_level && package.link && !resolution.dev_dependencies.contains_key(name) {
+                resolution
+                    .dependencies
+                    .entry(name.into())
+                    .or_insert_with(|| ids[path].clone());
+            }
+        }
+
+        Ok((resolution, layout))
+    }
+
+    /// Turns the lockfile into a resolution that `package_json` can be resolved from
+    /// again with [`dinopkg_resolver::resolve_from`], so only what's changed gets
+    /// resolved.
+    ///
+    /// The root's dependencies whose specs have changed since the lockfile was written
+    /// are left out, so they're resolved again.
+    ///
+    /// # Errors
+    /// This function fails for the same reasons as [`Lockfile::to_resolution`].
+    pub fn to_seed(&self, package_json: &PackageJson) -> Result<Resolution, Error> {
+        let (mut resolution, _) = self.to_resolution()?;
+        let root = self.packages.get("").cloned().unwrap_or_default();
+        let spec = |groups: [&Option<Dependencies>; 4], name: &str| {
+            groups
+                .into_iter()
+                .flatten()
+                .find_map(|dependencies| dependencies.get(name))
+                .cloned()
+        };
+        let unchanged = |name: &String| {
+            let locked = spec(
+                [
+                    &root.dependencies,
+                    &root.optional_dependencies,
+                    &root.peer_dependencies,
+                    &root.dev_dependencies,
+                ],
+                name,
+            );
+            let wanted = spec(
+                [
+                    &package_json.dependencies,
+                    &package_json.optional_dependencies,
+                    &package_json.peer_dependencies,
+                    &package_json.dev_dependencies,
+                ],
+                name,
+            );
+            locked.is_some() && locked == wanted
+        };
+        resolution.dependencies.retain(|name, _| unchanged(name));
+        resolution
+            .dev_dependencies
+            .retain(|name, _| unchanged(name));
+        Ok(resolution)
+    }
+
+    /// Packages that are installed into the root's `node_modules` (either directly or as
+    /// links), or into the `node_modules` of a directory that's linked.
+    ///
+    /// Other entries are the directories that links point at, which are only there for
+    /// their details.
+    fn installed(&self) -> impl Iterator<Item = (&str, &Package)> {
+        let targets: BTreeSet<&str> = self
+            .packages
+            .values()
+            .filter(|package| package.link)
+            .filter_map(|package| package.resolved.as_deref())
+            .collect();
+        self.packages
+            .iter()
+            .filter(move |(path, _)| {
+                path.starts_with("node_modules/")
+                    || path
+                        .find("/node_modules/")
+                        .is_some_and(|i| targets.contains(&path[..i]))
+            })
+            .map(|(path, package)| (path.as_str(), package))
+    }
+
+    /// The entry with a package's version and dependencies, which for a link is the
+    /// entry of the directory it points at.
+    fn details<'a>(&'a self, package: &'a Package) -> Option<&'a Package> {
+        if !package.link {
+            return Some(package);
+        }
+        self.packages.get(package.resolved.as_deref()?)
+    }
+
+    /// Finds what each of a package's (non-dev) dependencies resolves to from `path`.
+    fn link_dependencies(
+        &self,
+        path: &str,
+        package: &Package,
+        ids: &BTreeMap<&str, PackageId>,
+    ) -> Result<BTreeMap<String, PackageId>, Error> {
+        let mut dependencies =
+            self.find_all(path, package.dependencies.iter().flatten(), false, ids)?;
+        // These are allowed to be missing, because they're only installed when they can be.
+        let optional = package
+            .optional_dependencies
+            .iter()
+            .chain(&package.peer_dependencies)
+            .flatten();
+        dependencies.extend(self.find_all(path, optional, true, ids)?);
+        Ok(dependencies)
+    }
+
+    fn find_all<'a>(
+        &self,
+        path: &str,
+        names: impl Iterator<Item = (&'a String, &'a String)>,
+        optional: bool,
+        ids: &BTreeMap<&str, PackageId>,
+    ) -> Result<BTreeMap<String, PackageId>, Error> {
+        let mut found = BTreeMap::new();
+        for (name, _) in names {
+            match find(ids, path, name) {
+                Some(id) => {
+                    found.insert(name.clone(), id.clone());
+                }
+                None if optional => {}
+                None => {
+                    return Err(Error::MissingDependency {
+                        package: if path.is_empty() {
+                            self.name.clone().unwrap_or_default()
+                        } else {
+                            path.into()
+                        },
+                        dependency: name.clone(),
+                    })
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+impl fmt::Display for Lockfile {
+    /// Formats the lockfile exactly like npm would write it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        f.write_str(&format::stringify(&value, &self.style))
+    }
+}
+
+/// Finds the package Node would load for `require(name)` from the package at `path`.
+fn find<'a, K: Borrow<str> + Ord, V>(
+    ids: &'a BTreeMap<K, V>,
+    path: &str,
+    name: &str,
+) -> Option<&'a V> {
+    let mut base = path;
+    loop {
+        let candidate = if base.is_empty() {
+            format!("node_modules/{name}")
+        } else {
+            format!("{base}/node_modules/{name}")
+        };
+        if let Some(id) = ids.get(candidate.as_str()) {
+            return Some(id);
+        }
+        if base.is_empty() {
+            return None;
+        }
+        base = base.rfind("/node_modules/").map_or("", |i| &base[..i]);
+    }
+}
+
+/// The name of the directory a package is installed in, which may include a scope.
+fn dir_name(path: &str) -> &str {
+    path.rfind("node_modules/")
+        .map_or(path, |i| &path[i + "node_modules/".len()..])
+}
+
+/// Lockfiles always use forward slashes, even on Windows.
+fn path_key(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Package {
+    /// Reads one of the fields dino doesn't keep track of, if it's there and well-formed.
+    fn extra_field<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.extra.get(key)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    /// Where the package was installed from, going by its `resolved` field.
+    fn source(&self) -> Source {
+        match &self.resolved {
+            Some(target) if self.link => Source::Link {
+                path: target.clone(),
+            },
+            Some(resolved) => Source::from_resolved(resolved),
+            None => Source::default(),
+        }
+    }
+}
+
+/// The manifest fields npm copies into a lockfile entry, besides the dependencies.
+fn manifest_extras(manifest: &Manifest) -> BTreeMap<String, Value> {
+    let mut extras = BTreeMap::new();
+    if let Some(bin) = &manifest.bin {
+        // npm always writes `bin` as a map, named after the package if there's only one.
+        let bin = match bin {
+            BinVariant::Map(bin) => bin.clone().into_iter().collect(),
+            BinVariant::String(path) => {
+                let name = manifest.name.rsplit('/').next().unwrap_or_default();
+                BTreeMap::from([(name.to_owned(), path.clone())])
+            }
+        };
+        extras.insert("bin".into(), serde_json::json!(bin));
+    }
+    if let Some(meta) = &manifest.peer_dependencies_meta {
+        let meta: BTreeMap<_, _> = meta.iter().collect();
+        extras.insert("peerDependenciesMeta".into(), serde_json::json!(meta));
+    }
+    if let Some(engines) = &manifest.engines {
+        extras.insert("engines".into(), serde_json::json!(engines));
+    }
+    if let Some(os) = &manifest.os {
+        extras.insert("os".into(), serde_json::json!(os));
+    }
+    if let Some(cpu) = &manifest.cpu {
+        extras.insert("cpu".into(), serde_json::json!(cpu));
+    }
+    if let Some(deprecated) = &manifest.deprecated {
+        extras.insert("deprecated".into(), serde_json::json!(deprecated));
+    }
+    if manifest.has_install_script {
+        extras.insert("hasInstallScript".into(), Value::Bool(true));
+    }
+    extras
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dinopkg_package_json::Workspaces;
+    use maplit::btreemap;
+    use pretty_assertions::assert_eq;
+
+    const V3: &str = include_str!("../fixtures/package-lock-v3.json");
+    const V2: &str = include_str!("../fixtures/package-lock-v2.json");
+
+    #[test]
+    fn round_trips_npm_lockfiles() {
+        for json in [V3, V2] {
+            assert_eq!(Lockfile::parse(json).unwrap().to_string(), json);
+        }
+    }
+
+    #[test]
+    fn keeps_indentation_and_line_endings() {
+        let json = V3.replace("  ", "\t").replace('\n', "\r\n");
+        assert_eq!(Lockfile::parse(&json).unwrap().to_string(), json);
+    }
+
+    #[test]
+    fn rejects_old_lockfiles() {
+        assert!(matches!(
+            Lockfile::parse(r#"{"lockfileVersion": 1, "dependencies": {}}"#),
+            Err(Error::UnsupportedVersion(1))
+        ));
+    }
+
+    #[test]
+    fn reads_flags() {
+        let lockfile = Lockfile::parse(V3).unwrap();
+        assert!(lockfile.packages["node_modules/typescript"].dev);
+        assert!(lockfile.packages["node_modules/fsevents"].optional);
+        assert!(lockfile.packages["node_modules/react"].peer);
+        assert_eq!(
+            lockfile.packages["node_modules/left_pad"]
+                .integrity
+                .as_deref(),
+            Some("sha1-3M0dHqBKdSBvyoF/XZ4QB+9bbiM=")
+        );
+    }
+
+    #[test]
+    fn converts_to_a_resolution() {
+        let (resolution, layout) = Lockfile::parse(V3).unwrap().to_resolution().unwrap();
+        let id = |name: &str, version: &str| PackageId {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            layout.packages[Path::new("node_modules/react-dom/node_modules/scheduler")],
+            id("scheduler", "0.23.2")
+        );
+        assert_eq!(
+            resolution.dependencies.keys().collect::<Vec<_>>(),
+            [
+                "@babel/code-frame",
+                "fsevents",
+                "is-odd",
+                "left-pad",
+                "left_pad",
+                "react-dom"
+            ]
+        );
+        assert_eq!(
+            resolution.dev_dependencies["typescript"],
+            id("typescript", "5.5.3")
+        );
+        // Peer dependencies are linked up too.
+        let react_dom = &resolution.packages[&id("react-dom", "18.3.1")];
+        assert_eq!(react_dom.dependencies["react"], id("react", "18.3.1"));
+        assert_eq!(
+            react_dom.dependencies["scheduler"],
+            id("scheduler", "0.23.2")
+        );
+        assert_eq!(
+            resolution.packages[&id("is-odd", "3.0.1")].dist.tarball,
+            "https://registry.npmjs.org/is-odd/-/is-odd-3.0.1.tgz"
+        );
+    }
+
+    #[test]
+    fn keeps_version_2_lockfiles() {
+        let v2 = Lockfile::parse(V2).unwrap();
+        let (resolution, layout) = v2.to_resolution().unwrap();
+        let package_json = PackageJson {
+            name: "lock-fixture".into(),
+            version: "1.2.3".into(),
+            ..Default::default()
+        };
+        let mut rebuilt = Lockfile::from_resolution(&package_json, &resolution, &layout);
+        assert_eq!(rebuilt.lockfile_version, 3);
+        assert_eq!(rebuilt.dependencies, None);
+
+        rebuilt.inherit_style(&v2);
+        assert_eq!(rebuilt.lockfile_version, 2);
+        assert_eq!(rebuilt.dependencies, v2.dependencies);
+
+        let mut rebuilt = Lockfile::from_resolution(&package_json, &resolution, &layout);
+        rebuilt.inherit_style(&Lockfile::parse(V3).unwrap());
+        assert_eq!(rebuilt.dependencies, None);
+    }
+
+    #[test]
+    fn round_trips_through_a_resolution() {
+        let lockfile = Lockfile::parse(V3).unwrap();
+        let (resolution, layout) = lockfile.to_resolution().unwrap();
+        let package_json = PackageJson {
+            name: "lock-fixture".into(),
+            version: "1.2.3".into(),
+            dependencies: Some(btreemap! { "is-odd".into() => "^3.0.1".into() }),
+            ..Default::default()
+        };
+        let rebuilt = Lockfile::from_resolution(&package_json, &resolution, &layout);
+        assert_eq!(
+            rebuilt.packages.keys().collect::<Vec<_>>(),
+            lockfile.packages.keys().collect::<Vec<_>>()
+        );
+        for (path, package) in rebuilt.packages.iter().skip(1) {
+            let original = &lockfile.packages[path];
+            assert_eq!(package.version, original.version);
+            assert_eq!(package.resolved, original.resolved);
+            assert_eq!(package.integrity, original.integrity);
+            assert_eq!(package.dependencies, original.dependencies);
+            assert_eq!(
+                package.optional_dependencies,
+                original.optional_dependencies
+            );
+            assert_eq!(package.peer_dependencies, original.peer_dependencies);
+            assert_eq!(package.dev, original.dev);
+            assert_eq!(package.optional, original.optional);
+            assert_eq!(package.peer, original.peer);
+            for key in [
+                "bin",
+                "engines",
+                "os",
+                "deprecated",
+                "hasInstallScript",
+                "peerDependenciesMeta",
+            ] {
+                assert_eq!(package.extra.get(key), original.extra.get(key), "{key}");
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_local_dependencies() {
+        let lockfile = Lockfile::parse(
+            r#"{
+  "name": "app",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "app",
+      "version": "1.0.0",
+      "dependencies": {
+        "lib": "file:../lib",
+        "linked": "link:../linked",
+        "packed": "file:../packed.tgz"
+      }
+    },
+    "../linked": {
+      "name": "linked",
+      "version": "3.0.0",
+      "bin": { "linked": "cli.js" }
+    },
+    "node_modules/lib": {
+      "version": "1.0.0",
+      "resolved": "file:../lib"
+    },
+    "node_modules/linked": {
+      "resolved": "../linked",
+      "link": true
+    },
+    "node_modules/packed": {
+      "version": "2.0.0",
+      "resolved": "file:../packed.tgz",
+      "integrity": "sha512-AAAA"
+    }
+  }
+}
+"#,
+        )
+        .unwrap();
+        let (resolution, layout) = lockfile.to_resolution().unwrap();
+        let source = |name: &str| {
+            let id = &resolution.dependencies[name];
+            resolution.packages[id].source.clone()
+        };
+        assert_eq!(
+            source("lib"),
+            Source::Directory {
+                path: "../lib".into()
+            }
+        );
+        assert_eq!(
+            source("linked"),
+            Source::Link {
+                path: "../linked".into()
+            }
+        );
+        assert_eq!(
+            source("packed"),
+            Source::Tarball {
+                path: "../packed.tgz".into()
+            }
+        );
+
+        let package_json = PackageJson {
+            name: "app".into(),
+            version: "1.0.0".into(),
+            dependencies: lockfile.packages[""].dependencies.clone(),
+            ..Default::default()
+        };
+        let rebuilt = Lockfile::from_resolution(&package_json, &resolution, &layout);
+        assert_eq!(rebuilt.packages, lockfile.packages);
+        assert!(rebuilt.mismatches(&package_json, &[]).is_empty());
+    }
+
+    #[test]
+    fn round_trips_workspaces() {
+        let lockfile = Lockfile::parse(
+            r#"{
+  "name": "monorepo",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "monorepo",
+      "version": "1.0.0",
+      "workspaces": [
+        "packages/*"
+      ]
+    },
+    "node_modules/a": {
+      "resolved": "packages/a",
+      "link": true
+    },
+    "node_modules/debug": {
+      "version": "2.6.9",
+      "resolved": "https://registry.npmjs.org/debug/-/debug-2.6.9.tgz",
+      "integrity": "sha512-AAAA"
+    },
+    "packages/a": {
+      "name": "a",
+      "version": "1.0.0",
+      "dependencies": {
+        "debug": "^2.0.0",
+        "ms": "^2.0.0"
+      },
+      "devDependencies": {
+        "jest": "^29.0.0"
+      }
+    },
+    "packages/a/node_modules/jest": {
+      "version": "29.0.0",
+      "resolved": "https://registry.npmjs.org/jest/-/jest-29.0.0.tgz",
+      "integrity": "sha512-BBBB",
+      "dev": true
+    },
+    "packages/a/node_modules/ms": {
+      "version": "2.1.3",
+      "resolved": "https://registry.npmjs.org/ms/-/ms-2.1.3.tgz",
+      "integrity": "sha512-CCCC"
+    }
+  }
+}
+"#,
+        )
+        .unwrap();
+        let (resolution, layout) = lockfile.to_resolution().unwrap();
+        let id = |name: &str, version: &str| PackageId {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        let workspace = PackageId {
+            source: Some("packages/a".into()),
+            ..id("a", "1.0.0")
+        };
+        assert_eq!(resolution.dependencies["a"], workspace);
+        let a = &resolution.packages[&workspace];
+        assert_eq!(a.dependencies["debug"], id("debug", "2.6.9"));
+        assert_eq!(a.dependencies["jest"], id("jest", "29.0.0"));
+        assert_eq!(a.dependencies["ms"], id("ms", "2.1.3"));
+        assert_eq!(
+            layout.packages[Path::new("packages/a/node_modules/ms")],
+            id("ms", "2.1.3")
+        );
+
+        let package_json = PackageJson {
+            name: "monorepo".into(),
+            version: "1.0.0".into(),
+            workspaces: Some(Workspaces::Globs(vec!["packages/*".into()])),
+            ..Default::default()
+        };
+        let workspace = Workspace {
+            path: "packages/a".into(),
+            package_json: PackageJson {
+                name: "a".into(),
+                version: "1.0.0".into(),
+                dependencies: lockfile.packages["packages/a"].dependencies.clone(),
+                dev_dependencies: lockfile.packages["packages/a"].dev_dependencies.clone(),
+                ..Default::default()
+            },
+        };
+        let rebuilt = Lockfile::from_resolution(&package_json, &resolution, &layout);
+        assert_eq!(rebuilt.packages, lockfile.packages);
+        let workspaces = [workspace];
+        assert!(rebuilt.is_up_to_date(&package_json, &workspaces));
+        assert!(rebuilt.mismatches(&package_json, &workspaces).is_empty());
+
+        // npm 6 sees the workspace as a `file:` dependency, with what's nested in it.
+        let legacy = rebuilt.legacy_dependencies();
+        assert_eq!(legacy["a"]["version"], "file:packages/a");
+        assert_eq!(legacy["a"]["requires"]["debug"], "^2.0.0");
+        assert_eq!(legacy["a"]["dependencies"]["ms"]["version"], "2.1.3");
+        assert_eq!(legacy["a"]["dependencies"]["jest"]["dev"], true);
+
+        let [mut changed] = workspaces;
+        changed.package_json.version = "1.1.0".into();
+        changed.package_json.dependencies = Some(btreemap! {"ms".into() => "^3.0.0".into()});
+        assert!(!rebuilt.is_up_to_date(&package_json, &[changed.clone()]));
+        let mismatches: Vec<_> = rebuilt
+            .mismatches(&package_json, &[changed])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            mismatches,
+            [
+                "invalid: lockfile's a@1.0.0 does not satisfy a@workspace:1.1.0",
+                "invalid: lockfile's ms@2.1.3 does not satisfy ms@^3.0.0",
+                "extraneous: lockfile's debug@^2.0.0 is no longer in package.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_accepts_commit_hashes_for_git_dependencies() {
+        let lockfile = |resolved: &str| {
+            Lockfile::parse(&format!(
+                r#"{{
+  "lockfileVersion": 3,
+  "packages": {{
+    "": {{ "dependencies": {{ "lib": "github:ourco/lib" }} }},
+    "node_modules/lib": {{ "version": "1.0.0", "resolved": "{resolved}" }}
+  }}
+}}"#
+            ))
+            .unwrap()
+        };
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        let (resolution, _) = lockfile(&format!("git+ssh://git@github.com/ourco/lib.git#{commit}"))
+            .to_resolution()
+            .unwrap();
+        assert_eq!(resolution.dependencies["lib"].version, "1.0.0");
+        assert!(matches!(
+            lockfile("git+ssh://git@github.com/ourco/lib.git#main").to_resolution(),
+            Err(Error::InvalidCommit { commit, .. }) if commit == "main"
+        ));
+    }
+
+    #[test]
+    fn checks_package_json() {
+        let lockfile = Lockfile::parse(V3).unwrap();
+        let mut package_json = PackageJson {
+            name: "lock-fixture".into(),
+            version: "1.2.3".into(),
+            dependencies: Some(btreemap! {
+                "@babel/code-frame".into() => "^7.24.7".into(),
+                "is-odd".into() => "^3.0.1".into(),
+                "left_pad".into() => "1.0.0".into(),
+                "left-pad".into() => "^1.3.0".into(),
+                "react-dom".into() => "^18.3.1".into(),
+            }),
+            dev_dependencies: Some(btreemap! { "typescript".into() => "^5.5.3".into() }),
+            optional_dependencies: Some(btreemap! { "fsevents".into() => "~2.3.2".into() }),
+            ..Default::default()
+        };
+        assert!(lockfile.is_up_to_date(&package_json, &[]));
+
+        let mut changed = package_json.clone();
+        changed.optional_dependencies = None;
+        assert!(!lockfile.is_up_to_date(&changed, &[]));
+
+        package_json
+            .dependencies
+            .as_mut()
+            .unwrap()
+            .insert("is-odd".into(), "^4.0.0".into());
+        assert!(!lockfile.is_up_to_date(&package_json, &[]));
+    }
+
+    #[test]
+    fn seeds_unchanged_dependencies() {
+        let lockfile = Lockfile::parse(V3).unwrap();
+        let package_json = PackageJson {
+            name: "lock-fixture".into(),
+            version: "1.2.3".into(),
+            dependencies: Some(btreemap! {
+                "@babel/code-frame".into() => "^7.24.7".into(),
+                "is-odd".into() => "^4.0.0".into(),
+                "left-pad".into() => "^1.3.0".into(),
+                "lodash".into() => "^4.17.21".into(),
+            }),
+            dev_dependencies: Some(btreemap! { "typescript".into() => "^5.5.3".into() }),
+            ..Default::default()
+        };
+        let (resolution, _) = lockfile.to_resolution().unwrap();
+        let seed = lockfile.to_seed(&package_json).unwrap();
+        assert_eq!(
+            seed.dependencies.keys().collect::<Vec<_>>(),
+            ["@babel/code-frame", "left-pad"]
+        );
+        assert_eq!(
+            seed.dev_dependencies.keys().collect::<Vec<_>>(),
+            ["typescript"]
+        );
+        assert_eq!(seed.packages, resolution.packages);
+    }
+
+    #[test]
+    fn lists_mismatches() {
+        let lockfile = Lockfile::parse(V3).unwrap();
+        let package_json = PackageJson {
+            name: "lock-fixture".into(),
+            version: "1.2.3".into(),
+            dependencies: Some(btreemap! {
+                "@babel/code-frame".into() => "^7.24.7".into(),
+                // Changed, but still satisfied.
+                "is-odd".into() => "^3.0.0".into(),
+                "left-pad".into() => "^2.0.0".into(),
+                "lodash".into() => "^4.17.21".into(),
+                "react-dom".into() => "^18.3.1".into(),
+            }),
+            dev_dependencies: Some(btreemap! { "typescript".into() => "latest".into() }),
+            optional_dependencies: Some(btreemap! { "fsevents".into() => "~2.3.2".into() }),
+            ..Default::default()
+        };
+        let mismatches: Vec<_> = lockfile
+            .mismatches(&package_json, &[])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            mismatches,
+            [
+                "invalid: lockfile's left-pad@1.3.0 does not satisfy left-pad@^2.0.0",
+                "missing: lodash@^4.17.21 from lockfile",
+                "invalid: lockfile's typescript@5.5.3 does not satisfy typescript@latest",
+                "extraneous: lockfile's left_pad@1.0.0 is no longer in package.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_aliases_against_the_aliased_package() {
+        let lockfile = Lockfile::parse(
+            r#"{
+  "name": "aliases",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "aliases",
+      "version": "1.0.0",
+      "dependencies": { "lodash3": "npm:lodash@^3.10.0" }
+    },
+    "node_modules/lodash3": {
+      "name": "lodash",
+      "version": "3.10.1",
+      "resolved": "https://registry.npmjs.org/lodash/-/lodash-3.10.1.tgz"
+    }
+  }
+}
+"#,
+        )
+        .unwrap();
+        let mismatches = |spec: &str| {
+            let package_json = PackageJson {
+                name: "aliases".into(),
+                version: "1.0.0".into(),
+                dependencies: Some(btreemap! { "lodash3".into() => spec.into() }),
+                ..Default::default()
+            };
+            lockfile
+                .mismatches(&package_json, &[])
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert!(mismatches("npm:lodash@^3.0.0").is_empty());
+        assert_eq!(mismatches("npm:lodash@^4.0.0").len(), 1);
+        assert_eq!(mismatches("npm:underscore@^3.0.0").len(), 1);
+    }
+
+    #[test]
+    fn finds_dependencies_like_node() {
+        let ids: BTreeMap<&str, PackageId> = [
+            "node_modules/a",
+            "node_modules/@s/b",
+            "node_modules/a/node_modules/@s/b",
+        ]
+        .into_iter()
+        .map(|path| {
+            let id = PackageId {
+                name: dir_name(path).into(),
+                version: path.into(),
+                ..Default::default()
+            };
+            (path, id)
+        })
+        .collect();
+        let found = |path, name| find(&ids, path, name).map(|id| id.version.as_str());
+
+        assert_eq!(
+            found("node_modules/a", "@s/b"),
+            Some("node_modules/a/node_modules/@s/b")
+        );
+        assert_eq!(
+            found("node_modules/a/node_modules/@s/b", "@s/b"),
+            Some("node_modules/a/node_modules/@s/b")
+        );
+        assert_eq!(
+            found("node_modules/@s/b", "@s/b"),
+            Some("node_modules/@s/b")
+        );
+        assert_eq!(found("node_modules/@s/b", "a"), Some("node_modules/a"));
+        assert_eq!(found("", "c"), None);
+    }
+}
@@ -0,0 +1,381 @@
+//! An HTTP cache for packuments.
+//!
+//! Responses are stored along with their `ETag` and `Last-Modified` headers. While a
+//! response is fresh (according to its `Cache-Control` header) it's used as-is, and
+//! once it goes stale it's revalidated with a conditional request, so unchanged
+//! packuments aren't downloaded again.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{self, HeaderMap};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+/// A cached response.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+
+    /// When the response was generated, in seconds since the Unix epoch.
+    pub date: u64,
+
+    /// How many seconds after `date` the response can be used without revalidating it.
+    /// If this is `None`, it always has to be revalidated.
+    pub max_age: Option<u64>,
+
+    pub body: String,
+}
+
+impl CacheEntry {
+    /// Returns whether the entry can be used without asking the registry first.
+    pub fn is_fresh(&self) -> bool {
+        self.max_age
+            .is_some_and(|max_age| now().saturating_sub(self.date) < max_age)
+    }
+
+    /// Updates the entry's freshness from a new response's headers.
+    pub(crate) fn refresh(&mut self, headers: &HeaderMap) {
+        let policy = CachePolicy::from_headers(headers);
+        self.date = policy.date;
+        self.max_age = policy.max_age;
+        if let Some(etag) = header_string(headers, header::ETAG) {
+            self.etag = Some(etag);
+        }
+        if let Some(last_modified) = header_string(headers, header::LAST_MODIFIED) {
+            self.last_modified = Some(last_modified);
+        }
+    }
+}
+
+/// Somewhere to keep cached responses.
+pub trait CacheStore {
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<CacheEntry>, Error>>;
+    fn put(&self, key: &str, entry: &CacheEntry) -> impl Future<Output = Result<(), Error>>;
+}
+
+/// Keeps cached responses on disk, one file per URL.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/dino/packuments` on Linux, and the platform's equivalent elsewhere.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("dino").join("packuments"))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = hex::encode(Sha256::digest(key.as_bytes()));
+        self.dir.join(format!("{hash}.json"))
+    }
+}
+
+impl CacheStore for DiskCache {
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+        match tokio::fs::read(self.path(key)).await {
+            // A corrupted entry is no worse than a missing one.
+            Ok(contents) => Ok(serde_json::from_slice(&contents).ok()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, entry: &CacheEntry) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let contents = serde_json::to_vec(entry).map_err(io::Error::other)?;
+        // Write somewhere else first, so readers never see half-written entries.
+        let file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        tokio::fs::write(file.path(), contents).await?;
+        file.persist(self.path(key)).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+/// Keeps cached responses in memory, which is mostly useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStore for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, entry: &CacheEntry) -> Result<(), Error> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.into(), entry.clone());
+        Ok(())
+    }
+}
+
+/// What a response's headers say about caching it.
+pub(crate) struct CachePolicy {
+    pub no_store: bool,
+    pub date: u64,
+    pub max_age: Option<u64>,
+}
+
+impl CachePolicy {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let mut no_store = false;
+        let mut no_cache = false;
+        let mut max_age = None;
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            let Ok(value) = value.to_str() else { continue };
+            for directive in value.split(',') {
+                let directive = directive.trim().to_ascii_lowercase();
+                match directive.split_once('=') {
+                    Some(("max-age", seconds)) => {
+                        max_age = seconds.trim_matches('"').parse::<u64>().ok();
+                    }
+                    _ if directive == "no-store" => no_store = true,
+                    _ if directive == "no-cache" => no_cache = true,
+                    _ => {}
+                }
+            }
+        }
+
+        // If the response came from a shared cache, it's already `Age` seconds old.
+        let age = header_string(headers, header::AGE)
+            .and_then(|age| age.parse::<u64>().ok())
+            .unwrap_or(0);
+        Self {
+            no_store,
+            date: now().saturating_sub(age),
+            max_age: if no_cache { None } else { max_age },
+        }
+    }
+}
+
+pub(crate) fn header_string(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(Into::into)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use crate::{CacheMode, PackageInfo, Packument, RegistryClient, CORGI_ACCEPT, FULL_ACCEPT};
+    use pretty_assertions::assert_eq;
+
+    const PACKUMENT: &str = r#"{"name":"a","versions":{},"dist-tags":{"latest":"1.0.0"}}"#;
+
+    fn client() -> RegistryClient<MemoryCache> {
+        RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_etag() {
+        let server = TestServer::start(|request| {
+            if request.headers.get("if-none-match").map(String::as_str) == Some("\"v1\"") {
+                Response::new(304).header("cache-control", "max-age=0")
+            } else {
+                Response::new(200)
+                    .header("etag", "\"v1\"")
+                    .header("cache-control", "max-age=0")
+                    .body(PACKUMENT)
+            }
+        })
+        .await;
+        let client = client();
+        let url = format!("{}/a", server.url);
+
+        for _ in 0..2 {
+            let package_info = client
+                .document::<PackageInfo>(&url, FULL_ACCEPT)
+                .await
+                .unwrap();
+            assert_eq!(package_info.dist_keys["latest"], "1.0.0");
+        }
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].headers.get("if-none-match"), None);
+        assert_eq!(requests[1].headers["if-none-match"], "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_last_modified() {
+        let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let server = TestServer::start(move |request| {
+            if request.headers.contains_key("if-modified-since") {
+                Response::new(304)
+            } else {
+                Response::new(200)
+                    .header("last-modified", last_modified)
+                    .body(PACKUMENT)
+            }
+        })
+        .await;
+        let client = client();
+        let url = format!("{}/a", server.url);
+
+        client
+            .document::<PackageInfo>(&url, FULL_ACCEPT)
+            .await
+            .unwrap();
+        client
+            .document::<PackageInfo>(&url, FULL_ACCEPT)
+            .await
+            .unwrap();
+        assert_eq!(
+            server.requests()[1].headers["if-modified-since"],
+            last_modified
+        );
+    }
+
+    #[tokio::test]
+    async fn uses_fresh_responses_without_asking() {
+        let server = TestServer::start(|_| {
+            Response::new(200)
+                .header("cache-control", "public, max-age=300")
+                .body(PACKUMENT)
+        })
+        .await;
+        let client = client();
+        let url = format!("{}/a", server.url);
+
+        client
+            .document::<PackageInfo>(&url, FULL_ACCEPT)
+            .await
+            .unwrap();
+        client
+            .document::<PackageInfo>(&url, FULL_ACCEPT)
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn respects_no_store_and_age() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/no-store" => Response::new(200)
+                .header("cache-control", "no-store, max-age=300")
+                .body(PACKUMENT),
+            _ => Response::new(200)
+                .header("cache-control", "max-age=300")
+                .header("age", "300")
+                .body(PACKUMENT),
+        })
+        .await;
+        let client = client();
+
+        for path in ["no-store", "old"] {
+            let url = format!("{}/{path}", server.url);
+            client
+                .document::<PackageInfo>(&url, FULL_ACCEPT)
+                .await
+                .unwrap();
+            client
+                .document::<PackageInfo>(&url, FULL_ACCEPT)
+                .await
+                .unwrap();
+        }
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn caches_each_accept_header_separately() {
+        let server = TestServer::start(|request| {
+            Response::new(200)
+                .header("cache-control", "max-age=300")
+                .header("content-type", &request.headers["accept"])
+                .body(PACKUMENT)
+        })
+        .await;
+        let client = client();
+        let url = format!("{}/a", server.url);
+
+        for _ in 0..2 {
+            client
+                .document::<Packument>(&url, CORGI_ACCEPT)
+                .await
+                .unwrap();
+            client
+                .document::<PackageInfo>(&url, FULL_ACCEPT)
+                .await
+                .unwrap();
+        }
+        let accepts: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.headers["accept"].clone())
+            .collect();
+        assert_eq!(accepts, [CORGI_ACCEPT, FULL_ACCEPT]);
+    }
+
+    #[tokio::test]
+    async fn offline_never_uses_the_network() {
+        let server =
+            TestServer::start(|_| Response::new(200).header("etag", "\"v1\"").body(PACKUMENT))
+                .await;
+        let cache = MemoryCache::new();
+        let (cached, missing) = (format!("{}/a", server.url), format!("{}/b", server.url));
+        let online = RegistryClient::new(reqwest::Client::new(), cache);
+        online
+            .document::<PackageInfo>(&cached, FULL_ACCEPT)
+            .await
+            .unwrap();
+
+        let offline = online.with_mode(CacheMode::Offline);
+        offline
+            .document::<PackageInfo>(&cached, FULL_ACCEPT)
+            .await
+            .unwrap();
+        assert!(matches!(
+            offline.document::<PackageInfo>(&missing, FULL_ACCEPT).await,
+            Err(Error::NotCached(url)) if url == missing
+        ));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn disk_cache_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().join("packuments"));
+        let entry = CacheEntry {
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+            date: 1,
+            max_age: Some(300),
+            body: PACKUMENT.into(),
+        };
+
+        assert_eq!(cache.get("https://example.com/a").await.unwrap(), None);
+        cache.put("https://example.com/a", &entry).await.unwrap();
+        assert_eq!(
+            cache.get("https://example.com/a").await.unwrap(),
+            Some(entry)
+        );
+    }
+}
@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::cache::{header_string, CachePolicy};
+use crate::{
+    tarball, Auth, CacheEntry, CacheStore, Dist, Error, PackageInfo, Packument, RetryPolicy,
+    CORGI_ACCEPT, DEFAULT_REGISTRY, FULL_ACCEPT,
+};
+
+/// How many requests can be in flight at once by default, like npm's `maxsockets`.
+pub const DEFAULT_MAX_SOCKETS: usize = 15;
+
+/// Documents that are being fetched right now, so concurrent requests for the same one
+/// can wait for it instead of fetching it again. Each one holds the body once it's fetched.
+type InFlight = Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<String>>>>>;
+
+/// How much a [`RegistryClient`] relies on the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+    /// Stale responses are always revalidated.
+    #[default]
+    Online,
+
+    /// Stale responses can be used without revalidating them, if the caller is
+    /// happy with them. See [`RegistryClient::cached_packument`].
+    PreferOffline,
+
+    /// The network is never used, and anything that isn't cached is an error.
+    Offline,
+}
+
+/// Talks to npm registries, caching packuments in a [`CacheStore`].
+///
+/// Packages come from the default registry unless their scope has its own,
+/// like `@ourco:registry=https://npm.ourco.com/` in an `.npmrc`. Requests to registries
+/// that need credentials get them from its [`Auth`].
+///
+/// Requests that fail because of the network or the registry having a bad day are
+/// retried according to its [`RetryPolicy`], and only so many can run at once. Clones
+/// share the same limit.
+#[derive(Debug, Clone)]
+pub struct RegistryClient<S> {
+    client: reqwest::Client,
+    cache: S,
+    mode: CacheMode,
+    registry: String,
+    scopes: HashMap<String, String>,
+    auth: Auth,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    permits: Arc<Semaphore>,
+    in_flight: Arc<InFlight>,
+}
+
+impl<S: CacheStore> RegistryClient<S> {
+    pub fn new(client: reqwest::Client, cache: S) -> Self {
+        Self {
+            client,
+            cache,
+            mode: CacheMode::default(),
+            registry: DEFAULT_REGISTRY.into(),
+            scopes: HashMap::new(),
+            auth: Auth::new(),
+            retry: RetryPolicy::default(),
+            timeout: None,
+            permits: Arc::new(Semaphore::new(DEFAULT_MAX_SOCKETS)),
+            in_flight: Arc::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Uses `url` instead of the npm registry for packages without a registry of their own.
+    #[must_use]
+    pub fn with_registry(mut self, url: impl Into<String>) -> Self {
+        self.registry = url.into();
+        self
+    }
+
+    /// Uses `url` for every package in `scope`. The scope's `@` is optional.
+    #[must_use]
+    pub fn with_scope_registry(mut self, scope: &str, url: impl Into<String>) -> Self {
+        let scope = scope.strip_prefix('@').unwrap_or(scope);
+        self.scopes.insert(format!("@{scope}"), url.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Gives up on requests that take longer than `timeout`, like npm's `fetch-timeout`.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Allows at most `max_sockets` requests at once, like npm's `maxsockets`.
+    #[must_use]
+    pub fn with_max_sockets(mut self, max_sockets: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(max_sockets.max(1)));
+        self
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    /// The underlying HTTP client. Requests made with it directly don't have credentials.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Starts a GET request for `url`, with credentials if its registry needs them.
+    ///
+    /// Credentials are only ever sent to the host they're for. If the registry redirects
+    /// somewhere else, `reqwest` drops the `Authorization` header.
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let mut request = self.client.get(url);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        match self.auth.for_url(url) {
+            Some(credentials) => request.header(header::AUTHORIZATION, credentials.header_value()),
+            None => request,
+        }
+    }
+
+    /// Downloads the tarball described by `dist`, verifying it as it streams in.
+    ///
+    /// # Errors
+    /// This function fails if the download fails, or if the tarball doesn't match its integrity.
+    pub async fn download_tarball(&self, dist: &Dist) -> Result<Vec<u8>, Error> {
+        self.retrying(|| async {
+            let _permit = self.permit().await;
+            tarball::download(self.get(&dist.tarball), dist).await
+        })
+        .await
+    }
+
+    /// Runs `attempt` until it succeeds, fails in a way that retrying won't fix, or runs
+    /// out of retries.
+    async fn retrying<T, F: Future<Output = Result<T, Error>>>(
+        &self,
+        mut attempt: impl FnMut() -> F,
+    ) -> Result<T, Error> {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(err) if retries < self.retry.retries && err.is_retryable() => {
+                    let delay = match err {
+                        Error::RateLimited {
+                            retry_after: Some(after),
+                            ..
+                        } => after.min(self.retry.max_timeout),
+                        _ => self.retry.backoff(retries),
+                    };
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn permit(&self) -> SemaphorePermit<'_> {
+        self.permits
+            .acquire()
+            .await
+            .expect("the semaphore is never closed")
+    }
+
+    /// The registry that `package_name` comes from.
+    pub fn registry_for(&self, package_name: &str) -> &str {
+        package_name
+            .split_once('/')
+            .and_then(|(scope, _)| self.scopes.get(scope))
+            .unwrap_or(&self.registry)
+    }
+
+    /// The URL of `package_name`'s packument.
+    pub fn package_url(&self, package_name: &str) -> String {
+        package_url(self.registry_for(package_name), package_name)
+    }
+
+    /// Fetches the abbreviated packument for `package_name`. This is all that's needed
+    /// to install a package.
+    ///
+    /// # Errors
+    /// This function fails if the packument can't be fetched or parsed.
+    pub async fn packument(&self, package_name: &str) -> Result<Packument, Error> {
+        self.document(&self.package_url(package_name), CORGI_ACCEPT)
+            .await
+            .map_err(|err| not_found(err, package_name, self.registry_for(package_name)))
+    }
+
+    /// Fetches the full packument for `package_name`.
+    ///
+    /// # Errors
+    /// This function fails if the packument can't be fetched or parsed.
+    pub async fn package_info(&self, package_name: &str) -> Result<PackageInfo, Error> {
+        self.document(&self.package_url(package_name), FULL_ACCEPT)
+            .await
+            .map_err(|err| not_found(err, package_name, self.registry_for(package_name)))
+    }
+
+    /// Returns the cached abbreviated packument for `package_name` without asking the
+    /// registry, even if it's stale. This only returns something in
+    /// [`CacheMode::PreferOffline`], where it's up to the caller to decide whether the
+    /// packument is good enough, and to fall back to [`Self::packument`] if it isn't.
+    ///
+    /// # Errors
+    /// This function fails if the cache can't be read, or the packument can't be parsed.
+    pub async fn cached_packument(&self, package_name: &str) -> Result<Option<Packument>, Error> {
+        if self.mode != CacheMode::PreferOffline {
+            return Ok(None);
+        }
+        let url = self.package_url(package_name);
+        match self.cache.get(&cache_key(&url, CORGI_ACCEPT)).await? {
+            Some(entry) => Ok(Some(parse(&url, &entry.body)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Fetches the JSON document at `url`, using the cache when possible.
+    ///
+    /// The same URL can return different documents depending on `accept`, so they're
+    /// cached separately. If the same document is already being fetched, this waits for
+    /// it instead of fetching it again.
+    ///
+    /// # Errors
+    /// This function fails if the document can't be fetched or parsed. A 404 is
+    /// [`Error::Status`], since only the caller knows what was missing. In
+    /// [`CacheMode::Offline`], it fails with [`Error::NotCached`] if the document
+    /// isn't cached.
+    pub async fn document<T: DeserializeOwned>(&self, url: &str, accept: &str) -> Result<T, Error> {
+        let key = cache_key(url, accept);
+        let slot = Arc::clone(
+            self.in_flight
+                .lock()
+                .unwrap()
+                .entry(key.clone())
+                .or_default(),
+        );
+        let mut fetched = slot.lock().await;
+        let body = match &*fetched {
+            Some(body) => body.clone(),
+            None => {
+                let result = self.fetch(url, accept, &key).await;
+                if let Ok(body) = &result {
+                    *fetched = Some(body.clone());
+                }
+                let mut in_flight = self.in_flight.lock().unwrap();
+                if in_flight
+                    .get(&key)
+                    .is_some_and(|current| Arc::ptr_eq(current, &slot))
+                {
+                    in_flight.remove(&key);
+                }
+                result?
+            }
+        };
+        parse(url, &body)
+    }
+
+    /// Fetches the body of the document at `url`, using the cache when possible.
+    async fn fetch(&self, url: &str, accept: &str, key: &str) -> Result<String, Error> {
+        let cached = self.cache.get(key).await?;
+        if self.mode == CacheMode::Offline {
+            let entry = cached.ok_or_else(|| Error::NotCached(url.into()))?;
+            return Ok(entry.body);
+        }
+        if let Some(entry) = cached.as_ref().filter(|entry| entry.is_fresh()) {
+            return Ok(entry.body.clone());
+        }
+
+        let cached = &cached;
+        let response = self
+            .retrying(|| async move {
+                let mut request = self.get(url).header(header::ACCEPT, accept);
+                if let Some(entry) = cached {
+                    if let Some(etag) = &entry.etag {
+                        request = request.header(header::IF_NONE_MATCH, etag);
+                    }
+                    if let Some(last_modified) = &entry.last_modified {
+                        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+                    }
+                }
+                let _permit = self.permit().await;
+                let response = request.send().await?;
+                if let (StatusCode::NOT_MODIFIED, Some(entry)) = (response.status(), cached) {
+                    return Ok(Fetched::NotModified(
+                        entry.clone(),
+                        response.headers().clone(),
+                    ));
+                }
+                let response = Error::check_status(response)?;
+                let headers = response.headers().clone();
+                Ok(Fetched::Modified(headers, response.text().await?))
+            })
+            .await?;
+
+        match response {
+            Fetched::NotModified(mut entry, headers) => {
+                entry.refresh(&headers);
+                self.cache.put(key, &entry).await?;
+                Ok(entry.body)
+            }
+            Fetched::Modified(headers, body) => {
+                self.store(key, &headers, &body).await?;
+                Ok(body)
+            }
+        }
+    }
+
+    /// Caches a response, unless it says not to.
+    async fn store(&self, key: &str, headers: &HeaderMap, body: &str) -> Result<(), Error> {
+        let policy = CachePolicy::from_headers(headers);
+        if policy.no_store {
+            return Ok(());
+        }
+        let entry = CacheEntry {
+            etag: header_string(headers, header::ETAG),
+            last_modified: header_string(headers, header::LAST_MODIFIED),
+            date: policy.date,
+            max_age: policy.max_age,
+            body: body.into(),
+        };
+        self.cache.put(key, &entry).await
+    }
+}
+
+enum Fetched {
+    /// The cached entry is still up to date.
+    NotModified(CacheEntry, HeaderMap),
+    Modified(HeaderMap, String),
+}
+
+/// The URL of `package_name`'s packument on `registry`.
+///
+/// Registries expect the `/` in scoped names to be escaped, so `@scope/name` is
+/// requested as `@scope%2fname`.
+pub fn package_url(registry: &str, package_name: &str) -> String {
+    let registry = registry.trim_end_matches('/');
+    let escaped = package_name.replace('/', "%2f");
+    format!("{registry}/{escaped}")
+}
+
+fn cache_key(url: &str, accept: &str) -> String {
+    format!("{url}\n{accept}")
+}
+
+fn parse<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, Error> {
+    serde_json::from_str(body).map_err(|source| Error::MalformedPackument {
+        url: url.into(),
+        source,
+    })
+}
+
+/// A 404 for a packument means the package doesn't exist.
+fn not_found(err: Error, package_name: &str, registry: &str) -> Error {
+    match err {
+        Error::Status {
+            status: StatusCode::NOT_FOUND,
+            ..
+        } => Error::PackageNotFound {
+            name: package_name.into(),
+            registry: registry.into(),
+        },
+        err => err,
+    }
+}
+
+/// Reads a packument from an uncached response.
+pub(crate) async fn parse_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+    package_name: &str,
+    registry: &str,
+) -> Result<T, Error> {
+    let url = response.url().to_string();
+    let response =
+        Error::check_status(response).map_err(|err| not_found(err, package_name, registry))?;
+    parse(&url, &response.text().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use crate::{Credentials, Integrity, MemoryCache};
+    use pretty_assertions::assert_eq;
+    use sha2::{Digest, Sha512};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    const PACKUMENT: &str = r#"{"name":"a","versions":{},"dist-tags":{"latest":"1.0.0"}}"#;
+
+    #[test]
+    fn escapes_scoped_names() {
+        assert_eq!(
+            package_url("https://registry.npmjs.org/", "@types/node"),
+            "https://registry.npmjs.org/@types%2fnode"
+        );
+        assert_eq!(
+            package_url("https://npm.ourco.com/api/npm", "left-pad"),
+            "https://npm.ourco.com/api/npm/left-pad"
+        );
+    }
+
+    #[test]
+    fn picks_registries_by_scope() {
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry("https://mirror.example.com/")
+            .with_scope_registry("@ourco", "https://npm.ourco.com/")
+            .with_scope_registry("theirco", "https://npm.theirco.com/");
+
+        assert_eq!(client.registry_for("react"), "https://mirror.example.com/");
+        assert_eq!(client.registry_for("@ourco/ui"), "https://npm.ourco.com/");
+        assert_eq!(
+            client.registry_for("@theirco/ui"),
+            "https://npm.theirco.com/"
+        );
+        assert_eq!(
+            client.registry_for("@other/ui"),
+            "https://mirror.example.com/"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_scoped_packages_from_their_registry() {
+        let default = TestServer::start(|_| Response::new(404)).await;
+        let scoped = TestServer::start(|_| Response::new(200).body(PACKUMENT)).await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&default.url)
+            .with_scope_registry("@ourco", format!("{}/npm/", scoped.url));
+
+        client.packument("@ourco/a").await.unwrap();
+        assert!(default.requests().is_empty());
+        assert_eq!(scoped.requests()[0].path, "/npm/@ourco%2fa");
+    }
+
+    #[tokio::test]
+    async fn only_prefer_offline_hands_out_stale_packuments() {
+        let cache = MemoryCache::new();
+        let entry = CacheEntry {
+            etag: None,
+            last_modified: None,
+            date: 0,
+            max_age: None,
+            body: PACKUMENT.into(),
+        };
+        let key = cache_key(&package_url(DEFAULT_REGISTRY, "a"), CORGI_ACCEPT);
+        cache.put(&key, &entry).await.unwrap();
+        let client = RegistryClient::new(reqwest::Client::new(), cache);
+
+        assert!(client.cached_packument("a").await.unwrap().is_none());
+        let client = client.with_mode(CacheMode::PreferOffline);
+        assert!(client.cached_packument("a").await.unwrap().is_some());
+        assert!(client.cached_packument("b").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_credentials_for_packuments_and_tarballs() {
+        let server = TestServer::start(|request| {
+            match request.headers.get("authorization").map(String::as_str) {
+                Some("Bearer s3cret") if request.path.ends_with(".tgz") => {
+                    Response::new(200).body("tarball")
+                }
+                Some("Bearer s3cret") => Response::new(200).body(PACKUMENT),
+                _ => Response::new(401),
+            }
+        })
+        .await;
+        let registry = format!("{}/npm/", server.url);
+        let auth = Auth::new().with_credentials(&registry, Credentials::Token("s3cret".into()));
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&registry)
+            .with_auth(auth);
+
+        client.packument("a").await.unwrap();
+        let dist = Dist {
+            tarball: format!("{registry}a/-/a-1.0.0.tgz"),
+            integrity: Some(Integrity::Sha512(Sha512::digest(b"tarball").to_vec()).to_string()),
+            shasum: None,
+        };
+        assert_eq!(client.download_tarball(&dist).await.unwrap(), b"tarball");
+
+        // Without credentials, the registry turns us away.
+        let anonymous = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&registry);
+        assert!(anonymous.packument("a").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn never_sends_credentials_to_other_hosts() {
+        let elsewhere = TestServer::start(|_| Response::new(200).body("tarball")).await;
+        let tarball_url = format!("{}/a-1.0.0.tgz", elsewhere.url);
+        let registry = TestServer::start(move |request| {
+            if request.path.ends_with(".tgz") {
+                Response::new(302).header("location", &tarball_url)
+            } else {
+                Response::new(200).body(PACKUMENT)
+            }
+        })
+        .await;
+        let auth = Auth::new().with_credentials(&registry.url, Credentials::basic("me", "pass"));
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&registry.url)
+            .with_auth(auth);
+
+        let dist = Dist {
+            tarball: format!("{}/a/-/a-1.0.0.tgz", registry.url),
+            integrity: Some(Integrity::Sha512(Sha512::digest(b"tarball").to_vec()).to_string()),
+            shasum: None,
+        };
+        client.download_tarball(&dist).await.unwrap();
+        assert_eq!(
+            registry.requests()[0].headers["authorization"],
+            "Basic bWU6cGFzcw=="
+        );
+        assert_eq!(elsewhere.requests()[0].headers.get("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn explains_failed_requests() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/private" => Response::new(401),
+            "/forbidden" => Response::new(403),
+            "/popular" => Response::new(429).header("retry-after", "30"),
+            "/broken" => Response::new(200).body("<html>oops</html>"),
+            "/down" => Response::new(503),
+            _ => Response::new(404),
+        })
+        .await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&server.url)
+            .with_retry_policy(RetryPolicy::none());
+
+        let err = client.packument("typo").await.unwrap_err();
+        assert!(
+            matches!(&err, Error::PackageNotFound { name, registry } if name == "typo" && *registry == server.url),
+            "{err}"
+        );
+        let err = client.packument("private").await.unwrap_err();
+        assert!(matches!(&err, Error::Unauthorized { url } if url.ends_with("/private")));
+        let err = client.packument("forbidden").await.unwrap_err();
+        assert!(matches!(err, Error::Forbidden { .. }));
+        let err = client.packument("popular").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited { retry_after: Some(after), .. } if after.as_secs() == 30
+        ));
+        let err = client.packument("broken").await.unwrap_err();
+        assert!(matches!(&err, Error::MalformedPackument { url, .. } if url.ends_with("/broken")));
+        let err = client.packument("down").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Status { status, .. } if status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+    }
+
+    /// Retries quickly, so tests don't take forever.
+    fn impatient() -> RetryPolicy {
+        RetryPolicy {
+            retries: 3,
+            factor: 2,
+            min_timeout: Duration::from_millis(1),
+            max_timeout: Duration::from_millis(10),
+        }
+    }
+
+    /// A server that fails with `failure` the first `failures` times, then serves `PACKUMENT`.
+    async fn flaky(failures: usize, failure: fn() -> Response) -> TestServer {
+        let count = AtomicUsize::new(0);
+        TestServer::start(move |_| {
+            if count.fetch_add(1, Ordering::SeqCst) < failures {
+                failure()
+            } else {
+                Response::new(200).body(PACKUMENT)
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn retries_flaky_registries() {
+        let failures: [fn() -> Response; 4] = [
+            || Response::new(503),
+            || Response::new(429).header("retry-after", "0"),
+            Response::hang_up,
+            || {
+                Response::new(200)
+                    .body(PACKUMENT)
+                    .delay(Duration::from_secs(5))
+            },
+        ];
+        for failure in failures {
+            let server = flaky(2, failure).await;
+            let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+                .with_registry(&server.url)
+                .with_retry_policy(impatient())
+                .with_timeout(Duration::from_millis(500));
+
+            client.packument("a").await.unwrap();
+            assert_eq!(server.requests().len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_eventually() {
+        let server = flaky(usize::MAX, || Response::new(502)).await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&server.url)
+            .with_retry_policy(impatient());
+
+        let err = client.packument("a").await.unwrap_err();
+        assert!(matches!(err, Error::Status { status, .. } if status == StatusCode::BAD_GATEWAY));
+        assert_eq!(server.requests().len(), 4);
+
+        // Errors that won't go away by themselves aren't retried.
+        let server = flaky(usize::MAX, || Response::new(401)).await;
+        let client = client.with_registry(&server.url);
+        client.packument("a").await.unwrap_err();
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_tarball_downloads() {
+        let server = flaky(1, || Response::new(500)).await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_retry_policy(impatient());
+        let dist = Dist {
+            tarball: format!("{}/a/-/a-1.0.0.tgz", server.url),
+            integrity: Some(Integrity::Sha512(Sha512::digest(PACKUMENT).to_vec()).to_string()),
+            shasum: None,
+        };
+
+        client.download_tarball(&dist).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fetches_each_packument_once_at_a_time() {
+        let server = TestServer::start(|_| {
+            Response::new(200)
+                .body(PACKUMENT)
+                .delay(Duration::from_millis(100))
+        })
+        .await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&server.url);
+
+        let fetches = (0..5).map(|_| client.packument("a"));
+        for result in futures_util::future::join_all(fetches).await {
+            result.unwrap();
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_requests() {
+        let server = TestServer::start(|_| {
+            Response::new(200)
+                .body(PACKUMENT)
+                .delay(Duration::from_millis(100))
+        })
+        .await;
+        let client = RegistryClient::new(reqwest::Client::new(), MemoryCache::new())
+            .with_registry(&server.url)
+            .with_max_sockets(2);
+
+        let start = Instant::now();
+        let fetches = ["a", "b", "c", "d"].map(|name| client.packument(name));
+        for result in futures_util::future::join_all(fetches).await {
+            result.unwrap();
+        }
+        // Two at a time, so it takes (at least) two rounds.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(server.requests().len(), 4);
+    }
+}
@@ -0,0 +1,216 @@
+//! Abbreviated packuments, also known as "corgi" documents.
+//!
+//! Asking for `application/vnd.npm.install-v1+json` makes the registry leave out
+//! everything that isn't needed to install a package (READMEs, maintainers, and so on),
+//! which for big packages shrinks the packument from megabytes to kilobytes.
+//!
+//! See <https://github.com/npm/registry/blob/main/docs/responses/package-metadata.md>.
+
+use std::collections::HashMap;
+
+use dinopkg_package_json::{BinVariant, Dependencies, PackageJson, PeerDependencyMeta};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_with::skip_serializing_none;
+
+use crate::client::parse_response;
+use crate::{package_url, Dist, Error, DEFAULT_REGISTRY};
+
+/// The `Accept` header for abbreviated packuments. Registries that don't support them
+/// send the full document instead, which parses just as well.
+pub const CORGI_ACCEPT: &str =
+    "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+
+/// The `Accept` header for full packuments.
+pub const FULL_ACCEPT: &str = "application/json";
+
+/// An abbreviated packument: every version of a package, with just enough
+/// information to install them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Packument {
+    pub name: String,
+
+    /// When the package was last changed.
+    pub modified: Option<String>,
+
+    #[serde(rename = "dist-tags")]
+    pub dist_tags: HashMap<String, String>,
+
+    /// A map of versions to their respective manifests.
+    pub versions: HashMap<String, Manifest>,
+}
+
+/// A single version of a package, from an abbreviated packument.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+
+    pub dependencies: Option<Dependencies>,
+    pub optional_dependencies: Option<Dependencies>,
+    pub peer_dependencies: Option<Dependencies>,
+    pub peer_dependencies_meta: Option<HashMap<String, PeerDependencyMeta>>,
+
+    /// Only installed for workspaces, so it's only kept for packages read from a
+    /// `package.json`.
+    #[serde(skip)]
+    pub dev_dependencies: Option<Dependencies>,
+
+    // Old packages sometimes have nonsense in these fields, which shouldn't stop the
+    // rest of the packument from being usable.
+    #[serde(default, deserialize_with = "lenient")]
+    pub bin: Option<BinVariant>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub engines: Option<HashMap<String, String>>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub os: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub cpu: Option<Vec<String>>,
+
+    /// Why this version shouldn't be used anymore, if it's deprecated.
+    #[serde(default, deserialize_with = "lenient")]
+    pub deprecated: Option<String>,
+
+    /// Whether the package has `preinstall`, `install` or `postinstall` scripts.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub has_install_script: bool,
+
+    /// Abbreviated packuments don't include this, but full ones do.
+    pub license: Option<String>,
+
+    pub dist: Dist,
+}
+
+impl Manifest {
+    /// The manifest of a package that didn't come from a registry, like a git
+    /// dependency, read from its `package.json`. It has no `dist`.
+    pub fn from_package_json(package_json: &PackageJson) -> Self {
+        let scripts = package_json.scripts.as_ref();
+        Self {
+            name: package_json.name.clone(),
+            version: package_json.version.clone(),
+            dependencies: package_json.dependencies.clone(),
+            optional_dependencies: package_json.optional_dependencies.clone(),
+            peer_dependencies: package_json.peer_dependencies.clone(),
+            dev_dependencies: package_json.dev_dependencies.clone(),
+            peer_dependencies_meta: package_json
+                .peer_dependencies_meta
+                .clone()
+                .map(|meta| meta.into_iter().collect()),
+            bin: package_json.bin.clone(),
+            engines: package_json
+                .engines
+                .clone()
+                .map(|engines| engines.into_iter().collect()),
+            os: package_json.os.clone(),
+            cpu: package_json.cpu.clone(),
+            deprecated: None,
+            has_install_script: ["preinstall", "install", "postinstall"]
+                .iter()
+                .any(|script| scripts.is_some_and(|scripts| scripts.contains_key(*script))),
+            license: package_json.license.clone(),
+            dist: Dist::default(),
+        }
+    }
+}
+
+/// Deserializes a field, or ignores it if it's malformed.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+// serde :/
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Packument {
+    /// Fetches the abbreviated packument for `package_name` from the npm registry.
+    ///
+    /// # Errors
+    /// This function fails if the packument can't be fetched or parsed.
+    pub async fn from_name(package_name: &str, client: &reqwest::Client) -> Result<Self, Error> {
+        let url = package_url(DEFAULT_REGISTRY, package_name);
+        let response = client
+            .get(&url)
+            .header(reqwest::header::ACCEPT, CORGI_ACCEPT)
+            .send()
+            .await?;
+        parse_response(response, package_name, DEFAULT_REGISTRY).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::{btreemap, hashmap};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_abbreviated_packuments() {
+        let json = r#"{
+            "name": "esbuild",
+            "modified": "2024-07-20T18:32:54.612Z",
+            "dist-tags": { "latest": "0.23.0" },
+            "versions": {
+                "0.23.0": {
+                    "name": "esbuild",
+                    "version": "0.23.0",
+                    "bin": { "esbuild": "bin/esbuild" },
+                    "engines": { "node": ">=18" },
+                    "optionalDependencies": { "@esbuild/linux-x64": "0.23.0" },
+                    "hasInstallScript": true,
+                    "dist": {
+                        "integrity": "sha512-1yc3/1TLZg==",
+                        "shasum": "de06002d48424d9fdb7eb52dbe8e95927f852599",
+                        "tarball": "https://registry.npmjs.org/esbuild/-/esbuild-0.23.0.tgz"
+                    }
+                },
+                "0.0.1": {
+                    "name": "esbuild",
+                    "version": "0.0.1",
+                    "engines": ["node >= 0.4"],
+                    "os": "linux",
+                    "deprecated": false,
+                    "dist": { "tarball": "https://registry.npmjs.org/esbuild/-/esbuild-0.0.1.tgz" }
+                }
+            }
+        }"#;
+        let packument: Packument = serde_json::from_str(json).unwrap();
+        assert_eq!(packument.dist_tags["latest"], "0.23.0");
+        assert_eq!(
+            packument.versions["0.23.0"],
+            Manifest {
+                name: "esbuild".into(),
+                version: "0.23.0".into(),
+                optional_dependencies: Some(btreemap! {
+                    "@esbuild/linux-x64".into() => "0.23.0".into(),
+                }),
+                bin: Some(BinVariant::Map(
+                    [("esbuild".into(), "bin/esbuild".into())].into()
+                )),
+                engines: Some(hashmap! { "node".into() => ">=18".into() }),
+                has_install_script: true,
+                dist: Dist {
+                    tarball: "https://registry.npmjs.org/esbuild/-/esbuild-0.23.0.tgz".into(),
+                    integrity: Some("sha512-1yc3/1TLZg==".into()),
+                    shasum: Some("de06002d48424d9fdb7eb52dbe8e95927f852599".into()),
+                },
+                ..Default::default()
+            }
+        );
+
+        let old = &packument.versions["0.0.1"];
+        assert_eq!(
+            (&old.engines, &old.os, &old.deprecated),
+            (&None, &None, &None)
+        );
+    }
+}
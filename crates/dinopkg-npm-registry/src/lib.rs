@@ -1,35 +1,269 @@
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
-use dinopkg_package_json::PackageJson;
-use serde::{Deserialize, Serialize};
+use dinopkg_package_json::{PackageJson, Warning};
+use reqwest::StatusCode;
+use serde::{de, Deserialize, Deserializer, Serialize};
 
-const NPM_REGISTRY_ROOT_URL: &str = "https://registry.npmjs.org";
+mod auth;
+mod cache;
+mod client;
+mod corgi;
+mod network;
+mod retry;
+mod tarball;
+pub use auth::{nerf_dart, Auth, Credentials};
+pub use cache::{CacheEntry, CacheStore, DiskCache, MemoryCache};
+pub use client::{package_url, CacheMode, RegistryClient, DEFAULT_MAX_SOCKETS};
+pub use corgi::{Manifest, Packument, CORGI_ACCEPT, FULL_ACCEPT};
+pub use dinopkg_package_json::PeerDependencyMeta;
+pub use network::NetworkOptions;
+pub use retry::RetryPolicy;
+pub use tarball::{download_tarball, extract_tarball, read_tarball_file, Integrity};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[cfg(test)]
+mod test_server;
+
+/// The public npm registry, which is used unless another one is configured.
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// A full packument, with everything the registry knows about a package.
+///
+/// This is what commands like `view` want, but it can be many megabytes, so
+/// installs use the abbreviated [`Packument`] instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageInfo {
     /// The name of the package, for example `discord.js`.
     pub name: String,
 
     /// A map of versions to their respective version info.
     ///
-    /// The key is the version string (e.g. `0.1.0`), and the value is the version's `package.json` info.
-    pub versions: HashMap<String, PackageJson>,
+    /// The key is the version string (e.g. `0.1.0`), and the value is the version's manifest.
+    pub versions: HashMap<String, VersionManifest>,
 
     #[serde(rename = "dist-tags")]
     pub dist_keys: HashMap<String, String>,
 }
 
+/// A single version of a package, as published to the registry.
+///
+/// Old versions often don't match today's `package.json` schema, so they're normalised
+/// rather than failing the whole packument.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionManifest {
+    /// The version's `package.json` info.
+    #[serde(flatten)]
+    pub package_json: PackageJson,
+
+    pub dist: Dist,
+
+    /// What had to be fixed or dropped to read the `package.json` info.
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
+}
+
+impl<'de> Deserialize<'de> for VersionManifest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        let dist = fields
+            .remove("dist")
+            .ok_or_else(|| de::Error::missing_field("dist"))?;
+        let dist = Dist::deserialize(dist).map_err(de::Error::custom)?;
+        let (package_json, warnings) =
+            PackageJson::from_value_lenient(serde_json::Value::Object(fields))
+                .ok_or_else(|| de::Error::custom("unreadable package.json info"))?;
+        Ok(Self {
+            package_json,
+            dist,
+            warnings,
+        })
+    }
+}
+
+/// Where to download a version's tarball from, and how to verify it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Dist {
+    /// The URL of the `.tgz` file.
+    pub tarball: String,
+
+    /// A [Subresource Integrity](https://w3c.github.io/webappsec-subresource-integrity/)
+    /// string, such as `sha512-...`. Very old packages don't have one.
+    pub integrity: Option<String>,
+
+    /// The hex-encoded SHA-1 of the tarball.
+    pub shasum: Option<String>,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("network error: {0}")]
     NetworkError(#[from] reqwest::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("package `{name}` not found in {registry}")]
+    PackageNotFound { name: String, registry: String },
+
+    /// No version of the package matches what was asked for. `spec` is a range or a dist-tag.
+    #[error("no version of `{name}` matches `{spec}`")]
+    VersionNotFound {
+        name: String,
+        spec: String,
+        latest: Option<String>,
+    },
+
+    /// The registry wants credentials, and we either didn't send any or they were wrong.
+    #[error("{url} requires authentication")]
+    Unauthorized { url: String },
+
+    /// Our credentials were accepted, but they don't give us access.
+    #[error("access to {url} is forbidden")]
+    Forbidden { url: String },
+
+    #[error("too many requests to {url}{}", retry_after.map(|after| format!(", retry in {}s", after.as_secs())).unwrap_or_default())]
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+
+    /// Any other unsuccessful response.
+    #[error("{url} responded with {status}")]
+    Status { url: String, status: StatusCode },
+
+    #[error("malformed packument from {url}: {source}")]
+    MalformedPackument {
+        url: String,
+        source: serde_json::Error,
+    },
+
+    #[error("`{0}` isn't cached, and the network can't be used")]
+    NotCached(String),
+
+    #[error("tarball has no usable integrity or shasum")]
+    MissingIntegrity,
+
+    #[error("invalid integrity `{0}`")]
+    InvalidIntegrity(String),
+
+    #[error("integrity mismatch for {url}: expected {expected}, got {actual}")]
+    IntegrityMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("tarball entry `{0}` would be written outside of the package")]
+    UnsafePath(String),
+
+    #[error("invalid proxy URL `{0}`")]
+    InvalidProxy(String),
+
+    /// A certificate from `ca` or `cafile` couldn't be loaded.
+    #[error("invalid certificate: {0}")]
+    InvalidCertificate(String),
+}
+
+impl Error {
+    /// Whether the request that failed might succeed if it's tried again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::NetworkError(err) => retry::is_retryable_error(err),
+            Self::RateLimited { .. } => true,
+            Self::Status { status, .. } => retry::is_retryable_status(*status),
+            _ => false,
+        }
+    }
+
+    /// Turns an unsuccessful response into the matching error, or passes a successful one
+    /// through.
+    pub(crate) fn check_status(response: reqwest::Response) -> Result<reqwest::Response, Self> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let url = response.url().to_string();
+        Err(match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized { url },
+            StatusCode::FORBIDDEN => Self::Forbidden { url },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited {
+                url,
+                retry_after: retry_after(response.headers()),
+            },
+            status => Self::Status { url, status },
+        })
+    }
+}
+
+/// Reads a `Retry-After` header, which is either a number of seconds or a date.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
 }
 
 impl PackageInfo {
+    /// Fetches the full packument for `package_name` from the npm registry, without any
+    /// credentials. Use [`RegistryClient::package_info`] for other or private registries.
     pub async fn from_name(package_name: &str, client: &reqwest::Client) -> Result<Self, Error> {
-        let url = format!("{NPM_REGISTRY_ROOT_URL}/{package_name}");
+        let url = package_url(DEFAULT_REGISTRY, package_name);
         let response = client.get(&url).send().await?;
-        let package_info = response.json::<Self>().await?;
-        Ok(package_info)
+        client::parse_response(response, package_name, DEFAULT_REGISTRY).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    #[test]
+    fn reads_retry_after_in_either_format() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(600));
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&later).unwrap());
+        let after = retry_after(&headers).unwrap();
+        assert!(after > Duration::from_secs(590) && after <= Duration::from_secs(600));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn reads_packuments_with_malformed_versions() {
+        let info: PackageInfo = serde_json::from_value(serde_json::json!({
+            "name": "old",
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "0.0.1": {
+                    "name": "old",
+                    "version": "0.0.1",
+                    "author": ["a", "b"],
+                    "repository": { "url": "https://github.com/old/old" },
+                    "dependencies": [],
+                    "dist": { "tarball": "https://registry.npmjs.org/old/-/old-0.0.1.tgz" }
+                },
+                "1.0.0": {
+                    "name": "old",
+                    "version": "1.0.0",
+                    "dist": { "tarball": "https://registry.npmjs.org/old/-/old-1.0.0.tgz" }
+                }
+            }
+        }))
+        .unwrap();
+
+        let old = &info.versions["0.0.1"];
+        assert_eq!(old.package_json.dependencies, Some(Default::default()));
+        assert_eq!(old.warnings.len(), 2);
+        assert!(info.versions["1.0.0"].warnings.is_empty());
     }
 }
@@ -0,0 +1,244 @@
+//! Proxies and TLS, for reaching registries from behind corporate firewalls.
+
+use reqwest::{Certificate, Proxy, Url};
+
+use crate::Error;
+
+/// How to connect to registries, like npm's `proxy`, `https-proxy`, `noproxy`, `ca`,
+/// `cafile` and `strict-ssl` settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkOptions {
+    /// The proxy for `http://` URLs, and `https://` ones if there's no `https_proxy`.
+    pub proxy: Option<String>,
+
+    /// The proxy for `https://` URLs.
+    pub https_proxy: Option<String>,
+
+    /// Domains that are connected to directly, without a proxy. `ourco.com` also covers
+    /// its subdomains, like `npm.ourco.com`.
+    pub noproxy: Vec<String>,
+
+    /// PEM certificates to trust instead of the system's. Each one can be a bundle of
+    /// several certificates.
+    pub ca: Vec<String>,
+
+    /// Whether to check that registries' certificates are valid.
+    pub strict_ssl: bool,
+}
+
+impl Default for NetworkOptions {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            https_proxy: None,
+            noproxy: Vec::new(),
+            ca: Vec::new(),
+            strict_ssl: true,
+        }
+    }
+}
+
+impl NetworkOptions {
+    /// The proxy to use for `url`, if any.
+    pub fn proxy_for(&self, url: &Url) -> Option<&str> {
+        let proxy = match url.scheme() {
+            "https" => self.https_proxy.as_ref().or(self.proxy.as_ref()),
+            "http" => self.proxy.as_ref(),
+            _ => None,
+        }?;
+        let host = url.host_str()?;
+        if self
+            .noproxy
+            .iter()
+            .any(|domain| domain_matches(host, domain))
+        {
+            return None;
+        }
+        Some(proxy)
+    }
+
+    /// Builds an HTTP client that uses these options.
+    ///
+    /// Proxies from the environment (like `HTTPS_PROXY`) aren't used unless they're
+    /// in these options too, since npm treats them as defaults for its settings.
+    ///
+    /// # Errors
+    /// This function fails if a proxy URL or certificate is invalid.
+    pub fn build(&self) -> Result<reqwest::Client, Error> {
+        let mut builder = reqwest::Client::builder().no_proxy();
+
+        let mut proxies = Vec::new();
+        for proxy in self.proxy.iter().chain(&self.https_proxy) {
+            proxies.push((proxy.clone(), proxy_url(proxy)?));
+        }
+        if !proxies.is_empty() {
+            let options = self.clone();
+            builder = builder.proxy(Proxy::custom(move |url| {
+                let proxy = options.proxy_for(url)?;
+                proxies
+                    .iter()
+                    .find(|(raw, _)| raw == proxy)
+                    .map(|(_, url)| url.clone())
+            }));
+        }
+
+        if !self.ca.is_empty() {
+            // Like npm, custom CAs replace the system's instead of adding to them.
+            builder = builder.tls_built_in_root_certs(false);
+            for pem in &self.ca {
+                // npm allows newlines to be written as `\n`, so certificates fit on one line.
+                let pem = pem.replace("\\n", "\n");
+                let certificates = Certificate::from_pem_bundle(pem.as_bytes())
+                    .map_err(|err| Error::InvalidCertificate(err.to_string()))?;
+                if certificates.is_empty() {
+                    return Err(Error::InvalidCertificate(
+                        "no PEM certificates found".into(),
+                    ));
+                }
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+        }
+
+        if !self.strict_ssl {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Parses a proxy URL. Like npm, a proxy without a scheme is assumed to be `http://`.
+fn proxy_url(proxy: &str) -> Result<Url, Error> {
+    let with_scheme = if proxy.contains("://") {
+        proxy.to_owned()
+    } else {
+        format!("http://{proxy}")
+    };
+    Url::parse(&with_scheme).map_err(|_| Error::InvalidProxy(proxy.into()))
+}
+
+/// Whether `host` is `domain` or one of its subdomains, comparing whole labels like npm
+/// does. A leading `.` on `domain` is ignored.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut host_labels = host.rsplit('.');
+    let domain_labels: Vec<_> = domain.trim().split('.').filter(|l| !l.is_empty()).collect();
+    !domain_labels.is_empty()
+        && domain_labels.iter().rev().all(|label| {
+            host_labels
+                .next()
+                .is_some_and(|host| host.eq_ignore_ascii_case(label))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use pretty_assertions::assert_eq;
+
+    const CA: &str = "-----BEGIN CERTIFICATE-----
+MIIBhTCCASugAwIBAgIUfpTjhHRp4YIYl3zu92XzWZwzC38wCgYIKoZIzj0EAwIw
+FzEVMBMGA1UEAwwMZGlubyB0ZXN0IENBMCAXDTI2MTAxODEwMDk1MloYDzIxMjYw
+OTI0MTAwOTUyWjAXMRUwEwYDVQQDDAxkaW5vIHRlc3QgQ0EwWTATBgcqhkjOPQIB
+BggqhkjOPQMBBwNCAAQOrHGqc7sv6LgQJIVMd6fMt3D7FdoYpwV/Ro3psT1cwGqL
+UetyUrxhMVJlJT5OaV2Q5ZiHie1URB7JGyUZryano1MwUTAdBgNVHQ4EFgQUTVvf
+S9d78yhDGjFOUCw1Cu4PsFowHwYDVR0jBBgwFoAUTVvfS9d78yhDGjFOUCw1Cu4P
+sFowDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiEAzwx5Qe2aM4sQ
+TsT9Sc/agOG4e4QYSh4WguXZITyUZ3ACICtk6oSQQnvTwoqAQn5iqN+rzz64cP0D
+Q4OiloRU7fQL
+-----END CERTIFICATE-----
+";
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn picks_proxies_like_npm() {
+        let options = NetworkOptions {
+            proxy: Some("http://proxy.ourco.com:3128".into()),
+            https_proxy: Some("http://secure-proxy.ourco.com:3128".into()),
+            noproxy: vec!["ourco.com".into(), ".internal".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.proxy_for(&url("https://registry.npmjs.org/a")),
+            Some("http://secure-proxy.ourco.com:3128")
+        );
+        assert_eq!(
+            options.proxy_for(&url("http://registry.npmjs.org/a")),
+            Some("http://proxy.ourco.com:3128")
+        );
+        assert_eq!(options.proxy_for(&url("https://npm.ourco.com/a")), None);
+        assert_eq!(options.proxy_for(&url("https://ourco.com/a")), None);
+        assert_eq!(options.proxy_for(&url("https://npm.internal/a")), None);
+        assert_eq!(
+            options.proxy_for(&url("https://notourco.com/a")),
+            Some("http://secure-proxy.ourco.com:3128")
+        );
+
+        let http_only = NetworkOptions {
+            https_proxy: None,
+            ..options
+        };
+        assert_eq!(
+            http_only.proxy_for(&url("https://registry.npmjs.org/a")),
+            Some("http://proxy.ourco.com:3128")
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_requests_through_the_proxy() {
+        let proxy = TestServer::start(|_| Response::new(200).body("proxied")).await;
+        let direct = TestServer::start(|_| Response::new(200).body("direct")).await;
+        let proxy_url = proxy.url.replace("http://", "http://me:secret@");
+        let client = NetworkOptions {
+            proxy: Some(proxy_url),
+            noproxy: vec!["127.0.0.1".into()],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        let body = client
+            .get("http://registry.example.com/a")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "proxied");
+        let request = &proxy.requests()[0];
+        assert_eq!(request.path, "http://registry.example.com/a");
+        assert_eq!(request.headers["proxy-authorization"], "Basic bWU6c2VjcmV0");
+
+        let body = client.get(&direct.url).send().await.unwrap().text().await;
+        assert_eq!(body.unwrap(), "direct");
+        assert_eq!(proxy.requests().len(), 1);
+    }
+
+    #[test]
+    fn loads_custom_cas() {
+        let options = |ca: &str| NetworkOptions {
+            ca: vec![ca.into()],
+            ..Default::default()
+        };
+        options(CA).build().unwrap();
+        options(&CA.replace('\n', "\\n")).build().unwrap();
+        assert!(matches!(
+            options("not a certificate").build(),
+            Err(Error::InvalidCertificate(_))
+        ));
+        assert!(matches!(
+            NetworkOptions {
+                proxy: Some("http://[oops".into()),
+                ..Default::default()
+            }
+            .build(),
+            Err(Error::InvalidProxy(_))
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+//! Retrying requests that fail for reasons that might go away by themselves.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// When and how to retry failed requests, like npm's `fetch-retries`, `fetch-retry-factor`,
+/// `fetch-retry-mintimeout` and `fetch-retry-maxtimeout` settings.
+///
+/// Requests are retried if the connection fails or is reset, if they time out, or if the
+/// registry responds with a 5xx or 429.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to retry a request before giving up.
+    pub retries: u32,
+
+    /// How much longer to wait after each attempt.
+    pub factor: u32,
+
+    /// How long to wait before the first retry.
+    pub min_timeout: Duration,
+
+    /// The longest to wait between attempts.
+    pub max_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// npm's defaults.
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            factor: 10,
+            min_timeout: Duration::from_secs(10),
+            max_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries.
+    pub fn none() -> Self {
+        Self {
+            retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before retry number `attempt` (starting at 0).
+    ///
+    /// The delay grows exponentially, with jitter so lots of clients that failed at
+    /// the same time don't all retry at the same time too.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let growth = self.factor.saturating_pow(attempt);
+        let delay = self
+            .min_timeout
+            .saturating_mul(growth)
+            .min(self.max_timeout);
+        // Wait at least half the delay, so retries don't come too quickly.
+        delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Whether a response with `status` is worth retrying.
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Whether a request that failed with `err` is worth retrying. This includes connections
+/// that were reset, whether that was before the response or partway through its body.
+pub(crate) fn is_retryable_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout() || err.is_request() || err.is_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            retries: 5,
+            factor: 2,
+            min_timeout: Duration::from_secs(1),
+            max_timeout: Duration::from_secs(5),
+        };
+        for (attempt, max) in [(0, 1), (1, 2), (2, 4), (3, 5), (10, 5)] {
+            let max = Duration::from_secs(max);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= max / 2 && delay <= max, "{attempt}: {delay:?}");
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use base64::prelude::*;
+use flate2::read::GzDecoder;
+use futures_util::StreamExt;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use tar::EntryType;
+
+use crate::{Dist, Error};
+
+/// A hash that a tarball's contents must match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    Sha512(Vec<u8>),
+    Sha1(Vec<u8>),
+}
+
+impl Integrity {
+    /// Parses a [Subresource Integrity](https://w3c.github.io/webappsec-subresource-integrity/)
+    /// string, such as `sha512-...`.
+    ///
+    /// If the string has several hashes, the strongest one we support is used.
+    ///
+    /// # Errors
+    /// This function fails if `sri` has no hashes we support.
+    pub fn parse(sri: &str) -> Result<Self, Error> {
+        let mut best = None;
+        for hash in sri.split_whitespace() {
+            let Some((algorithm, digest)) = hash.split_once('-') else {
+                continue;
+            };
+            // Options (`sha512-...?foo`) are allowed by the spec, but meaningless to us.
+            let digest = digest.split('?').next().unwrap_or_default();
+            let Ok(digest) = BASE64_STANDARD.decode(digest) else {
+                continue;
+            };
+            match algorithm {
+                "sha512" => return Ok(Self::Sha512(digest)),
+                "sha1" => best = Some(Self::Sha1(digest)),
+                _ => {}
+            }
+        }
+        best.ok_or_else(|| Error::InvalidIntegrity(sri.into()))
+    }
+
+    /// Picks the strongest hash in `dist`, falling back to the legacy `shasum` if
+    /// the SRI `integrity` is missing.
+    ///
+    /// # Errors
+    /// This function fails if `dist` has no usable hashes.
+    pub fn from_dist(dist: &Dist) -> Result<Self, Error> {
+        if let Some(integrity) = &dist.integrity {
+            if let Ok(integrity) = Self::parse(integrity) {
+                return Ok(integrity);
+            }
+        }
+        match &dist.shasum {
+            Some(shasum) => hex::decode(shasum)
+                .map(Self::Sha1)
+                .map_err(|_| Error::InvalidIntegrity(shasum.clone())),
+            None => Err(Error::MissingIntegrity),
+        }
+    }
+
+    /// Hashes `data` with SHA-512, which is what npm uses for tarballs it makes itself.
+    pub fn sha512(data: &[u8]) -> Self {
+        Self::Sha512(Sha512::digest(data).to_vec())
+    }
+
+    /// The name of the hash algorithm, as used in SRI strings.
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            Self::Sha512(_) => "sha512",
+            Self::Sha1(_) => "sha1",
+        }
+    }
+
+    /// The digest as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        match self {
+            Self::Sha512(digest) | Self::Sha1(digest) => hex::encode(digest),
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            Self::Sha512(_) => Hasher::Sha512(Sha512::new()),
+            Self::Sha1(_) => Hasher::Sha1(Sha1::new()),
+        }
+    }
+
+    /// Checks that `data`, which was downloaded from `url`, matches this hash.
+    ///
+    /// # Errors
+    /// This function fails with [`Error::IntegrityMismatch`] if it doesn't.
+    pub fn verify(&self, data: &[u8], url: &str) -> Result<(), Error> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        self.check(hasher.finish(), url)
+    }
+
+    fn check(&self, actual: Self, url: &str) -> Result<(), Error> {
+        if *self == actual {
+            Ok(())
+        } else {
+            Err(Error::IntegrityMismatch {
+                url: url.into(),
+                expected: self.to_string(),
+                actual: actual.to_string(),
+            })
+        }
+    }
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha512(digest) => write!(f, "sha512-{}", BASE64_STANDARD.encode(digest)),
+            Self::Sha1(digest) => write!(f, "sha1-{}", BASE64_STANDARD.encode(digest)),
+        }
+    }
+}
+
+enum Hasher {
+    Sha512(Sha512),
+    Sha1(Sha1),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha512(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> Integrity {
+        match self {
+            Self::Sha512(hasher) => Integrity::Sha512(hasher.finalize().to_vec()),
+            Self::Sha1(hasher) => Integrity::Sha1(hasher.finalize().to_vec()),
+        }
+    }
+}
+
+/// Downloads the tarball described by `dist`, verifying it as it streams in.
+///
+/// # Errors
+/// This function fails if the download fails, or if the tarball doesn't match its integrity.
+pub async fn download_tarball(dist: &Dist, client: &reqwest::Client) -> Result<Vec<u8>, Error> {
+    download(client.get(&dist.tarball), dist).await
+}
+
+/// Sends `request` for the tarball described by `dist`, verifying it as it streams in.
+pub(crate) async fn download(
+    request: reqwest::RequestBuilder,
+    dist: &Dist,
+) -> Result<Vec<u8>, Error> {
+    let integrity = Integrity::from_dist(dist)?;
+    let response = Error::check_status(request.send().await?)?;
+
+    let mut hasher = integrity.hasher();
+    let mut data = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        data.extend_from_slice(&chunk);
+    }
+
+    integrity.check(hasher.finish(), &dist.tarball)?;
+    Ok(data)
+}
+
+/// Extracts a gzipped package tarball into `dest`.
+///
+/// Like npm, the first path component of every entry (usually `package/`) is
+/// stripped. Entries that would end up outside of `dest` - through `..`,
+/// absolute paths, or symlinks - are rejected.
+///
+/// # Errors
+/// This function fails if the tarball is invalid, contains unsafe paths, or
+/// if writing to `dest` fails.
+pub fn extract_tarball(data: &[u8], dest: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(data));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some(relative) = strip_package_dir(&path)? else {
+            continue;
+        };
+        let target = dest.join(&relative);
+        ensure_no_symlinks_between(dest, &target, &path)?;
+
+        match entry.header().entry_type() {
+            EntryType::Directory => fs::create_dir_all(&target)?,
+            EntryType::Regular | EntryType::Continuous => {
+                prepare_target(&target)?;
+                entry.unpack(&target)?;
+            }
+            EntryType::Symlink => {
+                let link = entry
+                    .link_name()?
+                    .ok_or_else(|| Error::UnsafePath(path.display().to_string()))?
+                    .into_owned();
+                check_symlink(&relative, &link, &path)?;
+                prepare_target(&target)?;
+                symlink(&link, &target)?;
+            }
+            EntryType::Link => {
+                let link = entry
+                    .link_name()?
+                    .ok_or_else(|| Error::UnsafePath(path.display().to_string()))?
+                    .into_owned();
+                let Some(source) = strip_package_dir(&link)? else {
+                    return Err(Error::UnsafePath(path.display().to_string()));
+                };
+                let source = dest.join(source);
+                ensure_no_symlinks_between(dest, &source, &path)?;
+                // Hard linking a symlink copies its text, which could point somewhere
+                // else from the new entry's depth, so only regular files are linked.
+                if !fs::symlink_metadata(&source)?.is_file() {
+                    return Err(Error::UnsafePath(path.display().to_string()));
+                }
+                prepare_target(&target)?;
+                fs::hard_link(source, &target)?;
+            }
+            // Device files, FIFOs and the like have no business being in a package.
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads one file out of a gzipped package tarball, without extracting anything.
+///
+/// `path` is relative to the package, like `package.json`, since the first path
+/// component of every entry is stripped just like [`extract_tarball`] does.
+///
+/// # Errors
+/// This function fails if the tarball is invalid or contains unsafe paths.
+pub fn read_tarball_file(data: &[u8], path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(data));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        if strip_package_dir(&entry_path)?.as_deref() != Some(path) {
+            continue;
+        }
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        ) {
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        return Ok(Some(contents));
+    }
+    Ok(None)
+}
+
+/// Strips the first component from a tarball path, rejecting anything that
+/// isn't a plain relative path.
+///
+/// Returns `None` for the top-level directory itself.
+fn strip_package_dir(path: &Path) -> Result<Option<PathBuf>, Error> {
+    let mut stripped = PathBuf::new();
+    for (i, component) in path.components().enumerate() {
+        match component {
+            Component::Normal(part) if i > 0 => stripped.push(part),
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::UnsafePath(path.display().to_string()))
+            }
+        }
+    }
+    Ok((!stripped.as_os_str().is_empty()).then_some(stripped))
+}
+
+/// Refuses to write through symlinks, since an earlier entry could have
+/// pointed one anywhere. This is what node-tar does too.
+fn ensure_no_symlinks_between(dest: &Path, target: &Path, path: &Path) -> Result<(), Error> {
+    let Ok(relative) = target.strip_prefix(dest) else {
+        return Err(Error::UnsafePath(path.display().to_string()));
+    };
+    let mut current = dest.to_path_buf();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        // The final component is allowed to be a symlink, since it'll be replaced.
+        if components.peek().is_none() {
+            break;
+        }
+        current.push(component);
+        if fs::symlink_metadata(&current).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            return Err(Error::UnsafePath(path.display().to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a symlink at `relative` pointing to `link` stays inside the package.
+fn check_symlink(relative: &Path, link: &Path, path: &Path) -> Result<(), Error> {
+    let unsafe_path = || Error::UnsafePath(path.display().to_string());
+
+    let mut depth = relative.components().count() - 1;
+    let mut seen_normal = false;
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => {
+                seen_normal = true;
+                depth += 1;
+            }
+            Component::CurDir => {}
+            // `a/..` could go anywhere if `a` is itself a symlink, so only leading `..`s are allowed.
+            Component::ParentDir if !seen_normal && depth > 0 => depth -= 1,
+            _ => return Err(unsafe_path()),
+        }
+    }
+    Ok(())
+}
+
+/// Creates `target`'s parent directory, and removes anything already at `target`.
+fn prepare_target(target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::symlink_metadata(target) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(target),
+        Ok(_) => fs::remove_file(target),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(link: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link, target)
+}
+
+#[cfg(windows)]
+fn symlink(link: &Path, target: &Path) -> io::Result<()> {
+    // Windows needs to know up front whether a symlink points to a directory.
+    let resolved = target.parent().map(|parent| parent.join(link));
+    if resolved.is_some_and(|resolved| resolved.is_dir()) {
+        std::os::windows::fs::symlink_dir(link, target)
+    } else {
+        std::os::windows::fs::symlink_file(link, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+        Hardlink(&'a str, &'a str),
+    }
+
+    fn tarball(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            let (path, contents, link) = match entry {
+                Entry::File(path, contents) => {
+                    header.set_entry_type(EntryType::Regular);
+                    (path, contents.as_bytes(), None)
+                }
+                Entry::Symlink(path, link) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    (path, &[][..], Some(link))
+                }
+                Entry::Hardlink(path, link) => {
+                    header.set_entry_type(EntryType::Link);
+                    (path, &[][..], Some(link))
+                }
+            };
+            // `Header::set_path` refuses `..`, so write the raw bytes to test that we do too.
+            let name = &mut header.as_old_mut().name;
+            name[..path.len()].copy_from_slice(path.as_bytes());
+            if let Some(link) = link {
+                let link_name = &mut header.as_old_mut().linkname;
+                link_name[..link.len()].copy_from_slice(link.as_bytes());
+            }
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn extracts_and_strips_package_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = tarball(&[
+            Entry::File("package/package.json", "{}"),
+            Entry::File("package/lib/index.js", "module.exports = 1"),
+            Entry::Symlink("package/lib/main.js", "index.js"),
+        ]);
+        extract_tarball(&data, dir.path()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            "{}"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib/main.js")).unwrap(),
+            "module.exports = 1"
+        );
+    }
+
+    #[test]
+    fn reads_single_files() {
+        let data = tarball(&[
+            Entry::File("package/package.json", r#"{"name":"a"}"#),
+            Entry::File("package/lib/package.json", "{}"),
+        ]);
+        assert_eq!(
+            read_tarball_file(&data, Path::new("package.json")).unwrap(),
+            Some(br#"{"name":"a"}"#.to_vec())
+        );
+        assert_eq!(
+            read_tarball_file(&data, Path::new("index.js")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn strips_any_top_level_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        extract_tarball(&tarball(&[Entry::File("node/index.js", "")]), dir.path()).unwrap();
+        assert!(dir.path().join("index.js").is_file());
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pkg");
+        for path in ["package/../../evil.js", "/etc/evil.js"] {
+            let err = extract_tarball(&tarball(&[Entry::File(path, "")]), &dest).unwrap_err();
+            assert!(matches!(err, Error::UnsafePath(_)), "{path}: {err}");
+        }
+        assert!(!dir.path().join("evil.js").exists());
+    }
+
+    #[test]
+    fn rejects_escaping_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        for link in ["../outside", "/etc/passwd", "lib/../../outside", "a/../b"] {
+            let data = tarball(&[Entry::Symlink("package/link", link)]);
+            let err = extract_tarball(&data, dir.path()).unwrap_err();
+            assert!(matches!(err, Error::UnsafePath(_)), "{link}: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_hard_links_to_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("pkg");
+        let data = tarball(&[
+            Entry::Symlink("package/a/b/x", "../../y"),
+            Entry::Hardlink("package/top", "package/a/b/x"),
+        ]);
+        let err = extract_tarball(&data, &dest).unwrap_err();
+        assert!(matches!(err, Error::UnsafePath(_)), "{err}");
+        assert!(fs::symlink_metadata(dest.join("top")).is_err());
+
+        let data = tarball(&[
+            Entry::File("package/a/b/x", "hi"),
+            Entry::Hardlink("package/top", "package/a/b/x"),
+        ]);
+        extract_tarball(&data, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("top")).unwrap(), "hi");
+    }
+
+    #[test]
+    fn rejects_writing_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = tarball(&[
+            Entry::Symlink("package/lib", "."),
+            Entry::File("package/lib/index.js", ""),
+        ]);
+        let err = extract_tarball(&data, dir.path()).unwrap_err();
+        assert!(matches!(err, Error::UnsafePath(_)));
+    }
+
+    #[test]
+    fn verifies_integrity() {
+        let sha512 = Integrity::Sha512(Sha512::digest(b"hello").to_vec());
+        let sri = format!("sha1-AAAA {sha512}");
+        assert_eq!(Integrity::parse(&sri).unwrap(), sha512);
+        sha512.verify(b"hello", "a.tgz").unwrap();
+        assert!(matches!(
+            sha512.verify(b"goodbye", "a.tgz"),
+            Err(Error::IntegrityMismatch { url, .. }) if url == "a.tgz"
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_shasum() {
+        let dist = Dist {
+            tarball: "https://registry.npmjs.org/a/-/a-1.0.0.tgz".into(),
+            integrity: None,
+            shasum: Some(hex::encode(Sha1::digest(b"hello"))),
+        };
+        let integrity = Integrity::from_dist(&dist).unwrap();
+        assert!(matches!(integrity, Integrity::Sha1(_)));
+        integrity.verify(b"hello", &dist.tarball).unwrap();
+
+        let dist = Dist {
+            shasum: None,
+            ..dist
+        };
+        assert!(matches!(
+            Integrity::from_dist(&dist),
+            Err(Error::MissingIntegrity)
+        ));
+    }
+}
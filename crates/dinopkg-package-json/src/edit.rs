@@ -0,0 +1,466 @@
+//! Edits to `package.json` that leave the rest of the file alone.
+//!
+//! Rather than parsing the file and writing it back out (which would lose its key order
+//! and formatting), each edit is spliced into the original text. New entries copy the
+//! whitespace around their neighbours, so tabs, 2 or 4 spaces, one-line objects,
+//! `\r\n` line endings and the trailing newline all survive.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::{Error, PackageJson};
+
+/// A `package.json` that's being edited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Editor {
+    text: String,
+}
+
+/// An entry in a JSON object, as byte offsets into the text.
+#[derive(Debug)]
+struct Member {
+    key: String,
+    key_start: usize,
+    value_start: usize,
+    value_end: usize,
+}
+
+/// A JSON object, as byte offsets into the text.
+#[derive(Debug)]
+struct Object {
+    open: usize,
+    close: usize,
+    members: Vec<Member>,
+}
+
+impl Editor {
+    /// # Errors
+    /// This function fails if `text` isn't a JSON object.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let value: Value = serde_json::from_str(text)?;
+        if !value.is_object() {
+            return Err(Error::NotAnObject("package.json".into()));
+        }
+        Ok(Self { text: text.into() })
+    }
+
+    /// The edited file.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Parses the edited file.
+    ///
+    /// # Errors
+    /// This function fails if the file isn't a valid `package.json`.
+    pub fn package_json(&self) -> Result<PackageJson, Error> {
+        PackageJson::parse(&self.text)
+    }
+
+    /// Returns the value at `path`, like `["scripts", "test"]`.
+    pub fn get(&self, path: &[&str]) -> Option<Value> {
+        let (start, end) = self.find(path)?;
+        serde_json::from_str(&self.text[start..end]).ok()
+    }
+
+    /// Sets the value at `path`, creating any objects along the way. New keys go at the
+    /// end of their object.
+    ///
+    /// # Errors
+    /// This function fails if something along `path` isn't an object.
+    pub fn set(&mut self, path: &[&str], value: &Value) -> Result<(), Error> {
+        self.insert(path, value, |_| None)
+    }
+
+    /// Removes the value at `path`, returning whether there was one.
+    ///
+    /// # Errors
+    /// This function fails if something along `path` isn't an object.
+    pub fn remove(&mut self, path: &[&str]) -> Result<bool, Error> {
+        let Some((key, parents)) = path.split_last() else {
+            return Ok(false);
+        };
+        let Some(object) = self.object_at(parents)? else {
+            return Ok(false);
+        };
+        let Some(index) = object.members.iter().rposition(|m| m.key == *key) else {
+            return Ok(false);
+        };
+
+        let members = &object.members;
+        let range = if let Some(next) = members.get(index + 1) {
+            // Take the whitespace before the next entry with it, so the next entry ends
+            // up where this one was.
+            members[index].key_start..next.key_start
+        } else if index > 0 {
+            members[index - 1].value_end..members[index].value_end
+        } else {
+            object.open + 1..object.close
+        };
+        self.text.replace_range(range, "");
+        Ok(true)
+    }
+
+    pub fn set_version(&mut self, version: &str) -> Result<(), Error> {
+        self.set(&["version"], &Value::from(version))
+    }
+
+    /// Adds or updates a dependency in `field` (like `dependencies` or
+    /// `devDependencies`).
+    ///
+    /// New dependencies are put in alphabetical order, like npm does.
+    ///
+    /// # Errors
+    /// This function fails if `field` isn't an object.
+    pub fn set_dependency(&mut self, field: &str, name: &str, spec: &str) -> Result<(), Error> {
+        self.insert(&[field, name], &Value::from(spec), |members| {
+            members.iter().position(|member| member.key.as_str() > name)
+        })
+    }
+
+    /// # Errors
+    /// This function fails if `field` isn't an object.
+    pub fn remove_dependency(&mut self, field: &str, name: &str) -> Result<bool, Error> {
+        self.remove(&[field, name])
+    }
+
+    /// # Errors
+    /// This function fails if `scripts` isn't an object.
+    pub fn set_script(&mut self, name: &str, command: &str) -> Result<(), Error> {
+        self.set(&["scripts", name], &Value::from(command))
+    }
+
+    /// # Errors
+    /// This function fails if `scripts` isn't an object.
+    pub fn remove_script(&mut self, name: &str) -> Result<bool, Error> {
+        self.remove(&["scripts", name])
+    }
+
+    /// Sets `path` to `value`. If the last key is new, it's inserted before the member
+    /// `position` picks, or at the end.
+    fn insert(
+        &mut self,
+        path: &[&str],
+        value: &Value,
+        position: impl Fn(&[Member]) -> Option<usize>,
+    ) -> Result<(), Error> {
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        // Find the deepest object that already exists.
+        let mut depth = 0;
+        let mut object = self.root();
+        while depth < path.len() {
+            let Some(member) = object.members.iter().rev().find(|m| m.key == path[depth]) else {
+                break;
+            };
+            if depth == path.len() - 1 {
+                let indent = self.indent_at(member.key_start);
+                let value = self.stringify(value, &indent);
+                self.text
+                    .replace_range(member.value_start..member.value_end, &value);
+                return Ok(());
+            }
+            object = self
+                .object(member.value_start)
+                .ok_or_else(|| Error::NotAnObject(path[..=depth].join(".")))?;
+            depth += 1;
+        }
+
+        // Wrap the value in any objects that don't exist yet.
+        let mut value = value.clone();
+        for key in path[depth + 1..].iter().rev() {
+            value = Value::Object([(String::from(*key), value)].into_iter().collect());
+        }
+        let key = path[depth];
+
+        let members = &object.members;
+        if members.is_empty() {
+            let outer = self.indent_at(object.open);
+            let inner = format!("{outer}{}", self.indent_unit());
+            let newline = self.newline();
+            let entry = self.entry(key, &value, &inner);
+            self.text.replace_range(
+                object.open + 1..object.close,
+                &format!("{newline}{inner}{entry}{newline}{outer}"),
+            );
+            return Ok(());
+        }
+
+        let index = position(members).unwrap_or(members.len());
+        // New entries are separated from their neighbours the same way the existing
+        // ones are.
+        let separator_before = |index: usize| {
+            let start = match index {
+                0 => object.open + 1,
+                _ => self.text[..members[index].key_start]
+                    .rfind(',')
+                    .map_or(object.open + 1, |comma| comma + 1),
+            };
+            self.text[start..members[index].key_start].to_owned()
+        };
+        if let Some(next) = members.get(index) {
+            let separator = separator_before(index);
+            let entry = self.entry(key, &value, &self.indent_at(next.key_start));
+            self.text
+                .insert_str(next.key_start, &format!("{entry},{separator}"));
+        } else {
+            let last = members.len() - 1;
+            let separator = separator_before(last);
+            let entry = self.entry(key, &value, &self.indent_at(members[last].key_start));
+            self.text
+                .insert_str(members[last].value_end, &format!(",{separator}{entry}"));
+        }
+        Ok(())
+    }
+
+    fn root(&self) -> Object {
+        let start = skip_whitespace(&self.text, 0);
+        self.object(start)
+            .expect("the root is checked to be an object when parsing")
+    }
+
+    /// The object at `path`, if it exists.
+    fn object_at(&self, path: &[&str]) -> Result<Option<Object>, Error> {
+        let mut object = self.root();
+        for (depth, key) in path.iter().enumerate() {
+            let Some(member) = object.members.iter().rev().find(|m| m.key == *key) else {
+                return Ok(None);
+            };
+            object = self
+                .object(member.value_start)
+                .ok_or_else(|| Error::NotAnObject(path[..=depth].join(".")))?;
+        }
+        Ok(Some(object))
+    }
+
+    /// The start and end of the value at `path`.
+    fn find(&self, path: &[&str]) -> Option<(usize, usize)> {
+        let (key, parents) = path.split_last()?;
+        let object = self.object_at(parents).ok()??;
+        let member = object.members.iter().rev().find(|m| m.key == *key)?;
+        Some((member.value_start, member.value_end))
+    }
+
+    /// Reads the object that starts at `open`, or returns `None` if it's not an object.
+    fn object(&self, open: usize) -> Option<Object> {
+        let text = &self.text;
+        if !text[open..].starts_with('{') {
+            return None;
+        }
+        let mut members = Vec::new();
+        let mut pos = skip_whitespace(text, open + 1);
+        while !text[pos..].starts_with('}') {
+            let key_start = pos;
+            let key_end = value_end(text, key_start);
+            let key = serde_json::from_str(&text[key_start..key_end]).ok()?;
+            let colon = skip_whitespace(text, key_end);
+            let value_start = skip_whitespace(text, colon + 1);
+            let value_end = value_end(text, value_start);
+            members.push(Member {
+                key,
+                key_start,
+                value_start,
+                value_end,
+            });
+            pos = skip_whitespace(text, value_end);
+            if text[pos..].starts_with(',') {
+                pos = skip_whitespace(text, pos + 1);
+            }
+        }
+        Some(Object {
+            open,
+            close: pos,
+            members,
+        })
+    }
+
+    /// Formats `key: value` for a line indented with `indent`.
+    fn entry(&self, key: &str, value: &Value, indent: &str) -> String {
+        format!("{}: {}", Value::from(key), self.stringify(value, indent))
+    }
+
+    /// Formats `value` in the file's style, for a line indented with `indent`.
+    fn stringify(&self, value: &Value, indent: &str) -> String {
+        let Value::Object(object) = value else {
+            return value.to_string();
+        };
+        if object.is_empty() {
+            return value.to_string();
+        }
+        let inner = format!("{indent}{}", self.indent_unit());
+        let newline = self.newline();
+        let entries: Vec<_> = object
+            .iter()
+            .map(|(key, value)| format!("{newline}{inner}{}", self.entry(key, value, &inner)))
+            .collect();
+        format!("{{{}{newline}{indent}}}", entries.join(","))
+    }
+
+    /// The whitespace at the start of the line that `pos` is on.
+    fn indent_at(&self, pos: usize) -> String {
+        let line_start = self.text[..pos].rfind('\n').map_or(0, |i| i + 1);
+        self.text[line_start..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    /// One level of indentation, from the first indented line in the file.
+    fn indent_unit(&self) -> String {
+        self.text
+            .lines()
+            .map(|line| -> String {
+                line.chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect()
+            })
+            .find(|indent| !indent.is_empty())
+            .unwrap_or_else(|| "  ".into())
+    }
+
+    fn newline(&self) -> &'static str {
+        if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+impl fmt::Display for Editor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn skip_whitespace(text: &str, pos: usize) -> usize {
+    text[pos..]
+        .find(|c: char| !c.is_ascii_whitespace())
+        .map_or(text.len(), |offset| pos + offset)
+}
+
+/// Finds where the JSON value starting at `start` ends. The text has already been
+/// checked to be valid JSON, so this doesn't need to validate anything.
+fn value_end(text: &str, start: usize) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut pos = start;
+    while pos < bytes.len() {
+        let byte = bytes[pos];
+        if in_string {
+            match byte {
+                b'\\' => pos += 1,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return pos + 1;
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return pos + 1;
+                    }
+                }
+                _ if depth == 0
+                    && (matches!(byte, b',' | b'}' | b']') || byte.is_ascii_whitespace()) =>
+                {
+                    return pos;
+                }
+                _ => {}
+            }
+        }
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_the_original_formatting() {
+        let original = "{\r\n\t\"version\": \"1.0.0\",\r\n\t\"name\": \"a\",\r\n\t\"files\": [\"dist\", \"bin\"],\r\n\t\"dependencies\": {\r\n\t\t\"b\": \"^1.0.0\",\r\n\t\t\"d\": \"^1.0.0\"\r\n\t},\r\n\t\"scripts\": {\r\n\t\t\"test\": \"vitest\",\r\n\t\t\"build\": \"tsc\"\r\n\t}\r\n}\r\n";
+        let mut editor = Editor::parse(original).unwrap();
+        editor
+            .set_dependency("dependencies", "c", "^2.0.0")
+            .unwrap();
+        editor
+            .set_dependency("dependencies", "d", "^1.1.0")
+            .unwrap();
+        editor.remove_script("test").unwrap();
+        editor.set_version("1.1.0").unwrap();
+        assert_eq!(
+            editor.as_str(),
+            "{\r\n\t\"version\": \"1.1.0\",\r\n\t\"name\": \"a\",\r\n\t\"files\": [\"dist\", \"bin\"],\r\n\t\"dependencies\": {\r\n\t\t\"b\": \"^1.0.0\",\r\n\t\t\"c\": \"^2.0.0\",\r\n\t\t\"d\": \"^1.1.0\"\r\n\t},\r\n\t\"scripts\": {\r\n\t\t\"build\": \"tsc\"\r\n\t}\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn creates_missing_objects() {
+        let mut editor = Editor::parse("{\n    \"name\": \"a\"\n}").unwrap();
+        editor
+            .set_dependency("devDependencies", "typescript", "^5.0.0")
+            .unwrap();
+        editor
+            .set_dependency("devDependencies", "@types/node", "^20")
+            .unwrap();
+        assert_eq!(
+            editor.as_str(),
+            "{\n    \"name\": \"a\",\n    \"devDependencies\": {\n        \"@types/node\": \"^20\",\n        \"typescript\": \"^5.0.0\"\n    }\n}"
+        );
+        assert_eq!(
+            editor.get(&["devDependencies", "typescript"]),
+            Some(json!("^5.0.0"))
+        );
+
+        let mut editor = Editor::parse("{\n  \"name\": \"a\",\n  \"scripts\": {}\n}\n").unwrap();
+        editor.set_script("test", "vitest").unwrap();
+        assert_eq!(
+            editor.as_str(),
+            "{\n  \"name\": \"a\",\n  \"scripts\": {\n    \"test\": \"vitest\"\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn removes_entries_anywhere() {
+        let original = r#"{ "a": 1, "b": { "c": [1, {"d": "}"}] }, "e": "x" }"#;
+        let remove = |key| {
+            let mut editor = Editor::parse(original).unwrap();
+            assert!(editor.remove(&[key]).unwrap());
+            editor.to_string()
+        };
+        assert_eq!(
+            remove("a"),
+            r#"{ "b": { "c": [1, {"d": "}"}] }, "e": "x" }"#
+        );
+        assert_eq!(remove("b"), r#"{ "a": 1, "e": "x" }"#);
+        assert_eq!(remove("e"), r#"{ "a": 1, "b": { "c": [1, {"d": "}"}] } }"#);
+
+        let mut editor = Editor::parse(r#"{"scripts": {"test": "x"}}"#).unwrap();
+        assert!(editor.remove_script("test").unwrap());
+        assert!(!editor.remove_script("test").unwrap());
+        assert_eq!(editor.as_str(), r#"{"scripts": {}}"#);
+
+        let mut editor = Editor::parse(r#"{ "a": 1 }"#).unwrap();
+        editor.set(&["b"], &json!(true)).unwrap();
+        assert_eq!(editor.as_str(), r#"{ "a": 1, "b": true }"#);
+        assert!(matches!(
+            editor.set(&["a", "b"], &json!(1)),
+            Err(Error::NotAnObject(path)) if path == "a"
+        ));
+    }
+}
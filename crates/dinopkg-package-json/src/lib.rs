@@ -1,47 +1,228 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use serde_with::{serde_as, skip_serializing_none};
 
+mod edit;
+mod normalize;
+mod spec;
 mod util;
+mod workspace;
+
+pub use edit::Editor;
+pub use normalize::{normalize, Warning};
+pub use spec::{DependencySpec, GitSpec, PackageArg};
+pub use workspace::{find_workspaces, Workspace};
 
 #[serde_as]
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PackageJson {
     pub name: String,
     pub version: String,
+    pub description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub homepage: Option<String>,
+    pub bugs: Option<BugsVariant>,
     pub author: Option<AuthorVariant>,
+    pub contributors: Option<Vec<AuthorVariant>>,
+    pub funding: Option<FundingVariant>,
     #[serde(default = "default_as_false")]
     #[serde(skip_serializing_if = "is_false")]
     pub private: bool,
     pub license: Option<String>,
-    pub description: Option<String>,
-    pub main: Option<String>,
     pub repository: Option<RepositoryVariant>,
 
+    pub r#type: Option<ModuleType>,
+    pub main: Option<String>,
+    #[serde(alias = "typings")]
+    pub types: Option<String>,
+    pub bin: Option<BinVariant>,
+    pub files: Option<Vec<String>>,
+    pub exports: Option<Exports>,
+    pub imports: Option<IndexMap<String, Exports>>,
+
     pub scripts: Option<Scripts>,
 
     pub dependencies: Option<Dependencies>,
     pub dev_dependencies: Option<Dependencies>,
+    pub peer_dependencies: Option<Dependencies>,
+    pub peer_dependencies_meta: Option<BTreeMap<String, PeerDependencyMeta>>,
+    pub optional_dependencies: Option<Dependencies>,
+    #[serde(alias = "bundledDependencies")]
+    pub bundle_dependencies: Option<BundleDependencies>,
+    pub overrides: Option<Overrides>,
+
+    pub engines: Option<IndexMap<String, String>>,
+    pub os: Option<Vec<String>>,
+    pub cpu: Option<Vec<String>>,
+
+    pub workspaces: Option<Workspaces>,
+    pub publish_config: Option<PublishConfig>,
+    /// Dependencies that are allowed to run lifecycle scripts when they're installed.
+    pub trusted_dependencies: Option<Vec<String>>,
+
+    /// Fields dino doesn't know about, so they aren't lost when the file is written back.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum AuthorVariant {
-    Author { name: String, url: Option<String> },
+    Author {
+        name: String,
+        email: Option<String>,
+        url: Option<String>,
+    },
     String(String),
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum RepositoryVariant {
-    Repository { r#type: String, url: Option<String> },
+    Repository {
+        r#type: String,
+        url: Option<String>,
+        /// Where the package lives, if it's in a monorepo.
+        directory: Option<String>,
+    },
     String(String),
 }
 
+/// Where to report issues: a URL, or a URL and/or an email address.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BugsVariant {
+    Bugs {
+        url: Option<String>,
+        email: Option<String>,
+    },
+    String(String),
+}
+
+/// Ways to fund a package's development.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FundingVariant {
+    Funding(Funding),
+    List(Vec<Funding>),
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Funding {
+    Funding { r#type: Option<String>, url: String },
+    String(String),
+}
+
+/// A package's executables: either a single one named after the package, or a map
+/// of command names to paths.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BinVariant {
+    String(String),
+    Map(IndexMap<String, String>),
+}
+
+/// How Node treats the package's `.js` files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleType {
+    #[default]
+    CommonJs,
+    Module,
+}
+
+/// The package's entry points, for `exports`, or one of its `imports`.
+///
+/// Maps are either subpaths (`"./feature"`) or conditions (`"import"`, `"node"`, and so on),
+/// and their order matters, since Node picks the first condition that matches.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Exports {
+    /// `null`, which hides a subpath that would otherwise be exported.
+    Hidden,
+    Path(String),
+    /// Targets to try in order.
+    Fallbacks(Vec<Exports>),
+    Map(IndexMap<String, Exports>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct PeerDependencyMeta {
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// Dependencies to include in the package's tarball.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BundleDependencies {
+    /// `true` bundles every dependency, and `false` none of them.
+    All(bool),
+    List(Vec<String>),
+}
+
+/// Replacements for dependencies anywhere in the tree.
+pub type Overrides = IndexMap<String, Override>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Override {
+    /// A spec to use instead, like `1.0.0` or `$foo` to use the root's own `foo` spec.
+    Spec(String),
+    /// Overrides for the dependency's own dependencies. The dependency itself can be
+    /// overridden with a `"."` key.
+    Nested(Overrides),
+}
+
+/// Packages in a monorepo, as globs like `packages/*`.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Globs(Vec<String>),
+    /// Yarn's form, which can also stop some of the workspaces' dependencies from
+    /// being hoisted.
+    Config {
+        packages: Vec<String>,
+        nohoist: Option<Vec<String>>,
+    },
+}
+
+impl Workspaces {
+    pub fn globs(&self) -> &[String] {
+        match self {
+            Self::Globs(globs)
+            | Self::Config {
+                packages: globs, ..
+            } => globs,
+        }
+    }
+}
+
+/// Config that's used when the package is published.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct PublishConfig {
+    pub registry: Option<String>,
+    pub access: Option<String>,
+    pub tag: Option<String>,
+
+    /// Anything else, like fields that replace the package's own when it's published.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
 // serde :/
 #[allow(clippy::trivially_copy_pass_by_ref)]
 #[cfg(not(tarpaulin_include))]
@@ -56,19 +237,46 @@ const fn default_as_false() -> bool {
     false
 }
 
-pub type Scripts = HashMap<String, String>;
-pub type Dependencies = HashMap<String, String>;
+/// Scripts, in the order they were written.
+pub type Scripts = IndexMap<String, String>;
+/// Dependencies, sorted by name like npm writes them.
+pub type Dependencies = BTreeMap<String, String>;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("deserialization error: {0}")]
     Serde(#[from] serde_json::Error),
 
+    #[error("`{0}` isn't an object")]
+    NotAnObject(String),
+
+    #[error("invalid package name `{name}`: {source}")]
+    InvalidName {
+        name: String,
+        source: validate_package_name::Error,
+    },
+
+    #[error("invalid tag `{0}`, tags can only contain URL-friendly characters")]
+    InvalidTag(String),
+
+    /// `npm:` aliases can only point to packages on the registry.
+    #[error("invalid alias `npm:{0}`, only registry packages can be aliased")]
+    InvalidAlias(String),
+
+    #[error("invalid workspace pattern `{0}`")]
+    InvalidWorkspacePattern(String),
+
+    #[error("workspaces `{first}` and `{second}` are both named `{name}`")]
+    DuplicateWorkspace {
+        name: String,
+        first: String,
+        second: String,
+    },
+
     #[cfg(feature = "tokio")]
     #[error("package.json not found")]
     NotFound,
 
-    #[cfg(feature = "tokio")]
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -97,7 +305,7 @@ impl PackageJson {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use maplit::hashmap;
+    use maplit::btreemap;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -117,7 +325,7 @@ mod tests {
                 name: "dinopkg-package-json".into(),
                 version: "0.1.0".into(),
                 author: Some(AuthorVariant::String("Skyfall".into())),
-                dependencies: Some(hashmap! {
+                dependencies: Some(btreemap! {
                     "express".into() => "^4.17.1".into(),
                 }),
                 ..Default::default()
@@ -143,6 +351,7 @@ mod tests {
                 version: "0.1.0".into(),
                 author: Some(AuthorVariant::Author {
                     name: "Skyfall".into(),
+                    email: None,
                     url: Some("https://skyfall.dev".into())
                 }),
                 ..Default::default()
@@ -168,10 +377,121 @@ mod tests {
                 version: "0.1.0".into(),
                 repository: Some(RepositoryVariant::Repository {
                     r#type: "git".into(),
-                    url: Some("git+https://github.com/SkyfallWasTaken/choco.git".into())
+                    url: Some("git+https://github.com/SkyfallWasTaken/choco.git".into()),
+                    directory: None,
                 }),
                 ..Default::default()
             }
         )
     }
+
+    #[test]
+    fn keeps_every_field() {
+        let json = r##"{
+            "name": "@ourco/widgets",
+            "version": "2.1.0",
+            "description": "Widgets, but better",
+            "keywords": ["widgets"],
+            "homepage": "https://ourco.com/widgets",
+            "bugs": { "url": "https://github.com/ourco/widgets/issues", "email": "bugs@ourco.com" },
+            "author": { "name": "Skyfall", "email": "me@skyfall.dev" },
+            "contributors": ["Someone Else <else@ourco.com>", { "name": "Another" }],
+            "funding": [{ "type": "github", "url": "https://github.com/sponsors/ourco" }, "https://ourco.com/donate"],
+            "license": "MIT",
+            "repository": { "type": "git", "url": "https://github.com/ourco/monorepo.git", "directory": "packages/widgets" },
+            "type": "module",
+            "main": "./dist/index.cjs",
+            "types": "./dist/index.d.ts",
+            "bin": { "widgets": "./bin/widgets.js" },
+            "files": ["dist", "bin"],
+            "exports": {
+                ".": { "types": "./dist/index.d.ts", "import": "./dist/index.js", "require": "./dist/index.cjs" },
+                "./internal/*": null,
+                "./polyfill": ["./dist/polyfill.js", "./dist/polyfill-fallback.js"]
+            },
+            "imports": { "#dep": { "node": "dep-node-native", "default": "./dep-polyfill.js" } },
+            "scripts": { "build": "tsc" },
+            "dependencies": { "left-pad": "^1.3.0" },
+            "peerDependencies": { "react": ">=17" },
+            "peerDependenciesMeta": { "react": { "optional": true } },
+            "optionalDependencies": { "fsevents": "^2.3.0" },
+            "bundleDependencies": ["left-pad"],
+            "overrides": { "foo": "1.0.0", "bar": { ".": "2.0.0", "baz": "$left-pad" } },
+            "engines": { "node": ">=18" },
+            "os": ["darwin", "linux"],
+            "cpu": ["x64", "arm64"],
+            "workspaces": { "packages": ["packages/*"], "nohoist": ["**/react"] },
+            "publishConfig": { "access": "public", "provenance": true },
+            "sideEffects": false,
+            "prettier": { "semi": false }
+        }"##;
+        let package_json = PackageJson::parse(json).unwrap();
+
+        assert_eq!(package_json.r#type, Some(ModuleType::Module));
+        assert_eq!(
+            package_json.workspaces.as_ref().unwrap().globs(),
+            ["packages/*"]
+        );
+        assert_eq!(
+            package_json.bundle_dependencies,
+            Some(BundleDependencies::List(vec!["left-pad".into()]))
+        );
+        let Some(Exports::Map(exports)) = &package_json.exports else {
+            panic!("exports should be a map");
+        };
+        assert_eq!(exports["./internal/*"], Exports::Hidden);
+        let Exports::Map(conditions) = &exports["."] else {
+            panic!("`.` should have conditions");
+        };
+        assert_eq!(
+            conditions.keys().collect::<Vec<_>>(),
+            ["types", "import", "require"]
+        );
+        assert_eq!(package_json.extra["sideEffects"], Value::Bool(false));
+
+        let written = serde_json::to_value(&package_json).unwrap();
+        assert_eq!(written, serde_json::from_str::<Value>(json).unwrap());
+    }
+
+    #[test]
+    fn reads_alternative_spellings() {
+        let json = r#"{
+            "name": "a",
+            "version": "1.0.0",
+            "typings": "index.d.ts",
+            "bin": "cli.js",
+            "bundledDependencies": true,
+            "workspaces": ["packages/*"]
+        }"#;
+        let package_json = PackageJson::parse(json).unwrap();
+        assert_eq!(package_json.types.as_deref(), Some("index.d.ts"));
+        assert_eq!(package_json.bin, Some(BinVariant::String("cli.js".into())));
+        assert_eq!(
+            package_json.bundle_dependencies,
+            Some(BundleDependencies::All(true))
+        );
+        assert_eq!(
+            package_json.workspaces,
+            Some(Workspaces::Globs(vec!["packages/*".into()]))
+        );
+    }
+
+    #[test]
+    fn writes_keys_in_a_stable_order() {
+        let json = r#"{
+            "name": "a",
+            "version": "1.0.0",
+            "scripts": { "test": "vitest", "build": "tsc", "lint": "eslint ." },
+            "dependencies": { "zod": "^3.0.0", "@types/node": "^20", "express": "^4.0.0" }
+        }"#;
+        let write = || serde_json::to_string(&PackageJson::parse(json).unwrap()).unwrap();
+        let written = write();
+        assert_eq!(
+            written,
+            r#"{"name":"a","version":"1.0.0","scripts":{"test":"vitest","build":"tsc","lint":"eslint ."},"dependencies":{"@types/node":"^20","express":"^4.0.0","zod":"^3.0.0"}}"#
+        );
+        for _ in 0..20 {
+            assert_eq!(write(), written);
+        }
+    }
 }
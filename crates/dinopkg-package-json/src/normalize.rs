@@ -0,0 +1,411 @@
+//! Cleans up `package.json` files from the wild, like npm's
+//! [normalize-package-data](https://github.com/npm/normalize-package-data).
+//!
+//! The registry has every version of every package ever published, and old ones often
+//! have fields that don't match today's schema: an array of authors, dependencies as a
+//! list, a number as a version. Rather than refusing the whole file, fields that can be
+//! fixed are, and anything else that doesn't parse is dropped. Both leave a [`Warning`].
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use crate::PackageJson;
+
+/// Something that was fixed or dropped while normalising.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` {}", self.field, self.message)
+    }
+}
+
+const DEPENDENCY_FIELDS: &[&str] = &[
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+];
+
+/// Fields that should be a string, or are otherwise dropped.
+const STRING_FIELDS: &[&str] = &["description", "homepage", "main", "types", "typings"];
+
+/// Fields that should be a list of strings. A single string becomes a list of one.
+const LIST_FIELDS: &[&str] = &["files", "os", "cpu"];
+
+/// Every field [`PackageJson`] has a type for, in its JSON spelling.
+const KNOWN_FIELDS: &[&str] = &[
+    "name",
+    "version",
+    "description",
+    "keywords",
+    "homepage",
+    "bugs",
+    "author",
+    "contributors",
+    "funding",
+    "private",
+    "license",
+    "repository",
+    "type",
+    "main",
+    "types",
+    "typings",
+    "bin",
+    "files",
+    "exports",
+    "imports",
+    "scripts",
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "peerDependenciesMeta",
+    "optionalDependencies",
+    "bundleDependencies",
+    "bundledDependencies",
+    "overrides",
+    "engines",
+    "os",
+    "cpu",
+    "workspaces",
+    "publishConfig",
+    "trustedDependencies",
+];
+
+impl PackageJson {
+    /// Reads a `package.json` that might not match the schema, fixing or dropping
+    /// fields instead of failing. Only a value that isn't an object at all can't be read.
+    pub fn from_value_lenient(mut value: Value) -> Option<(Self, Vec<Warning>)> {
+        let warnings = normalize(value.as_object_mut()?);
+        let package_json = serde_json::from_value(value).ok()?;
+        Some((package_json, warnings))
+    }
+}
+
+/// Fixes or drops any fields in `fields` that don't match the `package.json` schema.
+pub fn normalize(fields: &mut Map<String, Value>) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut warn = |field: &str, message: &str| {
+        warnings.push(Warning {
+            field: field.into(),
+            message: message.into(),
+        });
+    };
+
+    for field in ["name", "version"] {
+        match fields.get(field) {
+            Some(Value::String(_)) => {}
+            Some(Value::Number(number)) => {
+                let number = number.to_string();
+                warn(field, "should be a string");
+                fields.insert(field.into(), number.into());
+            }
+            _ => {
+                warn(field, "is missing");
+                fields.insert(field.into(), "".into());
+            }
+        }
+    }
+
+    // Both spellings can't be used at once.
+    for (field, alias) in [
+        ("types", "typings"),
+        ("bundleDependencies", "bundledDependencies"),
+    ] {
+        if fields.contains_key(field) && fields.remove(alias).is_some() {
+            warn(
+                alias,
+                &format!("is the same as `{field}`, so it was ignored"),
+            );
+        }
+    }
+
+    if let Some(Value::Array(authors)) = fields.get("author") {
+        let first = authors.first().cloned().unwrap_or(Value::Null);
+        warn("author", "should be one person, so only the first was kept");
+        fields.insert("author".into(), first);
+    }
+    if let Some(author) = fields.get_mut("author") {
+        if !normalize_person(author) {
+            warn("author", "isn't a person, so it was ignored");
+            fields.remove("author");
+        }
+    }
+    if let Some(contributors) = fields.get_mut("contributors") {
+        if !contributors.is_array() {
+            *contributors = Value::Array(vec![contributors.take()]);
+        }
+        if let Value::Array(people) = contributors {
+            let before = people.len();
+            people.retain_mut(normalize_person);
+            if people.len() < before {
+                warn(
+                    "contributors",
+                    "had entries that aren't people, which were ignored",
+                );
+            }
+        }
+    }
+
+    if let Some(Value::Object(repository)) = fields.get_mut("repository") {
+        if !repository.contains_key("type") {
+            repository.insert("type".into(), "git".into());
+        }
+    }
+
+    if let Some(license) = fields.get("license") {
+        // Old packages used `{ "type": "MIT" }`, or a list of them.
+        let license = match license {
+            Value::Object(license) => license.get("type").cloned(),
+            Value::Array(licenses) => {
+                let types: Option<Vec<&str>> = licenses
+                    .iter()
+                    .map(|license| license.get("type").and_then(Value::as_str))
+                    .collect();
+                types.map(|types| Value::from(types.join(" OR ")))
+            }
+            _ => None,
+        };
+        if let Some(license) = license {
+            warn("license", "should be an SPDX expression");
+            fields.insert("license".into(), license);
+        }
+    }
+
+    if let Some(Value::String(keywords)) = fields.get("keywords") {
+        let keywords: Vec<Value> = keywords
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|keyword| !keyword.is_empty())
+            .map(Value::from)
+            .collect();
+        warn("keywords", "should be a list");
+        fields.insert("keywords".into(), keywords.into());
+    }
+
+    for field in LIST_FIELDS {
+        if let Some(Value::String(item)) = fields.get(*field) {
+            let list = Value::Array(vec![item.as_str().into()]);
+            warn(field, "should be a list");
+            fields.insert((*field).into(), list);
+        }
+    }
+
+    for field in DEPENDENCY_FIELDS {
+        let Some(dependencies) = fields.get_mut(*field) else {
+            continue;
+        };
+        if let Value::String(list) = dependencies {
+            let list = list
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .map(Value::from)
+                .collect();
+            *dependencies = Value::Array(list);
+        }
+        if let Value::Array(list) = dependencies {
+            warn(field, "should be an object, not a list");
+            *dependencies = Value::Object(dependency_list(list));
+        }
+        if let Value::Object(dependencies) = dependencies {
+            let before = dependencies.len();
+            dependencies.retain(|_, range| range.is_string());
+            if dependencies.len() < before {
+                warn(
+                    field,
+                    "had versions that aren't strings, which were ignored",
+                );
+            }
+        }
+    }
+
+    for field in ["scripts", "engines"] {
+        if let Some(Value::Object(values)) = fields.get_mut(field) {
+            let before = values.len();
+            values.retain(|_, value| value.is_string());
+            if values.len() < before {
+                warn(field, "had values that aren't strings, which were ignored");
+            }
+        }
+    }
+    if let Some(Value::Object(bin)) = fields.get_mut("bin") {
+        bin.retain(|_, path| path.is_string());
+    }
+
+    for field in STRING_FIELDS {
+        if fields.get(*field).is_some_and(|value| !value.is_string()) {
+            warn(field, "should be a string, so it was ignored");
+            fields.remove(*field);
+        }
+    }
+
+    // Anything that still doesn't match its type is dropped. This is only worth
+    // checking field by field if the whole thing doesn't parse.
+    if serde_json::from_value::<PackageJson>(Value::Object(fields.clone())).is_err() {
+        for field in KNOWN_FIELDS {
+            let Some(value) = fields.get(*field) else {
+                continue;
+            };
+            let alone = serde_json::json!({ "name": "", "version": "", *field: value });
+            if serde_json::from_value::<PackageJson>(alone).is_err() {
+                warn(field, "is malformed, so it was ignored");
+                fields.remove(*field);
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Turns a person into an author, or returns `false` if it isn't one.
+fn normalize_person(person: &mut Value) -> bool {
+    match person {
+        Value::String(_) => true,
+        Value::Object(fields) => {
+            fields.retain(|_, value| value.is_string());
+            fields.contains_key("name")
+        }
+        _ => false,
+    }
+}
+
+/// Turns old-style dependency lists, like `["a@^1.0.0", "b >=2"]`, into an object.
+fn dependency_list(list: &[Value]) -> Map<String, Value> {
+    list.iter()
+        .filter_map(Value::as_str)
+        .map(str::trim)
+        .filter(|dependency| !dependency.is_empty())
+        .map(|dependency| {
+            // Skip the first character, so `@scope/a` isn't split.
+            let split = dependency
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| *c == '@' || c.is_whitespace() || "<>=".contains(*c))
+                .map_or(dependency.len(), |(index, _)| index);
+            let (name, range) = dependency.split_at(split);
+            let range = range.strip_prefix('@').unwrap_or(range).trim();
+            (name.to_owned(), Value::from(range))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorVariant, RepositoryVariant};
+    use maplit::btreemap;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn fixes_what_it_can() {
+        let (package_json, warnings) = PackageJson::from_value_lenient(json!({
+            "name": "old",
+            "version": 1,
+            "author": ["a", "b"],
+            "contributors": { "name": "c", "email": ["not", "a", "string"] },
+            "license": [{ "type": "MIT" }, { "type": "Apache-2.0" }],
+            "repository": { "url": "https://github.com/old/old" },
+            "keywords": "old, crusty",
+            "os": "linux",
+            "dependencies": ["a@^1.0.0", "@scope/b >=2", "c"],
+            "devDependencies": { "d": "^1.0.0", "e": 2 },
+        }))
+        .unwrap();
+
+        assert_eq!(package_json.version, "1");
+        assert_eq!(package_json.author, Some(AuthorVariant::String("a".into())));
+        assert_eq!(
+            package_json.contributors,
+            Some(vec![AuthorVariant::Author {
+                name: "c".into(),
+                email: None,
+                url: None
+            }])
+        );
+        assert_eq!(package_json.license.as_deref(), Some("MIT OR Apache-2.0"));
+        assert_eq!(
+            package_json.repository,
+            Some(RepositoryVariant::Repository {
+                r#type: "git".into(),
+                url: Some("https://github.com/old/old".into()),
+                directory: None,
+            })
+        );
+        assert_eq!(
+            package_json.keywords,
+            Some(vec!["old".into(), "crusty".into()])
+        );
+        assert_eq!(package_json.os, Some(vec!["linux".into()]));
+        assert_eq!(
+            package_json.dependencies,
+            Some(btreemap! {
+                "a".into() => "^1.0.0".into(),
+                "@scope/b".into() => ">=2".into(),
+                "c".into() => "".into(),
+            })
+        );
+        assert_eq!(
+            package_json.dev_dependencies,
+            Some(btreemap! { "d".into() => "^1.0.0".into() })
+        );
+        assert_eq!(
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "`version` should be a string",
+                "`author` should be one person, so only the first was kept",
+                "`license` should be an SPDX expression",
+                "`keywords` should be a list",
+                "`os` should be a list",
+                "`dependencies` should be an object, not a list",
+                "`devDependencies` had versions that aren't strings, which were ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_what_it_cant() {
+        let (package_json, warnings) = PackageJson::from_value_lenient(json!({
+            "name": "old",
+            "version": "1.0.0",
+            "author": 42,
+            "main": ["index.js"],
+            "bugs": true,
+            "workspaces": "packages/*",
+            "trustedDependencies": { "esbuild": true },
+            "bin": { "old": "cli.js", "broken": null },
+            "scripts": { "test": "tap", "nope": false },
+            "custom": [1, 2, 3],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            package_json,
+            PackageJson {
+                name: "old".into(),
+                version: "1.0.0".into(),
+                bin: Some(crate::BinVariant::Map(
+                    [("old".into(), "cli.js".into())].into()
+                )),
+                scripts: Some([("test".into(), "tap".into())].into()),
+                extra: [("custom".into(), json!([1, 2, 3]))].into(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "`author` isn't a person, so it was ignored",
+                "`scripts` had values that aren't strings, which were ignored",
+                "`main` should be a string, so it was ignored",
+                "`bugs` is malformed, so it was ignored",
+                "`workspaces` is malformed, so it was ignored",
+                "`trustedDependencies` is malformed, so it was ignored",
+            ]
+        );
+        assert!(PackageJson::from_value_lenient(json!("nope")).is_none());
+    }
+}
@@ -0,0 +1,536 @@
+//! Dependency specs, like the values in `dependencies` and the arguments to
+//! `dino install`, parsed the way [npm-package-arg](https://github.com/npm/npm-package-arg)
+//! does.
+
+use std::fmt;
+use std::str::FromStr;
+
+use dinopkg_semver::{Options, Range};
+
+use crate::Error;
+
+/// Where a dependency comes from, and which versions of it are wanted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencySpec {
+    /// A semver range or exact version from the registry, like `^1.2.3`. An empty spec
+    /// is `*`.
+    Range(String),
+
+    /// A dist-tag from the registry, like `latest` or `beta`.
+    Tag(String),
+
+    /// Another registry package, installed under the dependency's name, like
+    /// `npm:lodash@^4`.
+    Alias {
+        name: String,
+        spec: Box<DependencySpec>,
+    },
+
+    /// A tarball on disk, like `file:../lib.tgz`.
+    File(String),
+
+    /// A directory on disk, like `file:../lib` or `./lib`.
+    Directory(String),
+
+    /// A directory on disk that's symlinked rather than copied, like `link:../lib`.
+    Link(String),
+
+    /// Another package in the same workspace. This is `*`, `^`, `~` or a range.
+    Workspace(String),
+
+    Git(GitSpec),
+
+    /// A tarball URL.
+    Remote(String),
+}
+
+/// A git repository, and which commit of it to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSpec {
+    /// The spec as it was written, without the `#...` part, like `github:user/repo`.
+    pub source: String,
+
+    /// The URL to clone.
+    pub url: String,
+
+    /// A branch, tag or commit, from `#committish`.
+    pub committish: Option<String>,
+
+    /// A range to match against the repository's tags, from `#semver:^1.0.0`.
+    pub semver: Option<String>,
+}
+
+/// An argument to `dino install`, like `lodash@^4`, `@scope/pkg@beta` or `./local-lib`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageArg {
+    /// The package's name, unless it has to be found from the spec, like for
+    /// `./local-lib` or `github:user/repo`.
+    pub name: Option<String>,
+    pub spec: DependencySpec,
+}
+
+/// Hosts whose repositories can be written as `host:user/repo`.
+const GIT_HOSTS: &[(&str, &str)] = &[
+    ("github", "github.com"),
+    ("gitlab", "gitlab.com"),
+    ("bitbucket", "bitbucket.org"),
+];
+
+impl DependencySpec {
+    /// Parses the value of a dependency in `package.json`.
+    ///
+    /// # Errors
+    /// This function fails if the spec is an invalid tag, or an alias to an invalid name.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let spec = spec.trim();
+        if let Some(aliased) = spec.strip_prefix("npm:") {
+            let (name, spec) = split_name(aliased);
+            validate_name(name)?;
+            let spec = Self::parse(spec.unwrap_or_default())?;
+            if !spec.is_registry() {
+                return Err(Error::InvalidAlias(aliased.into()));
+            }
+            return Ok(Self::Alias {
+                name: name.into(),
+                spec: Box::new(spec),
+            });
+        }
+        if let Some(range) = spec.strip_prefix("workspace:") {
+            return Ok(Self::Workspace(range.into()));
+        }
+        if let Some(path) = spec.strip_prefix("link:") {
+            return Ok(Self::Link(path.into()));
+        }
+        if let Some(path) = spec.strip_prefix("file:") {
+            return Ok(Self::local(path));
+        }
+        if is_path(spec) {
+            return Ok(Self::local(spec));
+        }
+        if let Some(git) = GitSpec::parse(spec) {
+            return Ok(Self::Git(git));
+        }
+        if spec.starts_with("https://") || spec.starts_with("http://") {
+            return Ok(Self::Remote(spec.into()));
+        }
+        if is_tarball_name(spec) {
+            return Ok(Self::File(spec.into()));
+        }
+
+        if spec.is_empty() {
+            return Ok(Self::Range("*".into()));
+        }
+        let loose = Options {
+            loose: true,
+            ..Default::default()
+        };
+        if Range::parse_with(spec, loose).is_ok() {
+            return Ok(Self::Range(spec.into()));
+        }
+        // Tags end up in URLs, so they can't have anything that would need escaping.
+        if spec
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.!~*'()".contains(c))
+        {
+            return Ok(Self::Tag(spec.into()));
+        }
+        Err(Error::InvalidTag(spec.into()))
+    }
+
+    /// Whether this comes from the registry: a range, tag or alias.
+    pub fn is_registry(&self) -> bool {
+        matches!(self, Self::Range(_) | Self::Tag(_) | Self::Alias { .. })
+    }
+
+    /// A path on disk is either a tarball or a directory, depending on its extension.
+    fn local(path: &str) -> Self {
+        if is_tarball_name(path) {
+            Self::File(path.into())
+        } else {
+            Self::Directory(path.into())
+        }
+    }
+}
+
+impl FromStr for DependencySpec {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        Self::parse(spec)
+    }
+}
+
+impl fmt::Display for DependencySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Range(spec) | Self::Tag(spec) | Self::Remote(spec) => f.write_str(spec),
+            Self::Alias { name, spec } => write!(f, "npm:{name}@{spec}"),
+            Self::File(path) | Self::Directory(path) => write!(f, "file:{path}"),
+            Self::Link(path) => write!(f, "link:{path}"),
+            Self::Workspace(range) => write!(f, "workspace:{range}"),
+            Self::Git(git) => git.fmt(f),
+        }
+    }
+}
+
+impl GitSpec {
+    /// Parses `spec` if it's a git repository.
+    fn parse(spec: &str) -> Option<Self> {
+        let (source, fragment) = match spec.split_once('#') {
+            Some((source, fragment)) => (source, Some(fragment)),
+            None => (spec, None),
+        };
+        let url = git_url(source)?;
+
+        let mut git = Self {
+            source: source.into(),
+            url,
+            committish: None,
+            semver: None,
+        };
+        // npm allows several `::`-separated parts, like `#semver:^1.0.0::path:packages/a`.
+        for part in fragment
+            .into_iter()
+            .flat_map(|fragment| fragment.split("::"))
+        {
+            if let Some(range) = part.strip_prefix("semver:") {
+                git.semver = Some(range.into());
+            } else if !part.is_empty() && !part.contains(':') {
+                git.committish = Some(part.into());
+            }
+        }
+        Some(git)
+    }
+}
+
+impl fmt::Display for GitSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)?;
+        if let Some(range) = &self.semver {
+            write!(f, "#semver:{range}")
+        } else if let Some(committish) = &self.committish {
+            write!(f, "#{committish}")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl PackageArg {
+    /// Parses an argument to `dino install`.
+    ///
+    /// # Errors
+    /// This function fails if the name or spec is invalid.
+    pub fn parse(arg: &str) -> Result<Self, Error> {
+        let arg = arg.trim();
+        let (name, spec) = split_name(arg);
+        // Like npm, anything with a protocol, or a slash that isn't a scope, is a spec
+        // without a name.
+        let has_protocol = {
+            let scheme = name.strip_prefix("git+").unwrap_or(name);
+            scheme.find(':').is_some_and(|colon| {
+                colon > 0 && scheme[..colon].bytes().all(|b| b.is_ascii_alphabetic())
+            })
+        };
+        let is_path = !name.starts_with('@') && (name.contains('/') || is_tarball_name(name));
+        if has_protocol || is_path || is_scp_url(arg) {
+            return Ok(Self {
+                name: None,
+                spec: DependencySpec::parse(arg)?,
+            });
+        }
+
+        validate_name(name)?;
+        Ok(Self {
+            name: Some(name.into()),
+            spec: DependencySpec::parse(spec.unwrap_or_default())?,
+        })
+    }
+}
+
+impl FromStr for PackageArg {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        Self::parse(arg)
+    }
+}
+
+/// Splits `name@spec`, where the name might have a scope.
+fn split_name(arg: &str) -> (&str, Option<&str>) {
+    let search_from = usize::from(arg.starts_with('@'));
+    match arg[search_from..].find('@') {
+        Some(at) => (&arg[..search_from + at], Some(&arg[search_from + at + 1..])),
+        None => (arg, None),
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), Error> {
+    validate_package_name::validate_existing(name).map_err(|source| Error::InvalidName {
+        name: name.into(),
+        source,
+    })
+}
+
+/// Whether `arg` is an scp-like git URL, such as `git@github.com:user/repo.git`.
+fn is_scp_url(arg: &str) -> bool {
+    let Some((user, rest)) = arg.split_once('@') else {
+        return false;
+    };
+    let Some((host, path)) = rest.split_once(':') else {
+        return false;
+    };
+    !user.is_empty() && !user.contains('/') && host.contains('.') && !path.is_empty()
+}
+
+fn is_path(spec: &str) -> bool {
+    let bytes = spec.as_bytes();
+    let windows_drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && b"/\\".contains(&bytes[2]);
+    spec.starts_with(['.', '/', '\\']) || spec.starts_with("~/") || windows_drive
+}
+
+fn is_tarball_name(spec: &str) -> bool {
+    let spec = spec.to_ascii_lowercase();
+    [".tgz", ".tar.gz", ".tar"]
+        .iter()
+        .any(|extension| spec.ends_with(extension))
+}
+
+/// Returns the URL to clone if `source` is a git repository, in any of the forms npm
+/// understands.
+fn git_url(source: &str) -> Option<String> {
+    if let Some(url) = source.strip_prefix("git+") {
+        return Some(url.into());
+    }
+    if source.starts_with("git://") {
+        return Some(source.into());
+    }
+    if let Some(id) = source.strip_prefix("gist:") {
+        return Some(format!("https://gist.github.com/{id}.git"));
+    }
+    for (shortcut, host) in GIT_HOSTS {
+        if let Some(path) = source
+            .strip_prefix(shortcut)
+            .and_then(|s| s.strip_prefix(':'))
+        {
+            return hosted_path(path).map(|path| format!("https://{host}/{path}.git"));
+        }
+    }
+    if let Some(rest) = source
+        .strip_prefix("https://")
+        .or_else(|| source.strip_prefix("http://"))
+    {
+        // Only repositories on known hosts, like `https://github.com/user/repo`.
+        let (host, path) = rest.split_once('/')?;
+        GIT_HOSTS.iter().find(|(_, known)| *known == host)?;
+        return hosted_path(path).map(|path| format!("https://{host}/{path}.git"));
+    }
+    if !source.contains("://") {
+        // `git@github.com:user/repo.git`, like scp.
+        if let Some((user_host, path)) = source.split_once(':') {
+            let is_scp = user_host.contains('@') && !path.is_empty() && !path.starts_with('/');
+            return is_scp.then(|| source.into());
+        }
+        // `user/repo` is short for GitHub.
+        return hosted_path(source).map(|path| format!("https://github.com/{path}.git"));
+    }
+    None
+}
+
+/// Returns `user/repo` if `path` is a repository on a git host, without any `.git`.
+fn hosted_path(path: &str) -> Option<&str> {
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (user, repo) = path.split_once('/')?;
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    };
+    (valid(user) && valid(repo)).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn parse(spec: &str) -> DependencySpec {
+        DependencySpec::parse(spec).unwrap()
+    }
+
+    fn git(
+        source: &str,
+        url: &str,
+        committish: Option<&str>,
+        semver: Option<&str>,
+    ) -> DependencySpec {
+        DependencySpec::Git(GitSpec {
+            source: source.into(),
+            url: url.into(),
+            committish: committish.map(Into::into),
+            semver: semver.map(Into::into),
+        })
+    }
+
+    #[test]
+    fn parses_registry_specs() {
+        assert_eq!(parse("^4.17.1"), DependencySpec::Range("^4.17.1".into()));
+        assert_eq!(
+            parse("1.x || >=2.5.0"),
+            DependencySpec::Range("1.x || >=2.5.0".into())
+        );
+        assert_eq!(parse(""), DependencySpec::Range("*".into()));
+        assert_eq!(parse("=1.2.3"), DependencySpec::Range("=1.2.3".into()));
+        assert_eq!(parse("beta"), DependencySpec::Tag("beta".into()));
+        assert_eq!(
+            parse("npm:@types/node@^20"),
+            DependencySpec::Alias {
+                name: "@types/node".into(),
+                spec: Box::new(DependencySpec::Range("^20".into())),
+            }
+        );
+        assert!(matches!(
+            DependencySpec::parse("not a tag!?"),
+            Err(Error::InvalidTag(_))
+        ));
+        assert!(matches!(
+            DependencySpec::parse("npm:a@file:../a"),
+            Err(Error::InvalidAlias(_))
+        ));
+        assert!(matches!(
+            DependencySpec::parse("npm:_bad@1"),
+            Err(Error::InvalidName { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_local_specs() {
+        assert_eq!(
+            parse("file:../lib"),
+            DependencySpec::Directory("../lib".into())
+        );
+        assert_eq!(parse("./lib"), DependencySpec::Directory("./lib".into()));
+        assert_eq!(
+            parse("file:lib.tgz"),
+            DependencySpec::File("lib.tgz".into())
+        );
+        assert_eq!(
+            parse("../lib-1.0.0.tar.gz"),
+            DependencySpec::File("../lib-1.0.0.tar.gz".into())
+        );
+        assert_eq!(parse("link:../lib"), DependencySpec::Link("../lib".into()));
+        assert_eq!(parse("workspace:^"), DependencySpec::Workspace("^".into()));
+        assert_eq!(parse("./lib").to_string(), "file:./lib");
+    }
+
+    #[test]
+    fn parses_remote_specs() {
+        assert_eq!(
+            parse("github:user/repo#semver:^1.0.0"),
+            git(
+                "github:user/repo",
+                "https://github.com/user/repo.git",
+                None,
+                Some("^1.0.0")
+            )
+        );
+        assert_eq!(
+            parse("user/repo#main"),
+            git(
+                "user/repo",
+                "https://github.com/user/repo.git",
+                Some("main"),
+                None
+            )
+        );
+        assert_eq!(
+            parse("git+ssh://git@example.com/repo.git#v1.0.0"),
+            git(
+                "git+ssh://git@example.com/repo.git",
+                "ssh://git@example.com/repo.git",
+                Some("v1.0.0"),
+                None
+            )
+        );
+        assert_eq!(
+            parse("git@github.com:user/repo.git"),
+            git(
+                "git@github.com:user/repo.git",
+                "git@github.com:user/repo.git",
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            parse("https://github.com/user/repo"),
+            git(
+                "https://github.com/user/repo",
+                "https://github.com/user/repo.git",
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            parse("https://example.com/a-1.0.0.tgz"),
+            DependencySpec::Remote("https://example.com/a-1.0.0.tgz".into())
+        );
+        assert_eq!(
+            parse("github:user/repo#semver:^1.0.0").to_string(),
+            "github:user/repo#semver:^1.0.0"
+        );
+    }
+
+    #[test]
+    fn parses_install_arguments() {
+        let arg = |arg: &str| PackageArg::parse(arg).unwrap();
+        assert_eq!(
+            arg("lodash@^4"),
+            PackageArg {
+                name: Some("lodash".into()),
+                spec: DependencySpec::Range("^4".into()),
+            }
+        );
+        assert_eq!(
+            arg("@scope/pkg@beta"),
+            PackageArg {
+                name: Some("@scope/pkg".into()),
+                spec: DependencySpec::Tag("beta".into()),
+            }
+        );
+        assert_eq!(
+            arg("@scope/pkg"),
+            PackageArg {
+                name: Some("@scope/pkg".into()),
+                spec: DependencySpec::Range("*".into()),
+            }
+        );
+        assert_eq!(
+            arg("./local-lib"),
+            PackageArg {
+                name: None,
+                spec: DependencySpec::Directory("./local-lib".into()),
+            }
+        );
+        assert_eq!(arg("github:user/repo").name, None);
+        assert_eq!(arg("lib.tgz").spec, DependencySpec::File("lib.tgz".into()));
+        assert_eq!(arg("left@npm:right@1").spec.to_string(), "npm:right@1");
+        assert_eq!(
+            arg("lib@git+ssh://git@example.com/lib.git#v1.2.0")
+                .name
+                .as_deref(),
+            Some("lib")
+        );
+        assert_eq!(arg("git@github.com:user/repo.git").name, None);
+        assert_eq!(arg("git+ssh://git@example.com/lib.git").name, None);
+        assert!(matches!(
+            PackageArg::parse("Bad Name@1"),
+            Err(Error::InvalidName { .. })
+        ));
+    }
+}
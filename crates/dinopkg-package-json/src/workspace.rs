@@ -0,0 +1,163 @@
+//! Finding the packages in a monorepo from the root's `workspaces`.
+
+use std::fs;
+use std::path::Path;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::{Error, PackageJson, Workspaces};
+
+/// A package in a monorepo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// Where the package is, relative to the root, with forward slashes like `packages/a`.
+    pub path: String,
+    pub package_json: PackageJson,
+}
+
+/// Directories that are never searched for workspaces.
+const SKIPPED: &[&str] = &["node_modules", ".git"];
+
+/// Finds every directory under `root` that matches `workspaces` and has a `package.json`,
+/// sorted by path.
+///
+/// Patterns are globs like `packages/*`, and ones starting with `!` exclude directories.
+///
+/// # Errors
+/// This function fails if a pattern is invalid, if a workspace's `package.json` can't
+/// be read, or if two workspaces have the same name.
+pub fn find_workspaces(root: &Path, workspaces: &Workspaces) -> Result<Vec<Workspace>, Error> {
+    let mut included = GlobSetBuilder::new();
+    let mut excluded = GlobSetBuilder::new();
+    // Patterns without `**` can only match so deep, so there's no need to look further.
+    let mut max_depth = Some(0);
+    for pattern in workspaces.globs() {
+        let (set, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (&mut excluded, pattern),
+            None => (&mut included, pattern.as_str()),
+        };
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        if pattern.is_empty() {
+            continue;
+        }
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|_| Error::InvalidWorkspacePattern(pattern.into()))?;
+        set.add(glob);
+        if pattern.contains("**") {
+            max_depth = None;
+        } else if let Some(depth) = &mut max_depth {
+            *depth = pattern.split('/').count().max(*depth);
+        }
+    }
+    let build = |set: GlobSetBuilder| {
+        set.build()
+            .map_err(|err| Error::InvalidWorkspacePattern(err.to_string()))
+    };
+    let (included, excluded) = (build(included)?, build(excluded)?);
+
+    let mut found: Vec<Workspace> = Vec::new();
+    let mut walker = walkdir::WalkDir::new(root).min_depth(1).sort_by_file_name();
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth);
+    }
+    let walker = walker.into_iter().filter_entry(|entry| {
+        entry.file_type().is_dir()
+            && !SKIPPED.contains(&entry.file_name().to_string_lossy().as_ref())
+    });
+    for entry in walker {
+        let entry = entry.map_err(std::io::Error::other)?;
+        let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if !matches(&included, &excluded, path) {
+            continue;
+        }
+        let manifest = entry.path().join("package.json");
+        if !manifest.is_file() {
+            continue;
+        }
+        let package_json = PackageJson::parse(&fs::read_to_string(manifest)?)?;
+        let path = slash_path(path);
+        if let Some(other) = found
+            .iter()
+            .find(|other| other.package_json.name == package_json.name)
+        {
+            return Err(Error::DuplicateWorkspace {
+                name: package_json.name,
+                first: other.path.clone(),
+                second: path,
+            });
+        }
+        found.push(Workspace { path, package_json });
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+fn matches(included: &GlobSet, excluded: &GlobSet, path: &Path) -> bool {
+    included.is_match(path) && !excluded.is_match(path)
+}
+
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn write(root: &Path, path: &str, name: &str) {
+        let dir = root.join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("package.json"),
+            format!(r#"{{"name":"{name}","version":"1.0.0"}}"#),
+        )
+        .unwrap();
+    }
+
+    fn paths(root: &Path, globs: &[&str]) -> Vec<String> {
+        let workspaces = Workspaces::Globs(globs.iter().map(|glob| (*glob).into()).collect());
+        find_workspaces(root, &workspaces)
+            .unwrap()
+            .into_iter()
+            .map(|workspace| workspace.path)
+            .collect()
+    }
+
+    #[test]
+    fn finds_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "packages/a", "a");
+        write(dir.path(), "packages/b", "b");
+        write(dir.path(), "packages/b/node_modules/x", "x");
+        write(dir.path(), "packages/nested/c", "c");
+        write(dir.path(), "tools/cli", "cli");
+        fs::create_dir_all(dir.path().join("packages/empty")).unwrap();
+
+        assert_eq!(
+            paths(dir.path(), &["packages/*", "./tools/cli/"]),
+            ["packages/a", "packages/b", "tools/cli"]
+        );
+        assert_eq!(
+            paths(dir.path(), &["packages/**", "!packages/b"]),
+            ["packages/a", "packages/nested/c"]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "packages/a", "same");
+        write(dir.path(), "packages/b", "same");
+        let workspaces = Workspaces::Globs(vec!["packages/*".into()]);
+        assert!(matches!(
+            find_workspaces(dir.path(), &workspaces),
+            Err(Error::DuplicateWorkspace { name, .. }) if name == "same"
+        ));
+    }
+}
@@ -0,0 +1,1791 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::path::{Component, Path};
+
+use dinopkg_npm_registry::{read_tarball_file, Dist, Integrity, Manifest, Packument};
+use dinopkg_package_json::{
+    find_workspaces, Dependencies, DependencySpec, GitSpec, PackageJson, Workspace,
+};
+use dinopkg_semver::{Range, Version};
+
+mod registry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub use registry::{GitPackage, MemoryRegistry, Registry, WithGit};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("registry error: {0}")]
+    Registry(#[from] dinopkg_npm_registry::Error),
+
+    /// Dependency names become paths in `node_modules`, so they have to be valid package
+    /// names that can't point anywhere else.
+    #[error("invalid dependency name `{name}`: {source}")]
+    InvalidName {
+        name: String,
+        source: validate_package_name::Error,
+    },
+
+    #[error("invalid version range `{range}` for `{name}`")]
+    InvalidRange { name: String, range: String },
+
+    #[error("invalid spec `{spec}` for `{name}`: {source}")]
+    InvalidSpec {
+        name: String,
+        spec: String,
+        source: dinopkg_package_json::Error,
+    },
+
+    /// The spec is valid, but doesn't point at the registry, a git repository or a local path.
+    #[error(
+        "can't install `{name}@{spec}`, only registry, git and local dependencies are supported"
+    )]
+    UnsupportedSpec { name: String, spec: String },
+
+    /// Only packages on disk can depend on other packages on disk, since there's
+    /// nothing for the path to be relative to otherwise.
+    #[error("`{parent}` depends on `{name}@{spec}`, but it isn't on disk itself")]
+    RemoteLocalDependency {
+        parent: String,
+        name: String,
+        spec: String,
+    },
+
+    #[error("couldn't read `{path}`: {source}")]
+    Local {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("invalid package.json in `{path}`: {source}")]
+    LocalPackageJson {
+        path: String,
+        source: dinopkg_package_json::Error,
+    },
+
+    #[error("couldn't find workspaces: {0}")]
+    Workspaces(dinopkg_package_json::Error),
+
+    #[error("`{name}@{spec}` doesn't match any workspace")]
+    NoWorkspace { name: String, spec: String },
+
+    #[error("git error: {0}")]
+    Git(#[from] dinopkg_git::Error),
+
+    /// Git dependencies need somewhere to be cloned to, which a plain [`Registry`] doesn't
+    /// have. See [`WithGit`].
+    #[error("`{0}` is a git dependency, which can't be resolved without a git cache")]
+    NoGitCache(String),
+
+    /// Packuments that were needed, but weren't cached while offline.
+    #[error("not available offline: {}", .0.join(", "))]
+    NotCached(Vec<String>),
+
+    /// The package's `os` or `cpu` don't include the platform dino is running on.
+    #[error("`{package}` can't be installed on {os} ({cpu})")]
+    UnsupportedPlatform {
+        package: String,
+        os: &'static str,
+        cpu: &'static str,
+    },
+}
+
+/// Uniquely identifies a single version of a package.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PackageId {
+    pub name: String,
+    pub version: String,
+
+    /// Where the package comes from if it isn't the registry, as [`Source::resolved`]
+    /// describes it. Two commits (or a commit and the registry) can have the same
+    /// version, but they're still different packages.
+    pub source: Option<String>,
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// Where a package's contents come from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Source {
+    /// A tarball, usually from the registry, described by the package's `dist`.
+    #[default]
+    Registry,
+
+    /// A commit in a git repository.
+    Git { url: String, commit: String },
+
+    /// A directory on disk, which is packed and copied in as if it had been published.
+    ///
+    /// Like the other local sources, `path` uses forward slashes and is relative to
+    /// the project root (unless it's absolute).
+    Directory { path: String },
+
+    /// A tarball on disk.
+    Tarball { path: String },
+
+    /// A directory on disk that's symlinked into `node_modules`.
+    ///
+    /// Workspaces are links whose dependencies are installed along with everything
+    /// else. Other links are expected to install their own, so they aren't resolved.
+    Link { path: String },
+}
+
+impl Source {
+    /// Reads a lockfile's `resolved` field. Anything that isn't a git URL or a
+    /// `file:` path is a tarball.
+    ///
+    /// Links are marked with `link` in lockfiles, so they can't be told apart here.
+    pub fn from_resolved(resolved: &str) -> Self {
+        match DependencySpec::parse(resolved) {
+            Ok(DependencySpec::Git(GitSpec {
+                url,
+                committish: Some(commit),
+                ..
+            })) => Self::Git { url, commit },
+            Ok(DependencySpec::Directory(path)) if resolved.starts_with("file:") => {
+                Self::Directory { path }
+            }
+            Ok(DependencySpec::File(path)) if resolved.starts_with("file:") => {
+                Self::Tarball { path }
+            }
+            _ => Self::Registry,
+        }
+    }
+
+    /// What a lockfile's `resolved` field should be, if it's not the tarball URL.
+    pub fn resolved(&self) -> Option<String> {
+        match self {
+            Self::Registry => None,
+            Self::Git { url, commit } => Some(format!("git+{url}#{commit}")),
+            Self::Directory { path } | Self::Tarball { path } => Some(format!("file:{path}")),
+            Self::Link { path } => Some(path.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPackage {
+    pub id: PackageId,
+
+    pub source: Source,
+
+    /// Where to download the package from, if it comes from the registry.
+    pub dist: Dist,
+
+    /// The version's manifest, as published to the registry.
+    pub manifest: Manifest,
+
+    /// The package's own dependencies, mapped to the exact versions they resolved to.
+    pub dependencies: BTreeMap<String, PackageId>,
+
+    /// Whether the package is only needed because of a `devDependencies` entry.
+    pub dev: bool,
+
+    /// Whether the package is only needed because of an `optionalDependencies` entry.
+    pub optional: bool,
+
+    /// Whether the package is only needed because of a `peerDependencies` entry.
+    pub peer: bool,
+}
+
+/// A complete dependency graph with exact versions.
+///
+/// All maps are ordered, so iterating over a resolution is deterministic.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Resolution {
+    /// The root package's `dependencies`, mapped to the exact versions they resolved to.
+    pub dependencies: BTreeMap<String, PackageId>,
+
+    /// The root package's `devDependencies`, mapped to the exact versions they resolved to.
+    pub dev_dependencies: BTreeMap<String, PackageId>,
+
+    /// Every package in the graph.
+    pub packages: BTreeMap<PackageId, ResolvedPackage>,
+}
+
+/// Resolves every dependency of `package_json` to an exact version.
+///
+/// Local dependencies, like `file:../lib`, are relative to `root`, the directory
+/// `package_json` is in. If it has `workspaces`, they're found there and become
+/// dependencies of the root, along with their own dependencies.
+///
+/// Like npm 7 and above, peer dependencies are installed unless they're marked as
+/// optional. Optional dependencies are left out if they (or anything they need) can't
+/// be resolved, or can't be installed on this platform.
+///
+/// # Errors
+/// This function fails if a package can't be fetched, or if no version of a
+/// package satisfies the range it was requested with.
+pub async fn resolve<R: Registry>(
+    registry: &R,
+    package_json: &PackageJson,
+    root: &Path,
+) -> Result<Resolution, Error> {
+    resolve_from(registry, package_json, root, &Resolution::default()).await
+}
+
+/// Resolves like [`resolve`], but starting from `seed`, which is usually what a
+/// lockfile resolved to. Like npm, only new and changed dependencies are resolved.
+///
+/// A package's dependency keeps what it resolved to in `seed` if its spec hasn't
+/// changed there. The root's dependencies in `seed` are always kept, so any that have
+/// changed should be left out of it. Local packages are read again either way, since
+/// they could have changed on disk.
+///
+/// # Errors
+/// This function fails for the same reasons as [`resolve`].
+pub async fn resolve_from<R: Registry>(
+    registry: &R,
+    package_json: &PackageJson,
+    root: &Path,
+    seed: &Resolution,
+) -> Result<Resolution, Error> {
+    let empty = Dependencies::new();
+    let dev_dependencies = package_json.dev_dependencies.as_ref().unwrap_or(&empty);
+    let mut manifest = Manifest::from_package_json(package_json);
+    // A peer dependency that's also a dev dependency is only needed for development.
+    if let Some(peers) = &mut manifest.peer_dependencies {
+        peers.retain(|name, _| !dev_dependencies.contains_key(name));
+    }
+    let workspaces = match &package_json.workspaces {
+        Some(workspaces) => find_workspaces(root, workspaces).map_err(Error::Workspaces)?,
+        None => Vec::new(),
+    };
+
+    let mut resolver = Resolver {
+        registry,
+        root,
+        seed,
+        packuments: HashMap::new(),
+        resolution: Resolution::default(),
+        workspaces: BTreeMap::new(),
+        workspace_dev_dependencies: BTreeMap::new(),
+        kinds: BTreeMap::new(),
+        failed: BTreeSet::new(),
+        not_cached: BTreeSet::new(),
+    };
+    resolver
+        .run(&manifest, dev_dependencies, &workspaces)
+        .await?;
+    Ok(resolver.resolution)
+}
+
+/// Node's names for the operating system and CPU dino is running on, as in
+/// `process.platform` and `process.arch`.
+fn platform() -> (&'static str, &'static str) {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        "windows" => "win32",
+        "solaris" | "illumos" => "sunos",
+        os => os,
+    };
+    let cpu = match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "x86" => "ia32",
+        "aarch64" => "arm64",
+        "powerpc" => "ppc",
+        "powerpc64" => "ppc64",
+        "loongarch64" => "loong64",
+        cpu => cpu,
+    };
+    (os, cpu)
+}
+
+/// Whether a package with `manifest`'s `os` and `cpu` can be installed here.
+fn is_supported(manifest: &Manifest) -> bool {
+    // Like npm, entries starting with `!` exclude a platform, and the rest include one.
+    let allows = |list: &Option<Vec<String>>, value: &str| {
+        let Some(list) = list else {
+            return true;
+        };
+        let mut excluded = 0;
+        let mut included = false;
+        for entry in list {
+            match entry.strip_prefix('!') {
+                Some(entry) if entry == value => return false,
+                Some(_) => excluded += 1,
+                None => included |= entry == value || entry == "any",
+            }
+        }
+        included || excluded == list.len()
+    };
+    let (os, cpu) = platform();
+    allows(&manifest.os, os) && allows(&manifest.cpu, cpu)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Parent<'a> {
+    Root { dev: bool },
+    Package(&'a PackageId),
+}
+
+/// How a package depends on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Regular,
+
+    /// Left out if it can't be installed.
+    Optional,
+
+    /// Needed by the package, but meant to be shared with whatever depends on it.
+    Peer,
+}
+
+/// A dependency of a package, or of the root if there's no `parent`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Edge {
+    parent: Option<PackageId>,
+    name: String,
+}
+
+struct Request {
+    parent: Option<PackageId>,
+    dev: bool,
+    kind: Kind,
+
+    /// The optional dependency this request is needed for (which might be this one), if
+    /// any. If the request fails, that dependency is left out instead.
+    optional: Option<Edge>,
+
+    /// The name the dependency is installed as, which is only different from the
+    /// package's name for aliases.
+    name: String,
+    spec: String,
+}
+
+/// A packument, and whether it's known to be up to date.
+struct Fetched {
+    packument: Packument,
+    fresh: bool,
+}
+
+struct Resolver<'a, R> {
+    registry: &'a R,
+    root: &'a Path,
+
+    /// What each dependency was resolved to before, if anything.
+    seed: &'a Resolution,
+    packuments: HashMap<String, Fetched>,
+    resolution: Resolution,
+
+    /// Every workspace, by name.
+    workspaces: BTreeMap<String, PackageId>,
+
+    /// The names of each workspace's `devDependencies`, which don't make anything
+    /// they need a production dependency.
+    workspace_dev_dependencies: BTreeMap<PackageId, BTreeSet<String>>,
+
+    /// The kind of every dependency that isn't a regular one.
+    kinds: BTreeMap<Edge, Kind>,
+
+    /// Optional dependencies that couldn't be installed, which are left out.
+    failed: BTreeSet<Edge>,
+
+    /// Packages that couldn't be resolved because they weren't cached. These are
+    /// collected instead of failing straight away, so they can all be reported at once.
+    not_cached: BTreeSet<String>,
+}
+
+impl<'a, R: Registry> Resolver<'a, R> {
+    async fn run(
+        &mut self,
+        root: &Manifest,
+        dev_dependencies: &Dependencies,
+        workspaces: &[Workspace],
+    ) -> Result<(), Error> {
+        let mut queue = VecDeque::new();
+        for workspace in workspaces {
+            self.add_workspace(workspace, &mut queue)?;
+        }
+        queue_dependencies(None, root, false, None, &mut queue);
+        for (name, range) in sorted(dev_dependencies) {
+            // Like npm, a regular dependency wins over a dev dependency of the same name.
+            let depends_on = |dependencies: &Option<Dependencies>| {
+                dependencies
+                    .as_ref()
+                    .is_some_and(|dependencies| dependencies.contains_key(name))
+            };
+            if depends_on(&root.dependencies) || depends_on(&root.optional_dependencies) {
+                continue;
+            }
+            queue.push_back(Request {
+                parent: None,
+                dev: true,
+                kind: Kind::Regular,
+                optional: None,
+                name: name.clone(),
+                spec: range.clone(),
+            });
+        }
+
+        while let Some(request) = queue.pop_front() {
+            let id = match self.resolve_request(&request, &mut queue).await {
+                Ok(Some(id)) => id,
+                Ok(None) => continue,
+                Err(_) if request.optional.is_some() => {
+                    self.failed.extend(request.optional);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            let parent = match &request.parent {
+                Some(parent) => Parent::Package(parent),
+                None => Parent::Root { dev: request.dev },
+            };
+            if request.kind != Kind::Regular {
+                let edge = Edge {
+                    parent: request.parent.clone(),
+                    name: request.name.clone(),
+                };
+                self.kinds.insert(edge, request.kind);
+            }
+            self.link(parent, request.name, id);
+        }
+
+        if !self.not_cached.is_empty() {
+            return Err(Error::NotCached(
+                std::mem::take(&mut self.not_cached).into_iter().collect(),
+            ));
+        }
+        self.drop_failed();
+        self.mark_flags();
+        Ok(())
+    }
+
+    /// Resolves a single request, adding what it resolves to if it's new. Nothing is
+    /// returned if its packument wasn't cached while offline.
+    async fn resolve_request(
+        &mut self,
+        request: &Request,
+        queue: &mut VecDeque<Request>,
+    ) -> Result<Option<PackageId>, Error> {
+        validate_name(&request.name)?;
+        if let Some(package) = self.seeded(request) {
+            let (manifest, source) = (package.manifest.clone(), package.source.clone());
+            let id = self.add(
+                manifest,
+                source,
+                request.dev,
+                request.optional.as_ref(),
+                queue,
+            )?;
+            return Ok(Some(id));
+        }
+
+        let spec = DependencySpec::parse(&request.spec).map_err(|source| Error::InvalidSpec {
+            name: request.name.clone(),
+            spec: request.spec.clone(),
+            source,
+        })?;
+        let id = match spec {
+            DependencySpec::Git(git) => {
+                let package = self.registry.git_package(&git).await?;
+                let manifest = unpublished_manifest(&package.package_json);
+                let source = Source::Git {
+                    url: package.url,
+                    commit: package.commit,
+                };
+                self.add(
+                    manifest,
+                    source,
+                    request.dev,
+                    request.optional.as_ref(),
+                    queue,
+                )?
+            }
+            DependencySpec::Directory(path) => {
+                let path = self.local_path(request, &path)?;
+                let manifest = self.read_directory(&path)?;
+                self.add(
+                    manifest,
+                    Source::Directory { path },
+                    request.dev,
+                    request.optional.as_ref(),
+                    queue,
+                )?
+            }
+            DependencySpec::File(path) => {
+                let path = self.local_path(request, &path)?;
+                let manifest = self.read_tarball(&path)?;
+                self.add(
+                    manifest,
+                    Source::Tarball { path },
+                    request.dev,
+                    request.optional.as_ref(),
+                    queue,
+                )?
+            }
+            DependencySpec::Link(path) => {
+                let path = self.local_path(request, &path)?;
+                let mut manifest = self.read_directory(&path)?;
+                manifest.dependencies = None;
+                manifest.optional_dependencies = None;
+                manifest.peer_dependencies = None;
+                manifest.dev_dependencies = None;
+                self.add(
+                    manifest,
+                    Source::Link { path },
+                    request.dev,
+                    request.optional.as_ref(),
+                    queue,
+                )?
+            }
+            DependencySpec::Workspace(range) => self
+                .find_workspace(&request.name, &range)
+                .ok_or_else(|| Error::NoWorkspace {
+                    name: request.name.clone(),
+                    spec: request.spec.clone(),
+                })?,
+            spec => {
+                let (package, range) = registry_request(&request.name, &request.spec, spec)?;
+                // Like npm, a workspace that satisfies the range wins over the registry.
+                let found = self
+                    .find_workspace(&package, &range)
+                    .or_else(|| self.find_resolved(&package, &range));
+                match found {
+                    Some(id) => id,
+                    None => match self.pick_version(&package, &range).await {
+                        Ok(manifest) => self.add(
+                            manifest,
+                            Source::Registry,
+                            request.dev,
+                            request.optional.as_ref(),
+                            queue,
+                        )?,
+                        // Optional dependencies can just be left out instead.
+                        Err(Error::Registry(dinopkg_npm_registry::Error::NotCached(_)))
+                            if request.optional.is_none() =>
+                        {
+                            self.not_cached.insert(package);
+                            return Ok(None);
+                        }
+                        Err(err) => return Err(err),
+                    },
+                }
+            }
+        };
+        Ok(Some(id))
+    }
+
+    /// Finds what `request` was resolved to in the seed, if it can be used again.
+    fn seeded(&self, request: &Request) -> Option<&'a ResolvedPackage> {
+        let seed = self.seed;
+        let id = match &request.parent {
+            None => seed
+                .dependencies
+                .get(&request.name)
+                .or_else(|| seed.dev_dependencies.get(&request.name))?,
+            Some(parent) => {
+                let parent = seed.packages.get(parent)?;
+                let manifest = &parent.manifest;
+                let spec = [
+                    &manifest.dependencies,
+                    &manifest.optional_dependencies,
+                    &manifest.peer_dependencies,
+                    &manifest.dev_dependencies,
+                ]
+                .into_iter()
+                .flatten()
+                .find_map(|dependencies| dependencies.get(&request.name))?;
+                if *spec != request.spec {
+                    return None;
+                }
+                parent.dependencies.get(&request.name)?
+            }
+        };
+        let package = seed.packages.get(id)?;
+        // Local packages could have changed since, so they're always read again.
+        matches!(package.source, Source::Registry | Source::Git { .. }).then_some(package)
+    }
+
+    /// Adds a package to the resolution, and queues its dependencies, unless it's
+    /// already there.
+    ///
+    /// # Errors
+    /// This function fails if the package can't be installed on this platform.
+    fn add(
+        &mut self,
+        manifest: Manifest,
+        source: Source,
+        dev: bool,
+        optional: Option<&Edge>,
+        queue: &mut VecDeque<Request>,
+    ) -> Result<PackageId, Error> {
+        // Git and local packages name themselves, so their names need checking too.
+        validate_name(&manifest.name)?;
+        let id = PackageId {
+            name: manifest.name.clone(),
+            version: manifest.version.clone(),
+            source: source.resolved(),
+        };
+        if self.resolution.packages.contains_key(&id) {
+            return Ok(id);
+        }
+        if !is_supported(&manifest) {
+            let (os, cpu) = platform();
+            return Err(Error::UnsupportedPlatform {
+                package: id.to_string(),
+                os,
+                cpu,
+            });
+        }
+        queue_dependencies(Some(&id), &manifest, dev, optional, queue);
+        self.resolution.packages.insert(
+            id.clone(),
+            ResolvedPackage {
+                id: id.clone(),
+                source,
+                dist: manifest.dist.clone(),
+                manifest,
+                dependencies: BTreeMap::new(),
+                dev: true,
+                optional: false,
+                peer: false,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Adds a workspace as a dependency of the root, and queues its dependencies and
+    /// `devDependencies`.
+    fn add_workspace(
+        &mut self,
+        workspace: &Workspace,
+        queue: &mut VecDeque<Request>,
+    ) -> Result<(), Error> {
+        validate_name(&workspace.package_json.name)?;
+        let manifest = unpublished_manifest(&workspace.package_json);
+        let source = Source::Link {
+            path: workspace.path.clone(),
+        };
+        let id = self.add(manifest.clone(), source, false, None, queue)?;
+
+        let dependencies = manifest.dependencies.unwrap_or_default();
+        let dev_dependencies = manifest.dev_dependencies.unwrap_or_default();
+        for (name, spec) in sorted(&dev_dependencies) {
+            if dependencies.contains_key(name) {
+                continue;
+            }
+            queue.push_back(Request {
+                parent: Some(id.clone()),
+                dev: true,
+                kind: Kind::Regular,
+                optional: None,
+                name: name.clone(),
+                spec: spec.clone(),
+            });
+            self.workspace_dev_dependencies
+                .entry(id.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+
+        self.workspaces.insert(id.name.clone(), id.clone());
+        self.link(Parent::Root { dev: false }, id.name.clone(), id);
+        Ok(())
+    }
+
+    /// Finds the workspace called `name`, if its version satisfies `range`.
+    ///
+    /// Like pnpm, `workspace:*`, `workspace:^` and `workspace:~` match any version.
+    fn find_workspace(&self, name: &str, range: &str) -> Option<PackageId> {
+        let id = self.workspaces.get(name)?;
+        if matches!(range.trim(), "" | "*" | "^" | "~") {
+            return Some(id.clone());
+        }
+        let version = Version::parse(&id.version).ok()?;
+        parse_range(range)?.satisfies(&version).then(|| id.clone())
+    }
+
+    fn link(&mut self, parent: Parent, name: String, id: PackageId) {
+        let dependencies = match parent {
+            Parent::Root { dev: false } => &mut self.resolution.dependencies,
+            Parent::Root { dev: true } => &mut self.resolution.dev_dependencies,
+            Parent::Package(parent) => {
+                &mut self
+                    .resolution
+                    .packages
+                    .get_mut(parent)
+                    .expect("parent should have been resolved before its dependencies")
+                    .dependencies
+            }
+        };
+        dependencies.insert(name, id);
+    }
+
+    /// Works out where a local dependency is, relative to the root. Paths are relative
+    /// to the package that asked for them.
+    fn local_path(&self, request: &Request, path: &str) -> Result<String, Error> {
+        let base = match &request.parent {
+            None => "",
+            Some(parent) => match &self.resolution.packages[parent].source {
+                Source::Directory { path } | Source::Link { path } => path.as_str(),
+                _ => {
+                    return Err(Error::RemoteLocalDependency {
+                        parent: parent.to_string(),
+                        name: request.name.clone(),
+                        spec: request.spec.clone(),
+                    })
+                }
+            },
+        };
+        Ok(join_local(base, path))
+    }
+
+    /// Reads the manifest of the package in `path`, relative to the root.
+    fn read_directory(&self, path: &str) -> Result<Manifest, Error> {
+        let package_json = Path::new(path).join("package.json");
+        let display = package_json.to_string_lossy().replace('\\', "/");
+        let contents =
+            std::fs::read_to_string(self.root.join(&package_json)).map_err(|source| {
+                Error::Local {
+                    path: display.clone(),
+                    source,
+                }
+            })?;
+        let package_json =
+            PackageJson::parse(&contents).map_err(|source| Error::LocalPackageJson {
+                path: display,
+                source,
+            })?;
+        Ok(unpublished_manifest(&package_json))
+    }
+
+    /// Reads the manifest out of the tarball at `path`, relative to the root.
+    fn read_tarball(&self, path: &str) -> Result<Manifest, Error> {
+        let local = |source| Error::Local {
+            path: path.into(),
+            source,
+        };
+        let data = std::fs::read(self.root.join(path)).map_err(local)?;
+        let contents = read_tarball_file(&data, Path::new("package.json"))?.ok_or_else(|| {
+            local(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "it has no package.json",
+            ))
+        })?;
+        let package_json =
+            PackageJson::parse(&String::from_utf8_lossy(&contents)).map_err(|source| {
+                Error::LocalPackageJson {
+                    path: path.into(),
+                    source,
+                }
+            })?;
+        let mut manifest = unpublished_manifest(&package_json);
+        // The tarball could change, so it's pinned like a registry tarball would be.
+        manifest.dist.integrity = Some(Integrity::sha512(&data).to_string());
+        Ok(manifest)
+    }
+
+    /// Finds the highest already-resolved version that satisfies the request, if any.
+    ///
+    /// Reusing versions keeps the graph as small as possible, which is what npm does too.
+    fn find_resolved(&self, name: &str, range: &str) -> Option<PackageId> {
+        // If this fails, it's probably a dist-tag, which we can only resolve with the packument.
+        let range = parse_range(range)?;
+        let start = PackageId {
+            name: name.into(),
+            ..Default::default()
+        };
+        self.resolution
+            .packages
+            .range(start..)
+            .take_while(|(id, _)| id.name == name)
+            // A git dependency doesn't satisfy ranges, even if its version matches.
+            .filter(|(id, _)| id.source.is_none())
+            .filter_map(|(id, _)| Some((Version::parse(&id.version).ok()?, id)))
+            .filter(|(version, _)| range.satisfies(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, id)| id.clone())
+    }
+
+    /// Picks the best version of `name` for `range` (see [`best_version`]), fetching its
+    /// packument if needed.
+    async fn pick_version(&mut self, name: &str, range: &str) -> Result<Manifest, Error> {
+        if !self.packuments.contains_key(name) {
+            if let Some(packument) = self.registry.cached_packument(name).await? {
+                self.packuments.insert(
+                    name.into(),
+                    Fetched {
+                        packument,
+                        fresh: false,
+                    },
+                );
+            }
+        }
+        match self.packuments.get(name) {
+            Some(fetched) if fetched.fresh => return best_version(&fetched.packument, name, range),
+            Some(fetched) => {
+                // A stale packument is only good enough if it has what we need.
+                if let Ok(manifest) = best_version(&fetched.packument, name, range) {
+                    return Ok(manifest);
+                }
+            }
+            None => {}
+        }
+
+        let packument = self.registry.packument(name).await?;
+        let manifest = best_version(&packument, name, range);
+        self.packuments.insert(
+            name.into(),
+            Fetched {
+                packument,
+                fresh: true,
+            },
+        );
+        manifest
+    }
+
+    /// Leaves out the optional dependencies that couldn't be installed, along with
+    /// everything that only they needed.
+    fn drop_failed(&mut self) {
+        if self.failed.is_empty() {
+            return;
+        }
+        for edge in std::mem::take(&mut self.failed) {
+            let dependencies = match &edge.parent {
+                None => &mut self.resolution.dependencies,
+                Some(parent) => match self.resolution.packages.get_mut(parent) {
+                    Some(parent) => &mut parent.dependencies,
+                    None => continue,
+                },
+            };
+            dependencies.remove(&edge.name);
+        }
+        let reachable = self.reachable(true, |_| false);
+        self.resolution
+            .packages
+            .retain(|id, _| reachable.contains(id));
+    }
+
+    /// Works out which packages are only needed for development, as optional
+    /// dependencies or as peer dependencies, like npm's `dev`, `optional` and `peer` flags.
+    fn mark_flags(&mut self) {
+        let kind = |edge: &Edge| self.kinds.get(edge).copied().unwrap_or(Kind::Regular);
+        let production = self.reachable(false, |edge| {
+            edge.parent
+                .as_ref()
+                .and_then(|parent| self.workspace_dev_dependencies.get(parent))
+                .is_some_and(|dev_only| dev_only.contains(&edge.name))
+        });
+        let required = self.reachable(true, |edge| kind(edge) == Kind::Optional);
+        let non_peer = self.reachable(true, |edge| kind(edge) == Kind::Peer);
+        for (id, package) in &mut self.resolution.packages {
+            package.dev = !production.contains(id);
+            package.optional = !required.contains(id);
+            package.peer = !non_peer.contains(id);
+        }
+    }
+
+    /// Every package that can be reached from the root's dependencies (and its
+    /// `devDependencies`, if `dev` is set), without following the dependencies `skip`
+    /// is true for.
+    fn reachable(&self, dev: bool, skip: impl Fn(&Edge) -> bool) -> BTreeSet<PackageId> {
+        let root = self
+            .resolution
+            .dependencies
+            .iter()
+            .chain(
+                dev.then_some(&self.resolution.dev_dependencies)
+                    .into_iter()
+                    .flatten(),
+            )
+            .map(|(name, id)| (None, name, id));
+        let mut queue: VecDeque<(Option<&PackageId>, &String, &PackageId)> = root.collect();
+        let mut reached = BTreeSet::new();
+        while let Some((parent, name, id)) = queue.pop_front() {
+            let edge = Edge {
+                parent: parent.cloned(),
+                name: name.clone(),
+            };
+            if skip(&edge) || !reached.insert(id.clone()) {
+                continue;
+            }
+            if let Some(package) = self.resolution.packages.get(id) {
+                queue.extend(
+                    package
+                        .dependencies
+                        .iter()
+                        .map(|(name, dependency)| (Some(id), name, dependency)),
+                );
+            }
+        }
+        reached
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), Error> {
+    validate_package_name::validate_existing(name).map_err(|source| Error::InvalidName {
+        name: name.into(),
+        source,
+    })
+}
+
+/// Queues the dependencies in `manifest`, for the package `parent` (or the root).
+///
+/// Like npm, optional dependencies win over regular ones of the same name, which win
+/// over peer dependencies. Peer dependencies are only installed if they aren't marked
+/// as optional. `optional` is the optional dependency that `parent` is needed for, if any.
+fn queue_dependencies(
+    parent: Option<&PackageId>,
+    manifest: &Manifest,
+    dev: bool,
+    optional: Option<&Edge>,
+    queue: &mut VecDeque<Request>,
+) {
+    let empty = Dependencies::new();
+    let dependencies = manifest.dependencies.as_ref().unwrap_or(&empty);
+    let optional_dependencies = manifest.optional_dependencies.as_ref().unwrap_or(&empty);
+    let peer_dependencies = manifest.peer_dependencies.as_ref().unwrap_or(&empty);
+    let optional_peer = |name: &str| {
+        manifest
+            .peer_dependencies_meta
+            .as_ref()
+            .and_then(|meta| meta.get(name))
+            .is_some_and(|meta| meta.optional)
+    };
+
+    let regular = sorted(dependencies)
+        .into_iter()
+        .filter(|(name, _)| !optional_dependencies.contains_key(*name))
+        .map(|(name, spec)| (name, spec, Kind::Regular));
+    let optionals = sorted(optional_dependencies)
+        .into_iter()
+        .map(|(name, spec)| (name, spec, Kind::Optional));
+    let peers = sorted(peer_dependencies)
+        .into_iter()
+        .filter(|(name, _)| {
+            !dependencies.contains_key(*name)
+                && !optional_dependencies.contains_key(*name)
+                && !optional_peer(name)
+        })
+        .map(|(name, spec)| (name, spec, Kind::Peer));
+    for (name, spec, kind) in regular.chain(optionals).chain(peers) {
+        let optional = match kind {
+            Kind::Optional => Some(Edge {
+                parent: parent.cloned(),
+                name: name.clone(),
+            }),
+            _ => optional.cloned(),
+        };
+        queue.push_back(Request {
+            parent: parent.cloned(),
+            dev,
+            kind,
+            optional,
+            name: name.clone(),
+            spec: spec.clone(),
+        });
+    }
+}
+
+/// Picks the best version of `name` for `range`, following npm's rules:
+///
+/// - a range that names a dist-tag resolves to that tag
+/// - if the `latest` tag satisfies the range, it's used
+/// - otherwise, the highest satisfying version wins
+fn best_version(packument: &Packument, name: &str, range: &str) -> Result<Manifest, Error> {
+    let no_match = || {
+        Error::Registry(dinopkg_npm_registry::Error::VersionNotFound {
+            name: name.into(),
+            spec: range.into(),
+            latest: packument.dist_tags.get("latest").cloned(),
+        })
+    };
+
+    let version = if let Some(tagged) = packument.dist_tags.get(range.trim()) {
+        tagged.clone()
+    } else {
+        let parsed_range = parse_range(range).ok_or_else(|| Error::InvalidRange {
+            name: name.into(),
+            range: range.into(),
+        })?;
+        let latest = packument
+            .dist_tags
+            .get("latest")
+            .filter(|latest| packument.versions.contains_key(*latest))
+            .filter(|latest| {
+                Version::parse(latest).is_ok_and(|latest| parsed_range.satisfies(&latest))
+            });
+        match latest {
+            Some(latest) => latest.clone(),
+            None => parsed_range
+                .max_satisfying(packument.versions.keys().map(String::as_str))
+                .ok_or_else(no_match)?
+                .to_owned(),
+        }
+    };
+
+    let mut manifest = packument
+        .versions
+        .get(&version)
+        .cloned()
+        .ok_or_else(no_match)?;
+    // The package is whatever it was fetched as, not whatever its manifest says it is,
+    // so it can't pass itself off as another package.
+    manifest.name = name.into();
+    Ok(manifest)
+}
+
+/// Returns the registry package and the range or dist-tag that `spec` (`parsed`) asks for.
+fn registry_request(
+    name: &str,
+    spec: &str,
+    parsed: DependencySpec,
+) -> Result<(String, String), Error> {
+    match parsed {
+        DependencySpec::Range(range) | DependencySpec::Tag(range) => Ok((name.into(), range)),
+        DependencySpec::Alias { name, spec } => Ok((name, spec.to_string())),
+        _ => Err(Error::UnsupportedSpec {
+            name: name.into(),
+            spec: spec.into(),
+        }),
+    }
+}
+
+/// Builds a manifest for a package that isn't from the registry.
+fn unpublished_manifest(package_json: &PackageJson) -> Manifest {
+    let mut manifest = Manifest::from_package_json(package_json);
+    // Like npm, an unversioned package is only ever 0.0.0.
+    if manifest.version.is_empty() {
+        manifest.version = "0.0.0".into();
+    }
+    manifest
+}
+
+/// Joins a local dependency's `path` onto the path of the package that depends on it,
+/// without touching the filesystem, so the result can go in a lockfile.
+fn join_local(base: &str, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if Path::new(&path).is_absolute() {
+        return path;
+    }
+    let joined = Path::new(base).join(&path);
+    let mut parts: Vec<&str> = Vec::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir if parts.last().is_some_and(|part| *part != "..") => {
+                parts.pop();
+            }
+            Component::ParentDir => parts.push(".."),
+            Component::Normal(part) => parts.push(part.to_str().unwrap_or_default()),
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        ".".into()
+    } else {
+        parts.join("/")
+    }
+}
+
+fn parse_range(range: &str) -> Option<Range> {
+    Range::parse(range).ok()
+}
+
+fn sorted(dependencies: &Dependencies) -> Vec<(&String, &String)> {
+    let mut dependencies: Vec<_> = dependencies.iter().collect();
+    dependencies.sort();
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packument;
+    use dinopkg_npm_registry::Error as RegistryError;
+    use dinopkg_package_json::{PeerDependencyMeta, Workspaces};
+    use maplit::{btreemap, hashmap};
+    use pretty_assertions::assert_eq;
+
+    fn id(name: &str, version: &str) -> PackageId {
+        PackageId {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The ID of a package that doesn't come from the registry.
+    fn id_from(name: &str, version: &str, source: &Source) -> PackageId {
+        PackageId {
+            source: source.resolved(),
+            ..id(name, version)
+        }
+    }
+
+    fn root(dependencies: &[(&str, &str)], dev_dependencies: &[(&str, &str)]) -> PackageJson {
+        let to_map = |deps: &[(&str, &str)]| -> Dependencies {
+            deps.iter()
+                .map(|(name, range)| ((*name).into(), (*range).into()))
+                .collect()
+        };
+        PackageJson {
+            name: "root".into(),
+            version: "1.0.0".into(),
+            dependencies: Some(to_map(dependencies)),
+            dev_dependencies: Some(to_map(dev_dependencies)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_transitive_dependencies() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument(
+            "express",
+            &[("4.17.0", &[]), ("4.19.2", &[("debug", "~2.6.0")])],
+        ));
+        registry.insert(packument(
+            "debug",
+            &[("2.6.8", &[]), ("2.6.9", &[]), ("3.0.0", &[])],
+        ));
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("express", "^4.17.1")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution.dependencies,
+            btreemap! { "express".into() => id("express", "4.19.2") }
+        );
+        assert_eq!(
+            resolution.packages[&id("express", "4.19.2")].dependencies,
+            btreemap! { "debug".into() => id("debug", "2.6.9") }
+        );
+        assert_eq!(resolution.packages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_resolves_what_changed_since_the_seed() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("b", "^1.0.0")])]));
+        registry.insert(packument("b", &[("1.0.0", &[])]));
+        registry.insert(packument("c", &[("1.0.0", &[])]));
+        let package_json = root(&[("a", "^1.0.0"), ("c", "^1.0.0")], &[]);
+        let mut seed = resolve(&registry, &package_json, Path::new("."))
+            .await
+            .unwrap();
+
+        // Newer versions are only picked up for dependencies that have changed.
+        registry.insert(packument(
+            "a",
+            &[("1.0.0", &[("b", "^1.0.0")]), ("1.1.0", &[])],
+        ));
+        registry.insert(packument("b", &[("1.0.0", &[]), ("1.1.0", &[])]));
+        registry.insert(packument("c", &[("1.0.0", &[]), ("1.1.0", &[])]));
+        registry.insert(packument("d", &[("1.0.0", &[])]));
+        seed.dependencies.remove("c");
+        let package_json = root(&[("a", "^1.0.0"), ("c", "^1.1.0"), ("d", "*")], &[]);
+        let resolution = resolve_from(&registry, &package_json, Path::new("."), &seed)
+            .await
+            .unwrap();
+        assert_eq!(
+            resolution.dependencies,
+            btreemap! {
+                "a".into() => id("a", "1.0.0"),
+                "c".into() => id("c", "1.1.0"),
+                "d".into() => id("d", "1.0.0"),
+            }
+        );
+        assert_eq!(
+            resolution.packages[&id("a", "1.0.0")].dependencies,
+            btreemap! { "b".into() => id("b", "1.0.0") }
+        );
+        assert_eq!(resolution.packages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn rejects_names_that_lead_out_of_node_modules() {
+        for name in ["../../../pwned", "@scope/..", "a/b/c", "/abs", "a\\b"] {
+            let mut registry = MemoryRegistry::new();
+            let spec = "npm:payload@1.0.0";
+            registry.insert(packument("evil", &[("1.0.0", &[(name, spec)])]));
+            registry.insert(packument("payload", &[("1.0.0", &[])]));
+
+            let result =
+                resolve(&registry, &root(&[("evil", "^1.0.0")], &[]), Path::new(".")).await;
+            assert!(
+                matches!(&result, Err(Error::InvalidName { name: invalid, .. }) if invalid == name),
+                "{name}: {result:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn names_registry_packages_after_their_packument() {
+        let mut registry = MemoryRegistry::new();
+        let mut evil = packument("evil", &[("1.0.0", &[])]);
+        evil.versions.get_mut("1.0.0").unwrap().name = "esbuild".into();
+        registry.insert(evil);
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("esbuild", "npm:evil@1.0.0")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.dependencies["esbuild"], id("evil", "1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn prefers_latest_tag() {
+        let mut registry = MemoryRegistry::new();
+        let mut info = packument("a", &[("1.0.0", &[]), ("1.1.0", &[])]);
+        info.dist_tags.insert("latest".into(), "1.0.0".into());
+        info.dist_tags.insert("next".into(), "1.1.0".into());
+        registry.insert(info);
+        registry.insert(packument("b", &[("1.0.0", &[("a", "next")])]));
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("a", "^1.0.0"), ("b", "*")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.dependencies["a"], id("a", "1.0.0"));
+        assert_eq!(
+            resolution.packages[&id("b", "1.0.0")].dependencies["a"],
+            id("a", "1.1.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn dedupes_compatible_and_splits_conflicting_versions() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("c", "^1.0.0")])]));
+        registry.insert(packument("b", &[("1.0.0", &[("c", "^1.2.0")])]));
+        registry.insert(packument("d", &[("1.0.0", &[("c", "^2.0.0")])]));
+        registry.insert(packument(
+            "c",
+            &[("1.0.0", &[]), ("1.3.0", &[]), ("2.0.0", &[])],
+        ));
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("a", "1"), ("b", "1"), ("d", "1")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        let c_versions: Vec<_> = resolution
+            .packages
+            .keys()
+            .filter(|id| id.name == "c")
+            .map(|id| id.version.as_str())
+            .collect();
+        assert_eq!(c_versions, ["1.3.0", "2.0.0"]);
+    }
+
+    #[tokio::test]
+    async fn marks_dev_only_packages() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("shared", &[("1.0.0", &[])]));
+        registry.insert(packument("app", &[("1.0.0", &[("shared", "1")])]));
+        registry.insert(packument("jest", &[("29.0.0", &[("shared", "1")])]));
+        registry.insert(packument("only-dev", &[("1.0.0", &[])]));
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("app", "1")], &[("jest", "29"), ("only-dev", "1")]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert!(!resolution.packages[&id("app", "1.0.0")].dev);
+        assert!(!resolution.packages[&id("shared", "1.0.0")].dev);
+        assert!(resolution.packages[&id("jest", "29.0.0")].dev);
+        assert!(resolution.packages[&id("only-dev", "1.0.0")].dev);
+    }
+
+    #[tokio::test]
+    async fn resolves_optional_and_peer_dependencies() {
+        let (os, _) = platform();
+        let with = |mut packument: Packument, edit: &dyn Fn(&mut Manifest)| {
+            packument.versions.values_mut().for_each(edit);
+            packument
+        };
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("host", &[("2.0.0", &[]), ("2.1.0", &[])]));
+        registry.insert(packument("react", &[("18.0.0", &[])]));
+        registry.insert(packument("types", &[("1.0.0", &[])]));
+        registry.insert(packument("root-peer", &[("1.0.0", &[])]));
+        registry.insert(with(packument("native", &[("1.0.0", &[])]), &|manifest| {
+            manifest.os = Some(vec![os.into()]);
+        }));
+        registry.insert(with(
+            packument("other-os", &[("1.0.0", &[])]),
+            &|manifest| {
+                manifest.os = Some(vec![format!("!{os}")]);
+            },
+        ));
+        // This can't be installed, because something it needs doesn't exist.
+        registry.insert(packument("broken", &[("1.0.0", &[("gone", "1")])]));
+        registry.insert(with(packument("plugin", &[("1.0.0", &[])]), &|manifest| {
+            manifest.peer_dependencies = Some(btreemap! {
+                "host".into() => "^2".into(),
+                "react".into() => "^18".into(),
+                "types".into() => "1".into(),
+            });
+            manifest.peer_dependencies_meta = Some(hashmap! {
+                "types".into() => PeerDependencyMeta { optional: true },
+            });
+            manifest.optional_dependencies = Some(btreemap! {
+                "broken".into() => "1".into(),
+                "missing".into() => "1".into(),
+                "native".into() => "1".into(),
+                "other-os".into() => "1".into(),
+            });
+        }));
+
+        let mut package_json = root(&[("host", "2.0.0"), ("plugin", "1")], &[]);
+        package_json.peer_dependencies = Some(btreemap! { "root-peer".into() => "1".into() });
+        let resolution = resolve(&registry, &package_json, Path::new("."))
+            .await
+            .unwrap();
+        assert_eq!(
+            resolution.dependencies,
+            btreemap! {
+                "host".into() => id("host", "2.0.0"),
+                "plugin".into() => id("plugin", "1.0.0"),
+                "root-peer".into() => id("root-peer", "1.0.0"),
+            }
+        );
+        assert_eq!(
+            resolution.packages[&id("plugin", "1.0.0")].dependencies,
+            btreemap! {
+                "host".into() => id("host", "2.0.0"),
+                "native".into() => id("native", "1.0.0"),
+                "react".into() => id("react", "18.0.0"),
+            }
+        );
+        assert_eq!(resolution.packages.len(), 5);
+
+        let flags = |name: &str, version: &str| {
+            let package = &resolution.packages[&id(name, version)];
+            (package.optional, package.peer)
+        };
+        assert_eq!(flags("host", "2.0.0"), (false, false));
+        assert_eq!(flags("native", "1.0.0"), (true, false));
+        assert_eq!(flags("react", "18.0.0"), (false, true));
+        assert_eq!(flags("root-peer", "1.0.0"), (false, true));
+
+        // Only optional dependencies are left out if they can't be installed.
+        let err = resolve(&registry, &root(&[("other-os", "1")], &[]), Path::new("."))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::UnsupportedPlatform { package, .. } if package == "other-os@1.0.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_aliases() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("lodash", &[("3.10.1", &[]), ("4.17.21", &[])]));
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("lodash", "^4"), ("lodash3", "npm:lodash@^3")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.dependencies["lodash"], id("lodash", "4.17.21"));
+        assert_eq!(resolution.dependencies["lodash3"], id("lodash", "3.10.1"));
+
+        let err = resolve(
+            &registry,
+            &root(&[("lib", "https://example.com/lib.tgz")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::UnsupportedSpec { name, .. } if name == "lib"));
+    }
+
+    /// Makes a bare repository with one commit, tagged `v<version>`, and returns its URL.
+    fn git_repo(dir: &std::path::Path, package_json: &str, version: &str) -> String {
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["-c", "user.name=dino", "-c", "user.email=dino@example.com"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?} failed");
+        };
+        std::fs::create_dir_all(dir.join("work")).unwrap();
+        std::fs::write(dir.join("work/package.json"), package_json).unwrap();
+        git(&["init", "--quiet", "work"]);
+        git(&["-C", "work", "add", "."]);
+        git(&["-C", "work", "commit", "--quiet", "-m", version]);
+        git(&["-C", "work", "tag", &format!("v{version}")]);
+        git(&["clone", "--quiet", "--bare", "work", "lib.git"]);
+        format!("file://{}", dir.join("lib.git").display())
+    }
+
+    #[tokio::test]
+    async fn resolves_git_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = git_repo(
+            dir.path(),
+            r#"{"name":"lib","version":"2.1.0","dependencies":{"debug":"^2"}}"#,
+            "2.1.0",
+        );
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("debug", &[("2.6.9", &[])]));
+        let repositories = dinopkg_git::Repositories::new(dir.path().join("cache"));
+
+        let spec = format!("git+{url}#semver:^2");
+        let resolution = resolve(
+            &WithGit::new(&registry, &repositories),
+            &root(&[("lib", &spec)], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        let lib = &resolution.packages[&resolution.dependencies["lib"]];
+        assert_eq!(lib.id, id_from("lib", "2.1.0", &lib.source));
+        let Source::Git {
+            url: locked,
+            commit,
+        } = &lib.source
+        else {
+            panic!("lib should come from git, not {:?}", lib.source);
+        };
+        assert_eq!(*locked, url);
+        assert_eq!(lib.dependencies["debug"], id("debug", "2.6.9"));
+        assert_eq!(
+            Source::from_resolved(&lib.source.resolved().unwrap()),
+            lib.source
+        );
+        assert_eq!(commit.len(), 40);
+
+        // A plain registry can't clone anything.
+        let err = resolve(&registry, &root(&[("lib", &spec)], &[]), Path::new("."))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoGitCache(_)));
+    }
+
+    #[tokio::test]
+    async fn keeps_packages_from_different_sources_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut specs = Vec::new();
+        for description in ["one", "two"] {
+            let repo = dir.path().join(description);
+            let package_json =
+                format!(r#"{{"name":"lib","version":"1.0.0","description":"{description}"}}"#);
+            specs.push(format!("git+{}", git_repo(&repo, &package_json, "1.0.0")));
+        }
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("lib", &[("1.0.0", &[])]));
+        let repositories = dinopkg_git::Repositories::new(dir.path().join("cache"));
+
+        let resolution = resolve(
+            &WithGit::new(&registry, &repositories),
+            &root(
+                &[("lib", "1.0.0"), ("one", &specs[0]), ("two", &specs[1])],
+                &[],
+            ),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.packages.len(), 3);
+        assert_eq!(resolution.dependencies["lib"], id("lib", "1.0.0"));
+        let one = &resolution.packages[&resolution.dependencies["one"]];
+        let two = &resolution.packages[&resolution.dependencies["two"]];
+        assert_ne!(one.source, two.source);
+        assert_eq!(one.id, id_from("lib", "1.0.0", &one.source));
+        assert_eq!(two.id, id_from("lib", "1.0.0", &two.source));
+    }
+
+    #[tokio::test]
+    async fn resolves_local_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "libs/a/package.json",
+            r#"{"name":"a","version":"1.0.0","bin":{"a":"cli.js"},"dependencies":{"b":"file:../b","debug":"^2"}}"#,
+        );
+        write("libs/b/package.json", r#"{"name":"b","version":"2.0.0"}"#);
+        write("app/package.json", "{}");
+        // Linked packages install their own dependencies, so this is never looked up.
+        write(
+            "linked/package.json",
+            r#"{"name":"linked","version":"0.1.0","dependencies":{"missing":"^1"}}"#,
+        );
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("debug", &[("2.6.9", &[])]));
+
+        let resolution = resolve(
+            &registry,
+            &root(
+                &[("a", "file:../libs/a"), ("linked", "link:../linked")],
+                &[],
+            ),
+            &dir.path().join("app"),
+        )
+        .await
+        .unwrap();
+        let a = &resolution.packages[&resolution.dependencies["a"]];
+        assert_eq!(
+            a.source,
+            Source::Directory {
+                path: "../libs/a".into()
+            }
+        );
+        let b = Source::Directory {
+            path: "../libs/b".into(),
+        };
+        assert!(a.manifest.bin.is_some());
+        assert_eq!(
+            a.dependencies,
+            btreemap! {
+                "b".into() => id_from("b", "2.0.0", &b),
+                "debug".into() => id("debug", "2.6.9"),
+            }
+        );
+        assert_eq!(resolution.packages[&id_from("b", "2.0.0", &b)].source, b);
+        let linked = &resolution.packages[&resolution.dependencies["linked"]];
+        assert_eq!(
+            linked.source,
+            Source::Link {
+                path: "../linked".into()
+            }
+        );
+        assert!(linked.dependencies.is_empty());
+        assert_eq!(
+            Source::from_resolved(&a.source.resolved().unwrap()),
+            a.source
+        );
+
+        // A registry package can't point at something on disk.
+        registry.insert(packument("remote", &[("1.0.0", &[("b", "file:../b")])]));
+        let err = resolve(
+            &registry,
+            &root(&[("remote", "1")], &[]),
+            &dir.path().join("app"),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(err, Error::RemoteLocalDependency { parent, .. } if parent == "remote@1.0.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "packages/a/package.json",
+            r#"{"name":"a","version":"1.0.0","dependencies":{"b":"^2.0.0"},"devDependencies":{"jest":"29"}}"#,
+        );
+        write(
+            "packages/b/package.json",
+            r#"{"name":"b","version":"2.1.0","dependencies":{"debug":"^2"}}"#,
+        );
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("debug", &[("2.6.9", &[])]));
+        registry.insert(packument("jest", &[("29.0.0", &[("debug", "2")])]));
+        // The workspace wins, even though the registry has a matching version.
+        registry.insert(packument("b", &[("2.2.0", &[])]));
+
+        let mut package_json = root(&[("a", "workspace:^1.0.0")], &[]);
+        package_json.workspaces = Some(Workspaces::Globs(vec!["packages/*".into()]));
+        let resolution = resolve(&registry, &package_json, dir.path()).await.unwrap();
+        let workspace = |name: &str, version: &str| {
+            let path = format!("packages/{name}");
+            id_from(name, version, &Source::Link { path })
+        };
+        assert_eq!(
+            resolution.dependencies,
+            btreemap! {
+                "a".into() => workspace("a", "1.0.0"),
+                "b".into() => workspace("b", "2.1.0"),
+            }
+        );
+        let a = &resolution.packages[&workspace("a", "1.0.0")];
+        assert_eq!(
+            a.source,
+            Source::Link {
+                path: "packages/a".into()
+            }
+        );
+        assert_eq!(a.dependencies["b"], workspace("b", "2.1.0"));
+        assert_eq!(a.dependencies["jest"], id("jest", "29.0.0"));
+        assert!(resolution.packages[&id("jest", "29.0.0")].dev);
+        assert!(!resolution.packages[&id("debug", "2.6.9")].dev);
+
+        let mut package_json = root(&[("a", "workspace:^2.0.0")], &[]);
+        package_json.workspaces = Some(Workspaces::Globs(vec!["packages/*".into()]));
+        let err = resolve(&registry, &package_json, dir.path())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoWorkspace { name, .. } if name == "a"));
+    }
+
+    #[test]
+    fn joins_local_paths() {
+        assert_eq!(join_local("", "./lib"), "lib");
+        assert_eq!(join_local("", "../lib/"), "../lib");
+        assert_eq!(join_local("../libs/a", "../b"), "../libs/b");
+        assert_eq!(join_local("libs/a", "../../.."), "..");
+        assert_eq!(join_local("libs/a", "/abs/lib"), "/abs/lib");
+    }
+
+    #[tokio::test]
+    async fn handles_cycles() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("b", "1")])]));
+        registry.insert(packument("b", &[("1.0.0", &[("a", "1")])]));
+
+        let resolution = resolve(&registry, &root(&[("a", "1")], &[]), Path::new("."))
+            .await
+            .unwrap();
+        assert_eq!(resolution.packages.len(), 2);
+        assert_eq!(
+            resolution.packages[&id("b", "1.0.0")].dependencies["a"],
+            id("a", "1.0.0")
+        );
+    }
+
+    #[tokio::test]
+    async fn is_deterministic() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[("c", "^1.0.0")])]));
+        registry.insert(packument("b", &[("1.0.0", &[("c", "1.0.0")])]));
+        registry.insert(packument("c", &[("1.0.0", &[]), ("1.1.0", &[])]));
+
+        let package_json = root(&[("a", "1"), ("b", "1")], &[]);
+        let first = resolve(&registry, &package_json, Path::new("."))
+            .await
+            .unwrap();
+        for _ in 0..10 {
+            assert_eq!(
+                resolve(&registry, &package_json, Path::new("."))
+                    .await
+                    .unwrap(),
+                first
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_unsatisfiable_ranges() {
+        let mut registry = MemoryRegistry::new();
+        registry.insert(packument("a", &[("1.0.0", &[])]));
+
+        let err = resolve(&registry, &root(&[("a", "^2.0.0")], &[]), Path::new("."))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Registry(RegistryError::VersionNotFound { latest: Some(latest), .. })
+                if latest == "1.0.0"
+        ));
+
+        let err = resolve(&registry, &root(&[("missing", "1")], &[]), Path::new("."))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Registry(RegistryError::PackageNotFound { name, .. }) if name == "missing"
+        ));
+    }
+
+    /// Serves possibly-stale packuments from `stale`, and fresh ones from `fresh`.
+    /// Anything missing from both isn't cached.
+    struct CachedRegistry {
+        stale: MemoryRegistry,
+        fresh: MemoryRegistry,
+        fetched: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Registry for CachedRegistry {
+        async fn packument(&self, name: &str) -> Result<Packument, Error> {
+            self.fetched.lock().unwrap().push(name.into());
+            self.fresh.packument(name).await.map_err(|_| {
+                dinopkg_npm_registry::Error::NotCached(format!("https://example.com/{name}")).into()
+            })
+        }
+
+        async fn cached_packument(&self, name: &str) -> Result<Option<Packument>, Error> {
+            Ok(self.stale.packument(name).await.ok())
+        }
+    }
+
+    #[tokio::test]
+    async fn uses_stale_packuments_that_satisfy_the_range() {
+        let mut stale = MemoryRegistry::new();
+        stale.insert(packument("a", &[("1.0.0", &[])]));
+        stale.insert(packument("b", &[("1.0.0", &[])]));
+        let mut fresh = MemoryRegistry::new();
+        fresh.insert(packument("a", &[("1.0.0", &[]), ("1.1.0", &[])]));
+        fresh.insert(packument("b", &[("1.0.0", &[]), ("2.0.0", &[])]));
+        let registry = CachedRegistry {
+            stale,
+            fresh,
+            fetched: Default::default(),
+        };
+
+        let resolution = resolve(
+            &registry,
+            &root(&[("a", "^1.0.0"), ("b", "^2.0.0")], &[]),
+            Path::new("."),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution.dependencies,
+            btreemap! {
+                "a".into() => id("a", "1.0.0"),
+                "b".into() => id("b", "2.0.0"),
+            }
+        );
+        assert_eq!(*registry.fetched.lock().unwrap(), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn lists_every_package_that_isnt_cached() {
+        let mut fresh = MemoryRegistry::new();
+        fresh.insert(packument("a", &[("1.0.0", &[("c", "1"), ("d", "1")])]));
+        let registry = CachedRegistry {
+            stale: MemoryRegistry::new(),
+            fresh,
+            fetched: Default::default(),
+        };
+
+        let err = resolve(
+            &registry,
+            &root(&[("a", "1"), ("b", "1")], &[("c", "1")]),
+            Path::new("."),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(&err, Error::NotCached(names) if names == &["b", "c", "d"]));
+        assert_eq!(err.to_string(), "not available offline: b, c, d");
+    }
+}
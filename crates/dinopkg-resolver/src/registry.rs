@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use dinopkg_git::Repositories;
+use dinopkg_npm_registry::{CacheStore, Packument, RegistryClient};
+use dinopkg_package_json::{GitSpec, PackageJson};
+
+use crate::Error;
+
+/// A source of package metadata that the resolver can query.
+pub trait Registry {
+    /// Fetches the packument (all versions and dist-tags) for `name`.
+    fn packument(&self, name: &str) -> impl Future<Output = Result<Packument, Error>>;
+
+    /// Returns a packument for `name` that might be out of date, without using the network.
+    ///
+    /// The resolver only uses it if it has a version that satisfies what's being
+    /// requested, and calls [`Self::packument`] otherwise.
+    fn cached_packument(
+        &self,
+        _name: &str,
+    ) -> impl Future<Output = Result<Option<Packument>, Error>> {
+        async { Ok(None) }
+    }
+
+    /// Finds the commit a git dependency points at, and reads its `package.json` there.
+    ///
+    /// Registries can't do this on their own, so wrap them in [`WithGit`] to support git
+    /// dependencies.
+    fn git_package(&self, spec: &GitSpec) -> impl Future<Output = Result<GitPackage, Error>> {
+        let spec = spec.to_string();
+        async { Err(Error::NoGitCache(spec)) }
+    }
+}
+
+/// A git dependency, resolved to a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitPackage {
+    /// The URL the repository was cloned from.
+    pub url: String,
+
+    /// The full hash of the commit.
+    pub commit: String,
+
+    pub package_json: PackageJson,
+}
+
+/// A [`Registry`] that also resolves git dependencies, by cloning them into `repositories`.
+#[derive(Debug)]
+pub struct WithGit<'a, R> {
+    registry: &'a R,
+    repositories: &'a Repositories,
+}
+
+impl<'a, R: Registry> WithGit<'a, R> {
+    pub fn new(registry: &'a R, repositories: &'a Repositories) -> Self {
+        Self {
+            registry,
+            repositories,
+        }
+    }
+}
+
+impl<R: Registry> Registry for WithGit<'_, R> {
+    async fn packument(&self, name: &str) -> Result<Packument, Error> {
+        self.registry.packument(name).await
+    }
+
+    async fn cached_packument(&self, name: &str) -> Result<Option<Packument>, Error> {
+        self.registry.cached_packument(name).await
+    }
+
+    async fn git_package(&self, spec: &GitSpec) -> Result<GitPackage, Error> {
+        let commit = self.repositories.resolve(spec).await?;
+        let package_json = self.repositories.package_json(&spec.url, &commit).await?;
+        Ok(GitPackage {
+            url: spec.url.clone(),
+            commit,
+            package_json,
+        })
+    }
+}
+
+impl Registry for reqwest::Client {
+    async fn packument(&self, name: &str) -> Result<Packument, Error> {
+        Ok(Packument::from_name(name, self).await?)
+    }
+}
+
+impl<S: CacheStore> Registry for RegistryClient<S> {
+    async fn packument(&self, name: &str) -> Result<Packument, Error> {
+        Ok(RegistryClient::packument(self, name).await?)
+    }
+
+    async fn cached_packument(&self, name: &str) -> Result<Option<Packument>, Error> {
+        Ok(RegistryClient::cached_packument(self, name).await?)
+    }
+}
+
+/// A registry that lives entirely in memory.
+///
+/// This is mostly useful for tests, where hitting the network is undesirable.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    packages: HashMap<String, Packument>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a packument to the registry, replacing any existing one with the same name.
+    pub fn insert(&mut self, packument: Packument) {
+        self.packages.insert(packument.name.clone(), packument);
+    }
+}
+
+impl Registry for MemoryRegistry {
+    async fn packument(&self, name: &str) -> Result<Packument, Error> {
+        self.packages.get(name).cloned().ok_or_else(|| {
+            dinopkg_npm_registry::Error::PackageNotFound {
+                name: name.into(),
+                registry: "the memory registry".into(),
+            }
+            .into()
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures for tests, shared with the crates that build on the resolver through the
+//! `testing` feature.
+
+use dinopkg_npm_registry::{Dist, Manifest, Packument};
+
+/// A packument for `name` with each of `versions`, which depend on the given ranges.
+///
+/// The last version is tagged `latest`, and tarballs point at the npm registry (though
+/// there's nothing there).
+pub fn packument(name: &str, versions: &[(&str, &[(&str, &str)])]) -> Packument {
+    let latest = versions.last().map(|(version, _)| (*version).into());
+    Packument {
+        name: name.into(),
+        modified: None,
+        versions: versions
+            .iter()
+            .map(|(version, dependencies)| {
+                let manifest = Manifest {
+                    name: name.into(),
+                    version: (*version).into(),
+                    dependencies: Some(
+                        dependencies
+                            .iter()
+                            .map(|(name, range)| ((*name).into(), (*range).into()))
+                            .collect(),
+                    ),
+                    dist: Dist {
+                        tarball: format!(
+                            "https://registry.npmjs.org/{name}/-/{name}-{version}.tgz"
+                        ),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                ((*version).into(), manifest)
+            })
+            .collect(),
+        dist_tags: latest
+            .map(|latest| ("latest".into(), latest))
+            .into_iter()
+            .collect(),
+    }
+}
@@ -0,0 +1,52 @@
+//! npm-flavoured semver, ported from [node-semver](https://github.com/npm/node-semver).
+//!
+//! ```rust
+//! use dinopkg_semver::{Range, Version};
+//!
+//! let range = Range::parse("^4.17.1").unwrap();
+//! assert!(range.satisfies(&Version::parse("4.19.2").unwrap()));
+//! assert_eq!(range.max_satisfying(["4.17.0", "4.18.2", "5.0.0"]), Some("4.18.2"));
+//! ```
+
+mod range;
+mod re;
+mod version;
+
+pub use range::Range;
+pub use version::{Identifier, Version};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("invalid version `{0}`")]
+    InvalidVersion(String),
+
+    #[error("invalid version range `{0}`")]
+    InvalidRange(String),
+
+    #[error("invalid comparator `{0}`")]
+    InvalidComparator(String),
+}
+
+/// Options that change how versions and ranges are parsed, mirroring node-semver's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Be more forgiving about not-quite-valid semver strings, such as `=1.2.3` or `1.2.3beta`.
+    pub loose: bool,
+
+    /// Allow prerelease versions to satisfy ranges, even if the range doesn't
+    /// mention a prerelease on the same `major.minor.patch`.
+    pub include_prerelease: bool,
+}
+
+/// Returns whether `version` satisfies `range`, like `semver.satisfies(version, range, options)`.
+///
+/// Invalid versions and ranges never satisfy anything.
+pub fn satisfies(version: &str, range: &str, options: Options) -> bool {
+    let Ok(range) = Range::parse_with(range, options) else {
+        return false;
+    };
+    Version::parse_with(version, options).is_ok_and(|version| range.satisfies(&version))
+}
+
+#[cfg(test)]
+mod fixtures;
@@ -0,0 +1,601 @@
+use std::fmt;
+use std::str::FromStr;
+
+use regex::{Captures, Regex};
+
+use crate::version::MAX_SAFE_INTEGER;
+use crate::{re, Error, Options, Version};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Operator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+        }
+    }
+}
+
+/// A single `<operator><version>` constraint, such as `>=1.2.3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    operator: Operator,
+
+    /// `None` means the comparator matches any version.
+    version: Option<Version>,
+}
+
+impl Comparator {
+    fn parse(comp: &str, options: Options) -> Result<Self, Error> {
+        let comp = comp.split_whitespace().collect::<Vec<_>>().join(" ");
+        let regex = if options.loose {
+            &*re::COMPARATOR_LOOSE
+        } else {
+            &*re::COMPARATOR
+        };
+        let captures = regex
+            .captures(&comp)
+            .ok_or_else(|| Error::InvalidComparator(comp.clone()))?;
+
+        let operator = match captures.get(1).map_or("", |m| m.as_str()) {
+            ">" => Operator::Gt,
+            ">=" => Operator::Gte,
+            "<" => Operator::Lt,
+            "<=" => Operator::Lte,
+            _ => Operator::Eq,
+        };
+        let version = match captures.get(2) {
+            Some(version) if !version.as_str().is_empty() => Some(Version::parse_with(
+                version.as_str(),
+                Options {
+                    loose: options.loose,
+                    include_prerelease: false,
+                },
+            )?),
+            _ => None,
+        };
+
+        Ok(Self { operator, version })
+    }
+
+    fn is_any(&self) -> bool {
+        self.version.is_none()
+    }
+
+    /// `<0.0.0-0` can never be satisfied.
+    fn is_null_set(&self) -> bool {
+        self.to_string() == "<0.0.0-0"
+    }
+
+    fn test(&self, version: &Version) -> bool {
+        let Some(own) = &self.version else {
+            return true;
+        };
+        match self.operator {
+            Operator::Eq => version == own,
+            Operator::Gt => version > own,
+            Operator::Gte => version >= own,
+            Operator::Lt => version < own,
+            Operator::Lte => version <= own,
+        }
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}{version}", self.operator.as_str()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An npm version range, such as `^1.2.3`, `>=1 <2 || 3.x` or `1.2.3 - 2.0.0`.
+///
+/// Ranges are parsed with the same (sometimes surprising) rules as node-semver,
+/// so they match exactly the same versions that npm would.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    /// A list of `||`-separated comparator sets. A version satisfies the range if
+    /// it satisfies every comparator in any one of the sets.
+    set: Vec<Vec<Comparator>>,
+    options: Options,
+}
+
+impl Range {
+    /// Parses a range strictly, like `new semver.Range(range)`.
+    ///
+    /// # Errors
+    /// This function fails if `range` isn't a valid range.
+    pub fn parse(range: &str) -> Result<Self, Error> {
+        Self::parse_with(range, Options::default())
+    }
+
+    /// Parses a range, like `new semver.Range(range, options)`.
+    ///
+    /// # Errors
+    /// This function fails if `range` isn't a valid range.
+    pub fn parse_with(range: &str, options: Options) -> Result<Self, Error> {
+        let raw = range.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let mut set = Vec::new();
+        for part in raw.split("||") {
+            let comparators = parse_comparator_set(part.trim(), options)?;
+            // An empty set means the part was invalid, which is allowed in loose
+            // mode as long as the range as a whole isn't.
+            if !comparators.is_empty() {
+                set.push(comparators);
+            }
+        }
+        if set.is_empty() {
+            return Err(Error::InvalidRange(raw));
+        }
+
+        if set.len() > 1 {
+            // Throw out unsatisfiable sets, but keep the first if they're all unsatisfiable.
+            let first = set[0].clone();
+            set.retain(|comparators| !comparators[0].is_null_set());
+            if set.is_empty() {
+                set = vec![first];
+            } else if let Some(any) = set
+                .iter()
+                .find(|comparators| comparators.len() == 1 && comparators[0].is_any())
+            {
+                // If any set matches everything, so does the range.
+                set = vec![any.clone()];
+            }
+        }
+
+        Ok(Self { set, options })
+    }
+
+    /// Returns whether `version` satisfies this range.
+    ///
+    /// Prerelease versions only satisfy a range if a comparator in the same set
+    /// has a prerelease on the same `major.minor.patch` tuple, unless the range
+    /// was parsed with `include_prerelease`.
+    pub fn satisfies(&self, version: &Version) -> bool {
+        self.set
+            .iter()
+            .any(|comparators| test_set(comparators, version, self.options))
+    }
+
+    /// Returns the highest version in `versions` that satisfies this range, like
+    /// `semver.maxSatisfying(versions, range)`.
+    ///
+    /// Versions that fail to parse are skipped.
+    pub fn max_satisfying<'v, I>(&self, versions: I) -> Option<&'v str>
+    where
+        I: IntoIterator<Item = &'v str>,
+    {
+        versions
+            .into_iter()
+            .filter_map(|raw| Some((Version::parse_with(raw, self.options).ok()?, raw)))
+            .filter(|(version, _)| self.satisfies(version))
+            // `max_by` keeps the last maximum, but node-semver keeps the first.
+            .fold(
+                None,
+                |max: Option<(Version, &str)>, (version, raw)| match max {
+                    Some(max) if max.0 >= version => Some(max),
+                    _ => Some((version, raw)),
+                },
+            )
+            .map(|(_, raw)| raw)
+    }
+}
+
+impl fmt::Display for Range {
+    /// Formats the range in its desugared form, e.g. `^1.2.3` becomes `>=1.2.3 <2.0.0-0`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sets: Vec<String> = self
+            .set
+            .iter()
+            .map(|comparators| {
+                let comparators: Vec<String> = comparators.iter().map(|c| c.to_string()).collect();
+                comparators.join(" ").trim().to_owned()
+            })
+            .collect();
+        f.write_str(sets.join("||").trim())
+    }
+}
+
+impl FromStr for Range {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn test_set(comparators: &[Comparator], version: &Version, options: Options) -> bool {
+    if !comparators
+        .iter()
+        .all(|comparator| comparator.test(version))
+    {
+        return false;
+    }
+
+    if version.is_prerelease() && !options.include_prerelease {
+        // `^1.2.3-pr.1` desugars to `>=1.2.3-pr.1 <2.0.0-0`, which should allow
+        // `1.2.3-pr.2`, but *not* `1.2.4-alpha.notready`, even though it's
+        // within the bounds of the comparators.
+        return comparators.iter().any(|comparator| {
+            comparator.version.as_ref().is_some_and(|allowed| {
+                allowed.is_prerelease()
+                    && allowed.major == version.major
+                    && allowed.minor == version.minor
+                    && allowed.patch == version.patch
+            })
+        });
+    }
+
+    true
+}
+
+/// Parses one `||`-separated part of a range into a list of comparators.
+fn parse_comparator_set(range: &str, options: Options) -> Result<Vec<Comparator>, Error> {
+    let hyphen_range = if options.loose {
+        &*re::HYPHEN_RANGE_LOOSE
+    } else {
+        &*re::HYPHEN_RANGE
+    };
+    // `1.2.3 - 1.2.4` => `>=1.2.3 <=1.2.4`
+    let range = try_replace(hyphen_range, range, |captures| {
+        hyphen_replace(captures, options.include_prerelease)
+    })?;
+    // `> 1.2.3 < 1.2.5` => `>1.2.3 <1.2.5`
+    let range = re::COMPARATOR_TRIM.replace_all(&range, "$1$2$3");
+    // `~ 1.2.3` => `~1.2.3`
+    let range = re::TILDE_TRIM.replace_all(&range, "${1}~");
+    // `^ 1.2.3` => `^1.2.3`
+    let range = re::CARET_TRIM.replace_all(&range, "${1}^");
+
+    let desugared = range
+        .split(' ')
+        .map(|comp| desugar_comparator(comp, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let desugared = desugared.join(" ");
+    let mut comps: Vec<String> = re::WHITESPACE
+        .split(&desugared)
+        .map(|comp| replace_gte0(comp, options))
+        .collect();
+
+    if options.loose {
+        // In loose mode, invalid comparators are thrown out rather than rejected.
+        comps.retain(|comp| re::COMPARATOR_LOOSE.is_match(comp));
+    }
+
+    let parsed = comps
+        .iter()
+        .map(|comp| Comparator::parse(comp, options))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut comparators: Vec<Comparator> = Vec::with_capacity(parsed.len());
+    for comparator in parsed {
+        if comparator.is_null_set() {
+            return Ok(vec![comparator]);
+        }
+        if !comparators.contains(&comparator) {
+            comparators.push(comparator);
+        }
+    }
+    if comparators.len() > 1 {
+        comparators.retain(|comparator| !comparator.is_any());
+    }
+    Ok(comparators)
+}
+
+/// Turns carets, tildes, x-ranges and stars into plain comparators.
+fn desugar_comparator(comp: &str, options: Options) -> Result<String, Error> {
+    let comp = replace_each(comp, |comp| replace_caret(comp, options))?;
+    let comp = replace_each(&comp, |comp| replace_tilde(comp, options))?;
+    let comp = replace_each(&comp, |comp| replace_xrange(comp.trim(), options))?;
+    // Looseness is ignored here, as a star is always as loose as it gets.
+    Ok(re::STAR.replace(comp.trim(), "").into_owned())
+}
+
+fn replace_each(
+    comp: &str,
+    replace: impl Fn(&str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let replaced = re::WHITESPACE
+        .split(comp.trim())
+        .map(replace)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(replaced.join(" "))
+}
+
+/// Like [`Regex::replace`], but `replace` can fail (with `None`) if the match turns out
+/// to be invalid, like a version component that can't be incremented.
+fn try_replace(
+    regex: &Regex,
+    haystack: &str,
+    replace: impl FnOnce(&Captures) -> Option<String>,
+) -> Result<String, Error> {
+    let Some(captures) = regex.captures(haystack) else {
+        return Ok(haystack.to_owned());
+    };
+    let matched = captures.get(0).map_or(0..0, |m| m.range());
+    let replacement =
+        replace(&captures).ok_or_else(|| Error::InvalidComparator(haystack.to_owned()))?;
+    Ok(format!(
+        "{}{replacement}{}",
+        &haystack[..matched.start],
+        &haystack[matched.end..]
+    ))
+}
+
+fn replace_gte0(comp: &str, options: Options) -> String {
+    let gte0 = if options.include_prerelease {
+        &*re::GTE0_PRE
+    } else {
+        &*re::GTE0
+    };
+    gte0.replace(comp.trim(), "").into_owned()
+}
+
+/// Returns the capture group `i`, or an empty string if it didn't participate in the match.
+fn group<'h>(captures: &Captures<'h>, i: usize) -> &'h str {
+    captures.get(i).map_or("", |m| m.as_str())
+}
+
+fn is_x(id: &str) -> bool {
+    id.is_empty() || id.eq_ignore_ascii_case("x") || id == "*"
+}
+
+/// Adds one to a numeric identifier, like JavaScript's `+id + 1`. Like node-semver,
+/// nothing above `Number.MAX_SAFE_INTEGER` is allowed.
+fn inc(id: &str) -> Option<String> {
+    let num = id
+        .parse::<u64>()
+        .ok()
+        .filter(|num| *num <= MAX_SAFE_INTEGER)?;
+    num.checked_add(1).map(|num| num.to_string())
+}
+
+// ~, ~> --> * (any, kinda silly)
+// ~2, ~2.x, ~2.x.x, ~>2, ~>2.x ~>2.x.x --> >=2.0.0 <3.0.0-0
+// ~2.0, ~2.0.x, ~>2.0, ~>2.0.x --> >=2.0.0 <2.1.0-0
+// ~1.2, ~1.2.x, ~>1.2, ~>1.2.x --> >=1.2.0 <1.3.0-0
+// ~1.2.3, ~>1.2.3 --> >=1.2.3 <1.3.0-0
+// ~1.2.0, ~>1.2.0 --> >=1.2.0 <1.3.0-0
+// ~0.0.1 --> >=0.0.1 <0.1.0-0
+fn replace_tilde(comp: &str, options: Options) -> Result<String, Error> {
+    let regex = if options.loose {
+        &*re::TILDE_LOOSE
+    } else {
+        &*re::TILDE
+    };
+    try_replace(regex, comp, |captures| {
+        let (major, minor, patch, pre) = (
+            group(captures, 1),
+            group(captures, 2),
+            group(captures, 3),
+            group(captures, 4),
+        );
+        Some(if is_x(major) {
+            String::new()
+        } else if is_x(minor) {
+            format!(">={major}.0.0 <{}.0.0-0", inc(major)?)
+        } else if is_x(patch) {
+            format!(">={major}.{minor}.0 <{major}.{}.0-0", inc(minor)?)
+        } else if !pre.is_empty() {
+            format!(
+                ">={major}.{minor}.{patch}-{pre} <{major}.{}.0-0",
+                inc(minor)?
+            )
+        } else {
+            format!(">={major}.{minor}.{patch} <{major}.{}.0-0", inc(minor)?)
+        })
+    })
+}
+
+// ^ --> * (any, kinda silly)
+// ^2, ^2.x, ^2.x.x --> >=2.0.0 <3.0.0-0
+// ^2.0, ^2.0.x --> >=2.0.0 <3.0.0-0
+// ^1.2, ^1.2.x --> >=1.2.0 <2.0.0-0
+// ^1.2.3 --> >=1.2.3 <2.0.0-0
+// ^1.2.0 --> >=1.2.0 <2.0.0-0
+// ^0.0.1 --> >=0.0.1 <0.0.2-0
+// ^0.1.0 --> >=0.1.0 <0.2.0-0
+fn replace_caret(comp: &str, options: Options) -> Result<String, Error> {
+    let regex = if options.loose {
+        &*re::CARET_LOOSE
+    } else {
+        &*re::CARET
+    };
+    let z = if options.include_prerelease { "-0" } else { "" };
+    try_replace(regex, comp, |captures| {
+        let (major, minor, patch, pre) = (
+            group(captures, 1),
+            group(captures, 2),
+            group(captures, 3),
+            group(captures, 4),
+        );
+        Some(if is_x(major) {
+            String::new()
+        } else if is_x(minor) {
+            format!(">={major}.0.0{z} <{}.0.0-0", inc(major)?)
+        } else if is_x(patch) {
+            if major == "0" {
+                format!(">={major}.{minor}.0{z} <{major}.{}.0-0", inc(minor)?)
+            } else {
+                format!(">={major}.{minor}.0{z} <{}.0.0-0", inc(major)?)
+            }
+        } else if !pre.is_empty() {
+            let lower = format!(">={major}.{minor}.{patch}-{pre}");
+            if major == "0" && minor == "0" {
+                format!("{lower} <{major}.{minor}.{}-0", inc(patch)?)
+            } else if major == "0" {
+                format!("{lower} <{major}.{}.0-0", inc(minor)?)
+            } else {
+                format!("{lower} <{}.0.0-0", inc(major)?)
+            }
+        } else if major == "0" && minor == "0" {
+            format!(
+                ">={major}.{minor}.{patch}{z} <{major}.{minor}.{}-0",
+                inc(patch)?
+            )
+        } else if major == "0" {
+            format!(">={major}.{minor}.{patch}{z} <{major}.{}.0-0", inc(minor)?)
+        } else {
+            format!(">={major}.{minor}.{patch} <{}.0.0-0", inc(major)?)
+        })
+    })
+}
+
+fn replace_xrange(comp: &str, options: Options) -> Result<String, Error> {
+    let regex = if options.loose {
+        &*re::XRANGE_LOOSE
+    } else {
+        &*re::XRANGE
+    };
+    try_replace(regex, comp, |captures| {
+        let mut gtlt = group(captures, 1);
+        let mut major = group(captures, 2).to_owned();
+        let mut minor = group(captures, 3).to_owned();
+        let mut patch = group(captures, 4).to_owned();
+
+        let x_major = is_x(&major);
+        let x_minor = x_major || is_x(&minor);
+        let any_x = x_minor || is_x(&patch);
+
+        if gtlt == "=" && any_x {
+            gtlt = "";
+        }
+
+        // If we're including prereleases in the match, then we need to
+        // fix this to -0, the lowest possible prerelease value.
+        let mut pre = if options.include_prerelease { "-0" } else { "" };
+
+        Some(if x_major {
+            if gtlt == ">" || gtlt == "<" {
+                // Nothing is allowed.
+                "<0.0.0-0".into()
+            } else {
+                // Nothing is forbidden.
+                "*".into()
+            }
+        } else if !gtlt.is_empty() && any_x {
+            // We know the patch is an x, because we have any x at all.
+            if x_minor {
+                minor = "0".into();
+            }
+            patch = "0".into();
+
+            if gtlt == ">" {
+                // >1 => >=2.0.0
+                // >1.2 => >=1.3.0
+                gtlt = ">=";
+                if x_minor {
+                    major = inc(&major)?;
+                    minor = "0".into();
+                } else {
+                    minor = inc(&minor)?;
+                }
+            } else if gtlt == "<=" {
+                // <=0.7.x is actually <0.8.0, since any 0.7.x should pass.
+                // Similarly, <=7.x is actually <8.0.0, etc.
+                gtlt = "<";
+                if x_minor {
+                    major = inc(&major)?;
+                } else {
+                    minor = inc(&minor)?;
+                }
+            }
+
+            if gtlt == "<" {
+                pre = "-0";
+            }
+
+            format!("{gtlt}{major}.{minor}.{patch}{pre}")
+        } else if x_minor {
+            format!(">={major}.0.0{pre} <{}.0.0-0", inc(&major)?)
+        } else if is_x(&patch) {
+            format!(">={major}.{minor}.0{pre} <{major}.{}.0-0", inc(&minor)?)
+        } else {
+            captures[0].to_owned()
+        })
+    })
+}
+
+// 1.2 - 3.4.5 => >=1.2.0 <=3.4.5
+// 1.2.3 - 3.4 => >=1.2.0 <3.5.0-0 Any 3.4.x will do
+// 1.2 - 3.4 => >=1.2.0 <3.5.0-0
+fn hyphen_replace(captures: &Captures, include_prerelease: bool) -> Option<String> {
+    let z = if include_prerelease { "-0" } else { "" };
+
+    let (from, from_major, from_minor, from_patch, from_pre) = (
+        group(captures, 1),
+        group(captures, 2),
+        group(captures, 3),
+        group(captures, 4),
+        group(captures, 5),
+    );
+    let from = if is_x(from_major) {
+        String::new()
+    } else if is_x(from_minor) {
+        format!(">={from_major}.0.0{z}")
+    } else if is_x(from_patch) {
+        format!(">={from_major}.{from_minor}.0{z}")
+    } else if !from_pre.is_empty() {
+        format!(">={from}")
+    } else {
+        format!(">={from}{z}")
+    };
+
+    let (to, to_major, to_minor, to_patch, to_pre) = (
+        group(captures, 7),
+        group(captures, 8),
+        group(captures, 9),
+        group(captures, 10),
+        group(captures, 11),
+    );
+    let to = if is_x(to_major) {
+        String::new()
+    } else if is_x(to_minor) {
+        format!("<{}.0.0-0", inc(to_major)?)
+    } else if is_x(to_patch) {
+        format!("<{to_major}.{}.0-0", inc(to_minor)?)
+    } else if !to_pre.is_empty() {
+        format!("<={to_major}.{to_minor}.{to_patch}-{to_pre}")
+    } else if include_prerelease {
+        format!("<{to_major}.{to_minor}.{}-0", inc(to_patch)?)
+    } else {
+        format!("<={to}")
+    };
+
+    Some(format!("{from} {to}").trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_components_that_cant_be_incremented() {
+        for range in [
+            "^18446744073709551615.0.0",
+            "~1.18446744073709551615.0",
+            "<=18446744073709551615.x",
+            "1.0.0 - 18446744073709551615",
+            // Anything above `Number.MAX_SAFE_INTEGER` is invalid, like in node-semver.
+            "^9007199254740992.0.0",
+            "^9007199254740991.0.0",
+        ] {
+            assert!(Range::parse(range).is_err(), "{range}");
+        }
+        assert!(Range::parse("^9007199254740990.0.0").is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+//! The regular expressions used to tokenize versions and ranges.
+//!
+//! These are built up exactly like node-semver's `internal/re.js`, so that the two
+//! accept the same inputs. `\d` is spelled `[0-9]` because Rust's `\d` matches any
+//! Unicode digit, while JavaScript's only matches ASCII ones.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const NUMERIC_IDENTIFIER: &str = "0|[1-9][0-9]*";
+const NUMERIC_IDENTIFIER_LOOSE: &str = "[0-9]+";
+const NON_NUMERIC_IDENTIFIER: &str = "[0-9]*[a-zA-Z-][a-zA-Z0-9-]*";
+const BUILD_IDENTIFIER: &str = "[a-zA-Z0-9-]+";
+const GTLT: &str = "((?:<|>)?=?)";
+const LONE_TILDE: &str = "(?:~>?)";
+const LONE_CARET: &str = "(?:\\^)";
+
+fn main_version(identifier: &str) -> String {
+    format!("({identifier})\\.({identifier})\\.({identifier})")
+}
+
+fn prerelease(identifier: &str, loose: bool) -> String {
+    let hyphen = if loose { "-?" } else { "-" };
+    format!("(?:{hyphen}({identifier}(?:\\.{identifier})*))")
+}
+
+fn prerelease_identifier(loose: bool) -> String {
+    let numeric = if loose {
+        NUMERIC_IDENTIFIER_LOOSE
+    } else {
+        NUMERIC_IDENTIFIER
+    };
+    format!("(?:{numeric}|{NON_NUMERIC_IDENTIFIER})")
+}
+
+fn build() -> String {
+    format!("(?:\\+({BUILD_IDENTIFIER}(?:\\.{BUILD_IDENTIFIER})*))")
+}
+
+fn full_plain() -> String {
+    format!(
+        "v?{}{}?{}?",
+        main_version(NUMERIC_IDENTIFIER),
+        prerelease(&prerelease_identifier(false), false),
+        build()
+    )
+}
+
+fn loose_plain() -> String {
+    format!(
+        "[v=\\s]*{}{}?{}?",
+        main_version(NUMERIC_IDENTIFIER_LOOSE),
+        prerelease(&prerelease_identifier(true), true),
+        build()
+    )
+}
+
+fn xrange_plain(loose: bool) -> String {
+    let identifier = if loose {
+        format!("{NUMERIC_IDENTIFIER_LOOSE}|x|X|\\*")
+    } else {
+        format!("{NUMERIC_IDENTIFIER}|x|X|\\*")
+    };
+    format!(
+        "[v=\\s]*({identifier})(?:\\.({identifier})(?:\\.({identifier})(?:{})?{}?)?)?",
+        prerelease(&prerelease_identifier(loose), loose),
+        build()
+    )
+}
+
+fn regex(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("semver regexes should be valid")
+}
+
+lazy_static! {
+    pub static ref FULL: Regex = regex(&format!("^{}$", full_plain()));
+    pub static ref LOOSE: Regex = regex(&format!("^{}$", loose_plain()));
+
+    pub static ref XRANGE: Regex = regex(&format!("^{GTLT}\\s*{}$", xrange_plain(false)));
+    pub static ref XRANGE_LOOSE: Regex = regex(&format!("^{GTLT}\\s*{}$", xrange_plain(true)));
+
+    pub static ref TILDE_TRIM: Regex = regex(&format!("(\\s*){LONE_TILDE}\\s+"));
+    pub static ref TILDE: Regex = regex(&format!("^{LONE_TILDE}{}$", xrange_plain(false)));
+    pub static ref TILDE_LOOSE: Regex = regex(&format!("^{LONE_TILDE}{}$", xrange_plain(true)));
+
+    pub static ref CARET_TRIM: Regex = regex(&format!("(\\s*){LONE_CARET}\\s+"));
+    pub static ref CARET: Regex = regex(&format!("^{LONE_CARET}{}$", xrange_plain(false)));
+    pub static ref CARET_LOOSE: Regex = regex(&format!("^{LONE_CARET}{}$", xrange_plain(true)));
+
+    pub static ref COMPARATOR: Regex = regex(&format!("^{GTLT}\\s*({})$|^$", full_plain()));
+    pub static ref COMPARATOR_LOOSE: Regex = regex(&format!("^{GTLT}\\s*({})$|^$", loose_plain()));
+
+    /// Strips the whitespace between an operator and its version, so `> 1.2.3` becomes `>1.2.3`.
+    pub static ref COMPARATOR_TRIM: Regex = regex(&format!(
+        "(\\s*){GTLT}\\s*({}|{})",
+        loose_plain(),
+        xrange_plain(false)
+    ));
+
+    pub static ref HYPHEN_RANGE: Regex = regex(&format!(
+        "^\\s*({})\\s+-\\s+({})\\s*$",
+        xrange_plain(false),
+        xrange_plain(false)
+    ));
+    pub static ref HYPHEN_RANGE_LOOSE: Regex = regex(&format!(
+        "^\\s*({})\\s+-\\s+({})\\s*$",
+        xrange_plain(true),
+        xrange_plain(true)
+    ));
+
+    pub static ref STAR: Regex = regex("(<|>)?=?\\s*\\*");
+    pub static ref GTE0: Regex = regex("^\\s*>=\\s*0\\.0\\.0\\s*$");
+    pub static ref GTE0_PRE: Regex = regex("^\\s*>=\\s*0\\.0\\.0-0\\s*$");
+
+    pub static ref WHITESPACE: Regex = regex("\\s+");
+}
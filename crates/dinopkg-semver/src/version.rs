@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::{re, Error, Options};
+
+/// The longest version string node-semver will accept.
+pub(crate) const MAX_LENGTH: usize = 256;
+
+/// JavaScript's `Number.MAX_SAFE_INTEGER`. Version components above this are rejected.
+pub(crate) const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// A single dot-separated part of a prerelease tag, e.g. `beta` or `1` in `1.0.0-beta.1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Identifier {
+    Numeric(u64),
+    AlphaNumeric(String),
+}
+
+fn is_digits(identifier: &str) -> bool {
+    !identifier.is_empty() && identifier.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl Identifier {
+    fn parse(identifier: &str) -> Self {
+        if is_digits(identifier) {
+            if let Ok(num) = identifier.parse::<u64>() {
+                if num < MAX_SAFE_INTEGER {
+                    return Self::Numeric(num);
+                }
+            }
+        }
+        Self::AlphaNumeric(identifier.into())
+    }
+}
+
+impl Ord for Identifier {
+    /// Numeric identifiers always have lower precedence than alphanumeric ones.
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Numeric(a), Self::Numeric(b)) => a.cmp(b),
+            // Digit-only identifiers too big to be `Numeric` still compare as numbers.
+            (Self::Numeric(_), Self::AlphaNumeric(b)) if is_digits(b) => Ordering::Less,
+            (Self::AlphaNumeric(a), Self::Numeric(_)) if is_digits(a) => Ordering::Greater,
+            (Self::AlphaNumeric(a), Self::AlphaNumeric(b)) if is_digits(a) && is_digits(b) => {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (Self::Numeric(_), Self::AlphaNumeric(_)) => Ordering::Less,
+            (Self::AlphaNumeric(_), Self::Numeric(_)) => Ordering::Greater,
+            (Self::AlphaNumeric(a), Self::AlphaNumeric(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Identifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Numeric(num) => write!(f, "{num}"),
+            Self::AlphaNumeric(id) => f.write_str(id),
+        }
+    }
+}
+
+/// A parsed semver version, such as `1.2.3-beta.1+build.5`.
+///
+/// Build metadata is kept around, but (as the spec requires) it's ignored
+/// when comparing versions, so `1.0.0+a == 1.0.0+b`.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub prerelease: Vec<Identifier>,
+    pub build: Vec<String>,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            prerelease: Vec::new(),
+            build: Vec::new(),
+        }
+    }
+
+    /// Parses a version strictly, like `semver.parse(version)`.
+    ///
+    /// # Errors
+    /// This function fails if `version` isn't a valid semver version.
+    pub fn parse(version: &str) -> Result<Self, Error> {
+        Self::parse_with(version, Options::default())
+    }
+
+    /// Parses a version, like `semver.parse(version, options)`.
+    ///
+    /// In loose mode, things like `=1.2.3`, `v 1.2.3` and `1.2.3beta` are also accepted.
+    ///
+    /// # Errors
+    /// This function fails if `version` isn't a valid semver version.
+    pub fn parse_with(version: &str, options: Options) -> Result<Self, Error> {
+        let invalid = || Error::InvalidVersion(version.into());
+        if version.len() > MAX_LENGTH {
+            return Err(invalid());
+        }
+
+        let regex = if options.loose {
+            &*re::LOOSE
+        } else {
+            &*re::FULL
+        };
+        let captures = regex.captures(version.trim()).ok_or_else(invalid)?;
+        let component = |i: usize| -> Result<u64, Error> {
+            captures[i]
+                .parse::<u64>()
+                .ok()
+                .filter(|num| *num <= MAX_SAFE_INTEGER)
+                .ok_or_else(invalid)
+        };
+
+        Ok(Self {
+            major: component(1)?,
+            minor: component(2)?,
+            patch: component(3)?,
+            prerelease: captures
+                .get(4)
+                .map(|pre| pre.as_str().split('.').map(Identifier::parse).collect())
+                .unwrap_or_default(),
+            build: captures
+                .get(5)
+                .map(|build| build.as_str().split('.').map(Into::into).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.prerelease.is_empty()
+    }
+
+    fn cmp_prerelease(&self, other: &Self) -> Ordering {
+        // Not having a prerelease is *greater* than having one.
+        match (self.is_prerelease(), other.is_prerelease()) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => Ordering::Equal,
+            (true, true) => self.prerelease.cmp(&other.prerelease),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.major
+            .cmp(&other.major)
+            .then(self.minor.cmp(&other.minor))
+            .then(self.patch.cmp(&other.patch))
+            .then_with(|| self.cmp_prerelease(other))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Must agree with `Eq`, so the build metadata is left out.
+        self.major.hash(state);
+        self.minor.hash(state);
+        self.patch.hash(state);
+        self.prerelease.hash(state);
+    }
+}
+
+impl fmt::Display for Version {
+    /// Formats the version the same way node-semver does, which leaves out build metadata.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        for (i, identifier) in self.prerelease.iter().enumerate() {
+            let separator = if i == 0 { '-' } else { '.' };
+            write!(f, "{separator}{identifier}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_all_parts() {
+        let version = Version::parse("1.2.3-beta.4+build.5").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+        assert_eq!(
+            version.prerelease,
+            [
+                Identifier::AlphaNumeric("beta".into()),
+                Identifier::Numeric(4)
+            ]
+        );
+        assert_eq!(version.build, ["build", "5"]);
+        assert_eq!(version.to_string(), "1.2.3-beta.4");
+    }
+
+    #[test]
+    fn loose_mode() {
+        let loose = Options {
+            loose: true,
+            ..Default::default()
+        };
+        assert!(Version::parse("=1.2.3").is_err());
+        assert_eq!(
+            Version::parse_with("=1.2.3", loose).unwrap().to_string(),
+            "1.2.3"
+        );
+        assert_eq!(
+            Version::parse_with("1.2.3beta", loose).unwrap().to_string(),
+            "1.2.3-beta"
+        );
+    }
+
+    #[test]
+    fn ignores_build_metadata_in_comparisons() {
+        assert_eq!(
+            Version::parse("1.0.0+a").unwrap(),
+            Version::parse("1.0.0+b").unwrap()
+        );
+    }
+}
@@ -57,3 +57,10 @@ const BANNED_NAMES: [&str; 47] = [
 pub fn is_banned(name: &String) -> bool {
     BANNED_NAMES.contains(&name.to_lowercase().as_str())
 }
+
+/// Names that can never be used, even by packages published before today's rules.
+const BLACKLISTED: [&str; 2] = ["node_modules", "favicon.ico"];
+
+pub fn is_blacklisted(name: &str) -> bool {
+    BLACKLISTED.contains(&name.to_lowercase().as_str())
+}
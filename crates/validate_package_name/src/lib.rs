@@ -3,7 +3,7 @@ use regex::Regex;
 use urlencoding::encode;
 
 mod banned_names;
-use banned_names::is_banned;
+use banned_names::{is_banned, is_blacklisted};
 
 lazy_static! {
     static ref SCOPED_PACKAGE_REGEX: Regex = Regex::new("^(?:@([^/]+?)[/])?([^/]+?)$").unwrap();
@@ -48,7 +48,29 @@ pub enum Error {
 ///
 /// # Errors
 /// This function can fail if the package name is invalid.
-pub fn validate(name: &String) -> Result<(), Error> {
+pub fn validate(name: &str) -> Result<(), Error> {
+    check(name, true)
+}
+
+/// Validates the name of a package that might already be on the registry.
+///
+/// This is looser than [`validate`], like npm's `validForOldPackages`: names from
+/// before today's rules, with capital letters, more than 214 characters or the name of
+/// a core module, are still allowed.
+///
+/// ```rust
+/// use validate_package_name::validate_existing;
+///
+/// assert!(validate_existing("JSONStream").is_ok())
+/// ```
+///
+/// # Errors
+/// This function can fail if the package name is invalid.
+pub fn validate_existing(name: &str) -> Result<(), Error> {
+    check(name, false)
+}
+
+fn check(name: &str, new: bool) -> Result<(), Error> {
     if name.is_empty() {
         return Err(Error::NameEmpty);
     }
@@ -61,19 +83,19 @@ pub fn validate(name: &String) -> Result<(), Error> {
         return Err(Error::LeadingOrTrailingSpaces);
     }
 
-    if is_banned(name) {
+    if is_blacklisted(name) || (new && is_banned(&name.to_owned())) {
         return Err(Error::NameNotAllowed);
     }
 
-    if name.len() > MAX_LEN {
+    if new && name.len() > MAX_LEN {
         return Err(Error::NameTooLong);
     }
 
-    if &name.to_lowercase() != name {
+    if new && name.to_lowercase() != name {
         return Err(Error::CapsNotAllowed);
     }
 
-    if &encode(name).into_owned() != name {
+    if encode(name) != name {
         let name_match = SCOPED_PACKAGE_REGEX.captures(name);
 
         if let Some(matches) = name_match {
@@ -90,6 +112,11 @@ pub fn validate(name: &String) -> Result<(), Error> {
             let user = &matches[1];
             let pkg = &matches[2];
 
+            // Names are used as paths inside `node_modules`, so they can't point out of it.
+            if pkg == "." || pkg == ".." {
+                return Err(Error::ScopedPackageNameInvalid);
+            }
+
             if encode(user) == user && encode(pkg) == pkg {
                 return Ok(());
             }
@@ -103,7 +130,7 @@ pub fn validate(name: &String) -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::validate;
+    use crate::{validate, validate_existing};
 
     fn assert_err(name: &str) {
         let is_valid = validate(&String::from(name));
@@ -140,6 +167,22 @@ mod tests {
         assert_ok("@custard/hi")
     }
 
+    #[test]
+    fn reject_paths() {
+        for name in [
+            "@custard/..",
+            "@custard/.",
+            "../pwned",
+            "a/../b",
+            "/abs",
+            "a\\b",
+            "@a/b/c",
+        ] {
+            assert_err(name);
+            assert!(validate_existing(name).is_err());
+        }
+    }
+
     #[test]
     fn reject_zero_len() {
         assert_err("")
@@ -206,4 +249,14 @@ mod tests {
     fn reject_empty() {
         assert_err("")
     }
+
+    #[test]
+    fn accept_old_packages() {
+        assert!(validate_existing("JSONStream").is_ok());
+        assert!(validate_existing("http").is_ok());
+        assert!(validate_existing("@scope/Old").is_ok());
+        assert!(validate_existing("node_modules").is_err());
+        assert!(validate_existing("s/l/a/s/h/e/s").is_err());
+        assert!(validate_existing("_private").is_err());
+    }
 }